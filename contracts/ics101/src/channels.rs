@@ -0,0 +1,271 @@
+use cosmwasm_std::{
+    Deps, DepsMut, IbcPacket, MessageInfo, Order, Response, StdError, StdResult, Storage,
+};
+use cw_storage_plus::Bound;
+
+use crate::{
+    contract::{ensure_admin, resume_deferred_orders},
+    error::ContractError,
+    market::PoolStatus,
+    msg::{ChainChannelListResponse, ChainChannelResponse, FrozenPoolListResponse},
+    state::{
+        ChannelInfo, FrozenPool, CHAIN_CHANNELS, CHANNEL_INFO, FROZEN_POOLS, POOLS,
+        POOLS_BY_CHANNEL,
+    },
+};
+
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+/// Serves pools with `chain_id` over an open ics101 channel. A channel can only serve one
+/// chain, pools created earlier keep the channel they were created on.
+pub fn register_channel(
+    deps: DepsMut,
+    info: MessageInfo,
+    chain_id: String,
+    channel_id: String,
+) -> Result<Response, ContractError> {
+    ensure_admin(deps.storage, &info.sender)?;
+
+    let channel = CHANNEL_INFO
+        .may_load(deps.storage, &channel_id)?
+        .ok_or_else(|| StdError::generic_err(format!("Channel {} is not open", channel_id)))?;
+    for item in CHAIN_CHANNELS.range(deps.storage, None, None, Order::Ascending) {
+        let (registered_chain, registered) = item?;
+        if registered.id == channel_id && registered_chain != chain_id {
+            return Err(ContractError::Std(StdError::generic_err(format!(
+                "Channel {} already serves chain {}",
+                channel_id, registered_chain
+            ))));
+        }
+    }
+    CHAIN_CHANNELS.save(deps.storage, &chain_id, &channel)?;
+
+    Ok(Response::default()
+        .add_attribute("action", "register_channel")
+        .add_attribute("chain_id", chain_id)
+        .add_attribute("channel_id", channel_id))
+}
+
+/// Returns the channel registered for a counterparty chain.
+pub fn resolve_channel(
+    storage: &dyn Storage,
+    chain_id: &str,
+) -> Result<ChannelInfo, ContractError> {
+    CHAIN_CHANNELS
+        .may_load(storage, chain_id)?
+        .ok_or_else(|| ContractError::UnknownChain {
+            chain_id: chain_id.to_string(),
+        })
+}
+
+/// Checks that a packet creating a pool arrived over the channel registered for its chain.
+pub fn check_chain_channel(
+    storage: &dyn Storage,
+    chain_id: &str,
+    packet: &IbcPacket,
+) -> Result<(), ContractError> {
+    let channel = resolve_channel(storage, chain_id)?;
+    ensure_channel(&channel.id, &packet.dest.channel_id)
+}
+
+/// Checks that a packet for an existing pool arrived over the channel the pool is bound to.
+/// Packets for unknown pools are left to their handler to reject.
+pub fn check_pool_channel(
+    storage: &dyn Storage,
+    pool_id: &str,
+    packet: &IbcPacket,
+) -> Result<(), ContractError> {
+    match POOLS.may_load(storage, pool_id)? {
+        Some(pool) => ensure_channel(&pool.counter_party_channel, &packet.dest.channel_id),
+        None => Ok(()),
+    }
+}
+
+pub fn ensure_channel(expected: &str, actual: &str) -> Result<(), ContractError> {
+    if expected != actual {
+        return Err(ContractError::ChannelMismatch {
+            expected: expected.to_string(),
+            actual: actual.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Forgets a closed channel and freezes every live pool bound to it. Returns the ids of
+/// the frozen pools.
+pub fn close_channel(
+    storage: &mut dyn Storage,
+    channel_id: &str,
+    now: u64,
+) -> StdResult<Vec<String>> {
+    CHANNEL_INFO.remove(storage, channel_id);
+    let chains = CHAIN_CHANNELS
+        .range(storage, None, None, Order::Ascending)
+        .filter(|item| {
+            item.as_ref()
+                .map_or(true, |(_, channel)| channel.id == channel_id)
+        })
+        .map(|item| item.map(|(chain_id, _)| chain_id))
+        .collect::<StdResult<Vec<_>>>()?;
+    for chain_id in chains {
+        CHAIN_CHANNELS.remove(storage, &chain_id);
+    }
+
+    let pool_ids = POOLS_BY_CHANNEL
+        .prefix(channel_id)
+        .keys(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut frozen = vec![];
+    for pool_id in pool_ids {
+        let mut pool = match POOLS.may_load(storage, &pool_id)? {
+            Some(pool) if !matches!(pool.status, PoolStatus::Cancelled | PoolStatus::Frozen) => {
+                pool
+            }
+            _ => continue,
+        };
+        pool.status = PoolStatus::Frozen;
+        POOLS.save(storage, &pool_id, &pool)?;
+        resume_deferred_orders(storage, &pool_id)?;
+        FROZEN_POOLS.save(
+            storage,
+            &pool_id,
+            &FrozenPool {
+                pool_id: pool_id.clone(),
+                channel_id: channel_id.to_string(),
+                reason: format!("channel {} closed", channel_id),
+                frozen_at: now,
+            },
+        )?;
+        frozen.push(pool_id);
+    }
+    Ok(frozen)
+}
+
+pub fn query_chain_channel(deps: Deps, chain_id: String) -> StdResult<ChainChannelResponse> {
+    let channel = CHAIN_CHANNELS.load(deps.storage, &chain_id)?;
+    Ok(ChainChannelResponse { chain_id, channel })
+}
+
+pub fn query_chain_channels(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<ChainChannelListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+    let channels = CHAIN_CHANNELS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(chain_id, channel)| ChainChannelResponse { chain_id, channel }))
+        .collect::<StdResult<_>>()?;
+    Ok(ChainChannelListResponse { channels })
+}
+
+pub fn query_frozen_pools(
+    deps: Deps,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<FrozenPoolListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+    let pools = FROZEN_POOLS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, pool)| pool))
+        .collect::<StdResult<_>>()?;
+    Ok(FrozenPoolListResponse { pools })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Config, CONFIG};
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+    use cosmwasm_std::{Binary, IbcEndpoint, IbcTimeout};
+
+    fn open_channel(storage: &mut dyn Storage, channel_id: &str) {
+        let info = ChannelInfo {
+            id: channel_id.to_string(),
+            counterparty_endpoint: IbcEndpoint {
+                port_id: "wasm.ics101".to_string(),
+                channel_id: format!("remote-{}", channel_id),
+            },
+            connection_id: "connection-0".to_string(),
+        };
+        CHANNEL_INFO.save(storage, channel_id, &info).unwrap();
+    }
+
+    fn packet(channel_id: &str) -> IbcPacket {
+        let endpoint = |channel_id: &str| IbcEndpoint {
+            port_id: "wasm.ics101".to_string(),
+            channel_id: channel_id.to_string(),
+        };
+        IbcPacket::new(
+            Binary::default(),
+            endpoint("remote"),
+            endpoint(channel_id),
+            1,
+            IbcTimeout::with_timestamp(mock_env().block.time),
+        )
+    }
+
+    #[test]
+    fn test_register_channel() {
+        let mut deps = mock_dependencies();
+        CONFIG
+            .save(
+                deps.as_mut().storage,
+                &Config {
+                    counter: 0,
+                    token_code_id: 1,
+                    admin: "admin".to_string(),
+                    router: "".to_string(),
+                    protocol_fee_rate: 0,
+                    fee_collector: "admin".to_string(),
+                    guardian: "guardian".to_string(),
+                    paused: false,
+                    disabled_messages: vec![],
+                },
+            )
+            .unwrap();
+        open_channel(deps.as_mut().storage, "channel-0");
+        open_channel(deps.as_mut().storage, "channel-1");
+
+        let register = |deps: DepsMut, sender: &str, chain_id: &str, channel_id: &str| {
+            register_channel(
+                deps,
+                mock_info(sender, &[]),
+                chain_id.to_string(),
+                channel_id.to_string(),
+            )
+        };
+        register(deps.as_mut(), "bob", "chain-b", "channel-0").unwrap_err();
+        register(deps.as_mut(), "admin", "chain-b", "channel-9").unwrap_err();
+        register(deps.as_mut(), "admin", "chain-b", "channel-0").unwrap();
+        register(deps.as_mut(), "admin", "chain-c", "channel-0").unwrap_err();
+        register(deps.as_mut(), "admin", "chain-c", "channel-1").unwrap();
+
+        let storage = deps.as_ref().storage;
+        assert_eq!(resolve_channel(storage, "chain-c").unwrap().id, "channel-1");
+        assert_eq!(
+            resolve_channel(storage, "chain-d").unwrap_err(),
+            ContractError::UnknownChain {
+                chain_id: "chain-d".to_string()
+            }
+        );
+        check_chain_channel(storage, "chain-b", &packet("channel-0")).unwrap();
+        assert_eq!(
+            check_chain_channel(storage, "chain-b", &packet("channel-1")).unwrap_err(),
+            ContractError::ChannelMismatch {
+                expected: "channel-0".to_string(),
+                actual: "channel-1".to_string()
+            }
+        );
+
+        let channels = query_chain_channels(deps.as_ref(), None, None).unwrap();
+        assert_eq!(channels.channels.len(), 2);
+        assert_eq!(channels.channels[0].chain_id, "chain-b");
+    }
+}
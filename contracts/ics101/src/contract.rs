@@ -4,38 +4,56 @@ use std::vec;
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    from_binary, to_binary, Binary, Coin, Deps, DepsMut, Env, IbcMsg, IbcTimeout, MessageInfo,
-    Order, Reply, ReplyOn, Response, StdError, StdResult, SubMsg, SubMsgResult, Uint128, WasmMsg,
+    attr, from_binary, to_binary, Addr, Attribute, Binary, Coin, CosmosMsg, Decimal, Deps,
+    DepsMut, Empty, Env, IbcTimeout, IbcTimeoutBlock, MessageInfo, Order, Reply, ReplyOn,
+    Response, StdError, StdResult, Storage, SubMsg, SubMsgResult, Timestamp, Uint128, WasmMsg,
 };
-use protobuf::Message;
+use protobuf::{CodedOutputStream, Message, ProtobufResult};
 
 use cw2::set_contract_version;
+use semver::Version;
 use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, MinterResponse};
-use cw_storage_plus::Bound;
+use cw_storage_plus::{Bound, MultiIndex};
 
+use crate::channels;
 use crate::error::ContractError;
 use crate::ibc::{ACK_FAILURE_ID, RECEIVE_ID};
 use crate::interchainswap_handler::ack_fail;
-use crate::market::{InterchainLiquidityPool, InterchainMarketMaker, PoolSide, PoolStatus, LP_TOKEN_PRECISION};
+use crate::market::{
+    AmpRamp, InterchainLiquidityPool, InterchainMarketMaker, PoolCurve, PoolSide, PoolStatus,
+    FEE_PRECISION, LP_TOKEN_PRECISION, MAX_AMP, MAX_AMP_CHANGE, MIN_RAMP_TIME,
+};
 use crate::msg::{
-    Cw20HookMsg, ExecuteMsg, InstantiateMsg, InterchainListResponse, InterchainPoolResponse,
-    MigrateMsg, MsgCancelMultiAssetDepositRequest, MsgCancelPoolRequest,
+    Cw20HookMsg, DepositAsset, ExecuteMsg, InstantiateMsg, InterchainListResponse,
+    InterchainPoolResponse, MigrateMsg, MsgCancelMultiAssetDepositRequest, MsgCancelPoolRequest,
     MsgMakeMultiAssetDepositRequest, MsgMakePoolRequest, MsgMultiAssetWithdrawRequest,
-    MsgRemovePool, MsgSingleAssetDepositRequest, MsgSwapRequest, MsgTakeMultiAssetDepositRequest,
-    MsgTakePoolRequest, OrderListResponse, PoolListResponse, QueryConfigResponse, QueryMsg,
-    SwapMsgType, TokenInstantiateMsg,
+    MsgRampAmpRequest, MsgRemovePool, MsgSetPoolStatusRequest, MsgSingleAssetDepositRequest,
+    MsgSingleAssetWithdrawRequest, MsgStopRampAmpRequest, MsgSwapRequest,
+    MsgTakeMultiAssetDepositRequest, MsgTakePoolRequest, OrderListResponse, OwnershipResponse,
+    PageResponse, PoolListResponse, PoolOrderBy, QueryAllInterchainLiquidityPoolRequest,
+    QueryAllInterchainLiquidityPoolResponse, QueryConfigResponse, QueryMsg, QuerySwapResponse,
+    IbcLifecycleComplete, SudoMsg, SwapMsgType, TokenInstantiateMsg, TwapResponse,
 };
+use crate::ledger::{self, send_packet, Escrow};
+use crate::migrations::{self, CURRENT_STATE_VERSION, STATE_VERSION};
+use crate::oracle;
+use crate::orderbook;
+use crate::simulation;
 use crate::response::MsgInstantiateContractResponse;
 use crate::state::{
-    Config, ACTIVE_ORDERS, CONFIG, LOG_VOLUME, MULTI_ASSET_DEPOSIT_ORDERS, POOLS, POOL_TOKENS_LIST,
-    TEMP,
+    multi_asset_deposit_orders, Config, PendingForward, ACTIVE_ORDERS, CONFIG, DEFERRED_EXPIRIES,
+    FORWARDS_DISPATCHED, LOG_VOLUME, ORDER_EXPIRIES, PENDING_ADMIN, PENDING_FORWARDS, POOLS,
+    POOLS_BY_CHANNEL, POOLS_BY_PAIR, POOL_TOKENS_LIST, PROTOCOL_FEES, RESUMED_POOLS,
+    ROUTED_OUTPUTS, TEMP,
 };
 use crate::types::{
-    InterchainMessageType, InterchainSwapPacketData, MultiAssetDepositOrder, OrderStatus,
-    StateChange
+    InterchainMessageType, InterchainSwapPacketData, Memo, MultiAssetDepositOrder, OrderStatus,
+    StateChange, MULTI_DEPOSIT_DEFAULT_EXPIRY, MULTI_DEPOSIT_MAX_EXPIRY,
 };
 use crate::utils::{
-    get_coins_from_deposits, get_order_id, get_pool_id_with_tokens, INSTANTIATE_TOKEN_REPLY_ID,
+    burn_tokens_cw20, funds_match, get_coins_by_side, get_coins_from_deposits, get_depositors,
+    get_order_id, get_pair_key, get_pool_id, send_tokens_coin, FORWARD_REPLY_ID,
+    INSTANTIATE_TOKEN_REPLY_ID, PACKET_SENT_REPLY_ID, ROUTER_REPLY_ID,
 };
 
 
@@ -43,7 +61,12 @@ use crate::utils::{
 const CONTRACT_NAME: &str = "ics101-interchainswap";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 const DEFAULT_TIMEOUT_TIMESTAMP_OFFSET: u64 = 600;
+const MSG_TRANSFER_TYPE_URL: &str = "/ibc.applications.transfer.v1.MsgTransfer";
+/// Longest timeout, in seconds from the current block, a caller may set on a packet.
+const MAX_TIMEOUT_TIMESTAMP_OFFSET: u64 = 7 * 24 * 60 * 60;
 const MAXIMUM_SLIPPAGE: u64 = 10000;
+const DEFAULT_SWEEP_LIMIT: u32 = 10;
+const MAX_SWEEP_LIMIT: u32 = 30;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -53,12 +76,23 @@ pub fn instantiate(
     msg: InstantiateMsg,
 ) -> StdResult<Response> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    STATE_VERSION.save(deps.storage, &CURRENT_STATE_VERSION)?;
+
+    let protocol_fee_rate = msg.protocol_fee_rate.unwrap_or_default();
+    if protocol_fee_rate > FEE_PRECISION.into() {
+        return Err(StdError::generic_err("Protocol fee rate exceeds fee precision"));
+    }
 
     let config = Config {
         counter: 0,
         token_code_id: msg.token_code_id,
         admin: info.sender.to_string(),
         router: msg.router,
+        protocol_fee_rate,
+        fee_collector: msg.fee_collector.unwrap_or_else(|| info.sender.to_string()),
+        guardian: msg.guardian.unwrap_or_else(|| info.sender.to_string()),
+        paused: false,
+        disabled_messages: vec![],
     };
 
     CONFIG.save(deps.storage, &config)?;
@@ -110,10 +144,210 @@ pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractE
             SubMsgResult::Ok(_) => Ok(Response::new()),
             SubMsgResult::Err(err) => Ok(Response::new().set_data(ack_fail(err))),
         },
+        FORWARD_REPLY_ID => {
+            let pending = FORWARDS_DISPATCHED
+                .pop_front(deps.storage)?
+                .ok_or_else(|| StdError::generic_err("No forward is waiting for a reply"))?;
+            match msg.result {
+                SubMsgResult::Ok(response) => {
+                    // Settled by the ibc-hooks callback once the transfer is acked or times out
+                    let sequence = ledger::sent_packet_sequence(&response)?;
+                    PENDING_FORWARDS.save(
+                        deps.storage,
+                        (&pending.forward.channel, sequence),
+                        &pending,
+                    )?;
+                    Ok(Response::new()
+                        .add_attribute("action", "forward")
+                        .add_attribute("packet_channel", pending.forward.channel)
+                        .add_attribute("packet_sequence", sequence.to_string()))
+                }
+                SubMsgResult::Err(err) => {
+                    // The same transfer would be rejected again, pay out on this chain instead
+                    let send_msgs =
+                        send_tokens_coin(&Addr::unchecked(pending.recipient), pending.token)?;
+                    Ok(Response::new()
+                        .add_submessages(send_msgs)
+                        .add_attribute("action", "forward")
+                        .add_attribute("success", "false")
+                        .add_attribute("error", err))
+                }
+            }
+        }
+        PACKET_SENT_REPLY_ID => ledger::on_packet_sent(deps.storage, msg.result),
+        ROUTER_REPLY_ID => {
+            let routed = ROUTED_OUTPUTS
+                .pop_front(deps.storage)?
+                .ok_or_else(|| StdError::generic_err("No routed output is waiting for a reply"))?;
+            match msg.result {
+                SubMsgResult::Ok(_) => Ok(Response::new()
+                    .add_attribute("action", "route")
+                    .add_attribute("success", "true")),
+                SubMsgResult::Err(err) => {
+                    // The router kept nothing, the output is paid out on this chain instead
+                    let send_msgs =
+                        send_tokens_coin(&Addr::unchecked(routed.recipient), routed.token)?;
+                    Ok(Response::new()
+                        .add_submessages(send_msgs)
+                        .add_attribute("action", "route")
+                        .add_attribute("success", "false")
+                        .add_attribute("error", err))
+                }
+            }
+        }
         _ => Err(StdError::generic_err(format!("Unknown reply ID: {}", msg.id)).into()),
     }
 }
 
+/// Builds the timeout of an outgoing packet from the caller's `timeout_height`, a block
+/// height on the counterparty chain, and `timeout_timestamp`, in nanoseconds. The default
+/// offset only applies when both are zero. Counterparty heights cannot be checked from
+/// here, so a packet bound by height alone still times out after the maximum horizon.
+pub(crate) fn packet_timeout(
+    env: &Env,
+    counterparty_chain_id: &str,
+    timeout_height: u64,
+    timeout_timestamp: u64,
+) -> Result<IbcTimeout, ContractError> {
+    let horizon = env.block.time.plus_seconds(MAX_TIMEOUT_TIMESTAMP_OFFSET);
+    let timestamp = match timeout_timestamp {
+        0 => None,
+        nanos => {
+            let timestamp = Timestamp::from_nanos(nanos);
+            if timestamp <= env.block.time {
+                return Err(ContractError::InvalidTimeout {
+                    reason: format!("timestamp {} has already passed", timestamp),
+                });
+            }
+            if timestamp > horizon {
+                return Err(ContractError::InvalidTimeout {
+                    reason: format!(
+                        "timestamp {} is more than {} seconds ahead",
+                        timestamp, MAX_TIMEOUT_TIMESTAMP_OFFSET
+                    ),
+                });
+            }
+            Some(timestamp)
+        }
+    };
+
+    let timeout = match (timeout_height, timestamp) {
+        (0, None) => IbcTimeout::with_timestamp(
+            env.block
+                .time
+                .plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET),
+        ),
+        (0, Some(timestamp)) => IbcTimeout::with_timestamp(timestamp),
+        (height, timestamp) => IbcTimeout::with_both(
+            IbcTimeoutBlock {
+                revision: revision_number(counterparty_chain_id),
+                height,
+            },
+            timestamp.unwrap_or(horizon),
+        ),
+    };
+    Ok(timeout)
+}
+
+/// Revision number of a chain id in the `{identifier}-{revision}` format, zero otherwise.
+fn revision_number(chain_id: &str) -> u64 {
+    chain_id
+        .rsplit_once('-')
+        .and_then(|(_, revision)| revision.parse().ok())
+        .unwrap_or_default()
+}
+
+/// Sends a swap output on over ICS-20 as instructed by its forward memo. The transfer asks
+/// ibc-hooks to call back into `sudo` with its outcome, where a timed out forward is retried
+/// and a failed one falls back to paying the recipient locally.
+pub(crate) fn forward_tokens(
+    storage: &mut dyn Storage,
+    env: &Env,
+    pending: PendingForward,
+) -> StdResult<SubMsg> {
+    let timeout = match pending.forward.timeout_seconds()? {
+        0 => DEFAULT_TIMEOUT_TIMESTAMP_OFFSET,
+        seconds => seconds,
+    };
+    let transfer = CosmosMsg::Stargate {
+        type_url: MSG_TRANSFER_TYPE_URL.to_string(),
+        value: encode_transfer(env, &pending, env.block.time.plus_seconds(timeout))?,
+    };
+    FORWARDS_DISPATCHED.push_back(storage, &pending)?;
+    Ok(SubMsg::reply_always(transfer, FORWARD_REPLY_ID))
+}
+
+/// Encodes an ICS-20 `MsgTransfer` from this contract carrying an ibc-hooks callback memo,
+/// which `IbcMsg::Transfer` has no field for.
+fn encode_transfer(env: &Env, pending: &PendingForward, timeout: Timestamp) -> StdResult<Binary> {
+    let sender = env.contract.address.as_str();
+    let token = encode_proto(|os| {
+        os.write_string(1, &pending.token.denom)?;
+        os.write_string(2, &pending.token.amount.to_string())
+    })?;
+    let memo = format!(r#"{{"ibc_callback":"{}"}}"#, sender);
+    let transfer = encode_proto(|os| {
+        os.write_string(1, &pending.forward.port)?;
+        os.write_string(2, &pending.forward.channel)?;
+        os.write_bytes(3, &token)?;
+        os.write_string(4, sender)?;
+        os.write_string(5, &pending.forward.receiver)?;
+        os.write_uint64(7, timeout.nanos())?;
+        os.write_string(8, &memo)
+    })?;
+    Ok(Binary::from(transfer))
+}
+
+fn encode_proto(
+    write: impl FnOnce(&mut CodedOutputStream) -> ProtobufResult<()>,
+) -> StdResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    {
+        let mut os = CodedOutputStream::vec(&mut bytes);
+        write(&mut os)
+            .and_then(|_| os.flush())
+            .map_err(|err| StdError::generic_err(format!("Failed to encode message: {}", err)))?;
+    }
+    Ok(bytes)
+}
+
+/// Outcome of a forwarded transfer reported by ibc-hooks. A timed out forward is sent again
+/// while it has retries left, the tokens of any other failure are back in this contract and
+/// are paid to the recipient here.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    let SudoMsg::IbcLifecycleComplete(outcome) = msg;
+    let (channel, sequence, timed_out) = match &outcome {
+        IbcLifecycleComplete::IbcAck { channel, sequence, success: true, .. } => {
+            PENDING_FORWARDS.remove(deps.storage, (channel, *sequence));
+            return Ok(Response::new()
+                .add_attribute("action", "forward_settled")
+                .add_attribute("success", "true"));
+        }
+        IbcLifecycleComplete::IbcAck { channel, sequence, .. } => (channel, *sequence, false),
+        IbcLifecycleComplete::IbcTimeout { channel, sequence } => (channel, *sequence, true),
+    };
+    // Transfers this contract did not forward have nothing to settle here
+    let mut pending = match PENDING_FORWARDS.may_load(deps.storage, (channel, sequence))? {
+        Some(pending) => pending,
+        None => return Ok(Response::new().add_attribute("action", "forward_settled")),
+    };
+    PENDING_FORWARDS.remove(deps.storage, (channel, sequence));
+
+    if timed_out && pending.attempts_left > 0 {
+        pending.attempts_left -= 1;
+        let forward_msg = forward_tokens(deps.storage, &env, pending)?;
+        return Ok(Response::new()
+            .add_submessage(forward_msg)
+            .add_attribute("action", "forward_retry"));
+    }
+    let send_msgs = send_tokens_coin(&Addr::unchecked(pending.recipient), pending.token)?;
+    Ok(Response::new()
+        .add_submessages(send_msgs)
+        .add_attribute("action", "forward_settled")
+        .add_attribute("success", "false"))
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn execute(
     deps: DepsMut,
@@ -132,12 +366,73 @@ pub fn execute(
         }
         ExecuteMsg::TakeMultiAssetDeposit(msg) => take_multi_asset_deposit(deps, env, info, msg),
         ExecuteMsg::MultiAssetWithdraw(msg) => multi_asset_withdraw(deps, env, info, msg),
+        ExecuteMsg::SingleAssetWithdraw(msg) => single_asset_withdraw(deps, env, info, msg),
         ExecuteMsg::Swap(msg) => swap(deps, env, info, msg),
         ExecuteMsg::RemovePool(msg) => remove_pool(deps, env, info, msg),
         ExecuteMsg::SetLogAddress { pool_id, address } => {
             set_log_address(deps, env, info, pool_id, address)
         } //ExecuteMsg::Receive(msg) => receive_cw20(deps, env, info, msg),
-        ExecuteMsg::SetRouter { address } => set_router_address(deps, env, info, address)
+        ExecuteMsg::SetRouter { address } => set_router_address(deps, env, info, address),
+        ExecuteMsg::RampAmp(msg) => ramp_amp(deps, env, info, msg),
+        ExecuteMsg::StopRampAmp(msg) => stop_ramp_amp(deps, env, info, msg),
+        ExecuteMsg::SetPoolStatus(msg) => set_pool_status(deps, env, info, msg),
+        ExecuteMsg::SetProtocolFee {
+            protocol_fee_rate,
+            fee_collector,
+        } => set_protocol_fee(deps, env, info, protocol_fee_rate, fee_collector),
+        ExecuteMsg::ClaimProtocolFees { pool_id } => claim_protocol_fees(deps, env, info, pool_id),
+        ExecuteMsg::PlaceLimitOrder(msg) => orderbook::place_limit_order(deps, env, info, msg),
+        ExecuteMsg::CancelLimitOrder { order_id } => {
+            orderbook::cancel_limit_order(deps, info, order_id)
+        }
+        ExecuteMsg::ExecuteLimitOrders {
+            pool_id,
+            denom_in,
+            limit,
+        } => orderbook::execute_limit_orders(deps, env, pool_id, denom_in, limit),
+        ExecuteMsg::PlaceTwapOrder(msg) => orderbook::place_twap_order(deps, env, info, msg),
+        ExecuteMsg::CancelTwapOrder { order_id } => {
+            orderbook::cancel_twap_order(deps, info, order_id)
+        }
+        ExecuteMsg::ExecuteTwapOrder { order_id } => {
+            orderbook::execute_twap_order(deps, env, order_id)
+        }
+        ExecuteMsg::RetryPacket {
+            channel_id,
+            sequence,
+            timeout_height,
+            timeout_timestamp,
+        } => ledger::retry_packet(
+            deps,
+            env,
+            info,
+            channel_id,
+            sequence,
+            timeout_height,
+            timeout_timestamp,
+        ),
+        ExecuteMsg::RefundPacket {
+            channel_id,
+            sequence,
+        } => ledger::refund_packet(deps, info, channel_id, sequence),
+        ExecuteMsg::TransferOwnership { new_admin } => transfer_ownership(deps, info, new_admin),
+        ExecuteMsg::AcceptOwnership {} => accept_ownership(deps, info),
+        ExecuteMsg::RenounceOwnership {} => renounce_ownership(deps, info),
+        ExecuteMsg::Pause {} => pause(deps, info),
+        ExecuteMsg::Unpause {} => unpause(deps, info),
+        ExecuteMsg::SetGuardian { address } => set_guardian(deps, info, address),
+        ExecuteMsg::SetMessageEnabled {
+            message_type,
+            enabled,
+        } => set_message_enabled(deps, info, message_type, enabled),
+        ExecuteMsg::RegisterChannel {
+            chain_id,
+            channel_id,
+        } => channels::register_channel(deps, info, chain_id, channel_id),
+        ExecuteMsg::WithdrawFrozen { pool_id, amount } => {
+            withdraw_frozen(deps, env, info, pool_id, amount)
+        }
+        ExecuteMsg::SweepExpiredOrders { limit } => sweep_expired_orders(deps, env, limit),
     }
 }
 
@@ -147,14 +442,13 @@ fn remove_pool(
     info: MessageInfo,
     msg: MsgRemovePool,
 ) -> Result<Response, ContractError> {
-    let config = CONFIG.load(deps.storage)?;
-    if config.admin != info.sender {
-        return Err(ContractError::Std(StdError::generic_err(
-            "not allowed".to_string(),
-        )));
-    }
+    ensure_admin(deps.storage, &info.sender)?;
 
     POOL_TOKENS_LIST.remove(deps.storage, &msg.pool_id);
+    if let Some(pool) = POOLS.may_load(deps.storage, &msg.pool_id)? {
+        POOLS_BY_PAIR.remove(deps.storage, (&pool.pair_key(), &msg.pool_id));
+        POOLS_BY_CHANNEL.remove(deps.storage, (&pool.counter_party_channel, &msg.pool_id));
+    }
     POOLS.remove(deps.storage, &msg.pool_id);
 
     Ok(Response::default())
@@ -167,12 +461,7 @@ fn set_log_address(
     pool_id: String,
     address: String,
 ) -> Result<Response, ContractError> {
-    let config = CONFIG.load(deps.storage)?;
-    if config.admin != info.sender {
-        return Err(ContractError::Std(StdError::generic_err(
-            "not allowed".to_string(),
-        )));
-    }
+    ensure_admin(deps.storage, &info.sender)?;
 
     LOG_VOLUME.save(deps.storage, pool_id, &address)?;
 
@@ -184,18 +473,254 @@ fn set_router_address(
     _env: Env,
     info: MessageInfo,
     address: String,
+) -> Result<Response, ContractError> {
+    let mut config = ensure_admin(deps.storage, &info.sender)?;
+
+    config.router = address;
+    CONFIG.save(deps.storage, &config)?;
+
+    Ok(Response::default())
+}
+
+/// Loads the config, failing unless `sender` is the admin. Every admin-only handler
+/// authorizes through here.
+pub(crate) fn ensure_admin(
+    storage: &dyn Storage,
+    sender: &Addr,
+) -> Result<Config, ContractError> {
+    let config = CONFIG.load(storage)?;
+    if config.admin != *sender {
+        return Err(ContractError::Unauthorized);
+    }
+    Ok(config)
+}
+
+/// Proposes a new admin. The current admin stays in charge until the proposal is accepted.
+fn transfer_ownership(
+    deps: DepsMut,
+    info: MessageInfo,
+    new_admin: String,
+) -> Result<Response, ContractError> {
+    ensure_admin(deps.storage, &info.sender)?;
+    let new_admin = deps.api.addr_validate(&new_admin)?;
+    PENDING_ADMIN.save(deps.storage, &new_admin.to_string())?;
+
+    Ok(Response::default()
+        .add_attribute("action", "transfer_ownership")
+        .add_attribute("pending_admin", new_admin))
+}
+
+fn accept_ownership(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let pending_admin = PENDING_ADMIN.may_load(deps.storage)?;
+    if pending_admin.as_deref() != Some(info.sender.as_str()) {
+        return Err(ContractError::Unauthorized);
+    }
+
+    let mut config = CONFIG.load(deps.storage)?;
+    config.admin = info.sender.to_string();
+    CONFIG.save(deps.storage, &config)?;
+    PENDING_ADMIN.remove(deps.storage);
+
+    Ok(Response::default()
+        .add_attribute("action", "accept_ownership")
+        .add_attribute("admin", info.sender))
+}
+
+/// Leaves the contract without an admin for good. Admin-only handlers can no longer be used,
+/// so it is refused while the contract is paused.
+fn renounce_ownership(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = ensure_admin(deps.storage, &info.sender)?;
+    if config.paused {
+        return Err(ContractError::Paused);
+    }
+    config.admin = String::new();
+    CONFIG.save(deps.storage, &config)?;
+    PENDING_ADMIN.remove(deps.storage);
+
+    Ok(Response::default().add_attribute("action", "renounce_ownership"))
+}
+
+fn pause(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    if info.sender != config.guardian && info.sender != config.admin {
+        return Err(ContractError::Unauthorized);
+    }
+
+    config.paused = true;
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::default().add_attribute("action", "pause"))
+}
+
+/// Only the admin can resume the contract, or the guardian once ownership was renounced.
+fn unpause(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
+    let mut config = CONFIG.load(deps.storage)?;
+    let allowed = info.sender == config.admin
+        || (config.admin.is_empty() && info.sender == config.guardian);
+    if !allowed {
+        return Err(ContractError::Unauthorized);
+    }
+
+    config.paused = false;
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::default().add_attribute("action", "unpause"))
+}
+
+fn set_guardian(
+    deps: DepsMut,
+    info: MessageInfo,
+    address: String,
+) -> Result<Response, ContractError> {
+    let mut config = ensure_admin(deps.storage, &info.sender)?;
+
+    config.guardian = deps.api.addr_validate(&address)?.to_string();
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::default()
+        .add_attribute("action", "set_guardian")
+        .add_attribute("guardian", address))
+}
+
+/// The guardian can switch a message type off, only the admin can switch it back on.
+fn set_message_enabled(
+    deps: DepsMut,
+    info: MessageInfo,
+    message_type: InterchainMessageType,
+    enabled: bool,
 ) -> Result<Response, ContractError> {
     let mut config = CONFIG.load(deps.storage)?;
-    if config.admin != info.sender {
+    let allowed = info.sender == config.admin || (!enabled && info.sender == config.guardian);
+    if !allowed {
+        return Err(ContractError::Unauthorized);
+    }
+
+    config.disabled_messages.retain(|disabled| *disabled != message_type);
+    if !enabled {
+        config.disabled_messages.push(message_type.clone());
+    }
+    CONFIG.save(deps.storage, &config)?;
+    Ok(Response::default()
+        .add_attribute("action", "set_message_enabled")
+        .add_attribute("message_type", format!("{:?}", message_type))
+        .add_attribute("enabled", enabled.to_string()))
+}
+
+fn set_protocol_fee(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    protocol_fee_rate: u32,
+    fee_collector: String,
+) -> Result<Response, ContractError> {
+    let mut config = ensure_admin(deps.storage, &info.sender)?;
+    if protocol_fee_rate > FEE_PRECISION.into() {
         return Err(ContractError::Std(StdError::generic_err(
-            "not allowed".to_string(),
+            "Protocol fee rate exceeds fee precision",
         )));
     }
 
-    config.router = address;
+    config.protocol_fee_rate = protocol_fee_rate;
+    config.fee_collector = deps.api.addr_validate(&fee_collector)?.to_string();
     CONFIG.save(deps.storage, &config)?;
 
-    Ok(Response::default())
+    Ok(Response::default()
+        .add_attribute("action", "set_protocol_fee")
+        .add_attribute("protocol_fee_rate", protocol_fee_rate.to_string())
+        .add_attribute("fee_collector", fee_collector))
+}
+
+/// Sends every protocol fee accrued on this chain for the pool to the fee collector.
+fn claim_protocol_fees(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    pool_id: String,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if config.fee_collector != info.sender {
+        return Err(ContractError::Unauthorized);
+    }
+
+    let fees: Vec<Coin> = PROTOCOL_FEES
+        .prefix(&pool_id)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+        .collect::<StdResult<_>>()?;
+
+    let mut sub_messages = vec![];
+    for fee in fees.into_iter().filter(|fee| !fee.amount.is_zero()) {
+        PROTOCOL_FEES.remove(deps.storage, (&pool_id, &fee.denom));
+        sub_messages.append(&mut send_tokens_coin(&info.sender, fee)?);
+    }
+
+    Ok(Response::default()
+        .add_submessages(sub_messages)
+        .add_attribute("pool_id", pool_id)
+        .add_attribute("action", "claim_protocol_fees"))
+}
+
+/// Pays out the local side of a frozen pool, see [`ExecuteMsg::WithdrawFrozen`].
+fn withdraw_frozen(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    pool_id: String,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let mut interchain_pool = POOLS.load(deps.storage, &pool_id)?;
+    if interchain_pool.status != PoolStatus::Frozen {
+        return Err(ContractError::InvalidStatus);
+    }
+
+    let local_assets = interchain_pool.find_assets_by_side(PoolSide::SOURCE);
+    let mut sub_messages = vec![];
+    let payout: Vec<Coin> = if interchain_pool.supply.amount.is_zero() {
+        // Never taken, the deposit escrowed by make_pool goes back to the creator
+        if interchain_pool.source_chain_id != env.block.chain_id
+            || interchain_pool.source_creator != info.sender
+        {
+            return Err(ContractError::InvalidSender);
+        }
+        interchain_pool.status = PoolStatus::Cancelled;
+        local_assets.into_iter().map(|asset| asset.balance).collect()
+    } else {
+        if amount.is_zero() || amount > interchain_pool.supply.amount {
+            return Err(ContractError::InvalidAmount);
+        }
+        let lp_token = POOL_TOKENS_LIST.load(deps.storage, &pool_id)?;
+        let share = Decimal::from_ratio(amount, interchain_pool.supply.amount);
+        sub_messages.push(SubMsg::new(WasmMsg::Execute {
+            contract_addr: lp_token.clone(),
+            msg: to_binary(&Cw20ExecuteMsg::TransferFrom {
+                owner: info.sender.to_string(),
+                recipient: env.contract.address.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        }));
+        sub_messages.push(burn_tokens_cw20(lp_token, amount)?);
+        interchain_pool.subtract_supply(Coin {
+            denom: pool_id.clone(),
+            amount,
+        })?;
+        local_assets
+            .into_iter()
+            .map(|asset| Coin {
+                denom: asset.balance.denom,
+                amount: asset.balance.amount * share,
+            })
+            .collect()
+    };
+
+    for token in payout.into_iter().filter(|token| !token.amount.is_zero()) {
+        interchain_pool.subtract_asset(token.clone())?;
+        sub_messages.append(&mut send_tokens_coin(&info.sender, token)?);
+    }
+    POOLS.save(deps.storage, &pool_id, &interchain_pool)?;
+
+    Ok(Response::default()
+        .add_submessages(sub_messages)
+        .add_attribute("pool_id", pool_id)
+        .add_attribute("action", "withdraw_frozen")
+        .add_attribute("amount", amount))
 }
 
 /// Receives a message of type [`Cw20ReceiveMsg`] and processes it depending on the received template.
@@ -238,8 +763,20 @@ fn make_pool(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    msg: MsgMakePoolRequest,
+    mut msg: MsgMakePoolRequest,
 ) -> Result<Response, ContractError> {
+    // The pool is served over the channel registered for the destination chain
+    let channel = channels::resolve_channel(deps.storage, &msg.destination_chain_id)?;
+    if !msg.source_channel.is_empty() {
+        channels::ensure_channel(&channel.id, &msg.source_channel)?;
+    }
+    let counterparty_channel = &channel.counterparty_endpoint.channel_id;
+    if !msg.counterparty_channel.is_empty() {
+        channels::ensure_channel(counterparty_channel, &msg.counterparty_channel)?;
+    }
+    msg.source_channel = channel.id.clone();
+    msg.counterparty_channel = counterparty_channel.clone();
+
     // validate message
     let _source_port = msg.source_port.clone();
     let source_channel = msg.source_channel.clone();
@@ -251,36 +788,28 @@ fn make_pool(
         ))));
     }
 
-    let mut tokens: [Coin; 2] = Default::default();
-    tokens[0] = msg.liquidity[0].balance.clone();
-    tokens[1] = msg.liquidity[1].balance.clone();
-
-    let pool_id = get_pool_id_with_tokens(
-        &tokens,
+    let pool_id = get_pool_id(
+        &msg.liquidity,
+        msg.swap_fee,
+        &msg.curve,
         msg.source_chain_id.clone(),
         msg.destination_chain_id.clone(),
     );
 
     TEMP.save(deps.storage, &pool_id)?;
-    // load pool throw error if not found
-    let interchain_pool_temp = POOLS.may_load(deps.storage, &pool_id)?;
-    if let Some(_pool) = interchain_pool_temp {
-        return Err(ContractError::Std(StdError::generic_err(
-            "Pool already exists".to_string(),
-        )));
+    if POOLS.has(deps.storage, &pool_id) {
+        return Err(ContractError::PoolAlreadyExists { pool_id });
     }
 
     // check if given tokens are received here
-    let mut ok = false;
-    // First token in this chain only first token needs to be verified
-    for asset in info.funds {
-        if (asset.denom == tokens[0].denom && asset.amount == tokens[0].amount)
-            || (asset.denom == tokens[1].denom && asset.amount == tokens[1].amount)
-        {
-            ok = true;
-        }
-    }
-    if !ok {
+    // Only the assets on this chain (source side) need to be verified
+    let source_tokens: Vec<Coin> = msg
+        .liquidity
+        .iter()
+        .filter(|asset| asset.side == PoolSide::SOURCE)
+        .map(|asset| asset.balance.clone())
+        .collect();
+    if !funds_match(&info.funds, &source_tokens) {
         return Err(ContractError::Std(StdError::generic_err(
             "Funds mismatch: Funds mismatched to with message and sent values: Make Pool"
                 .to_string(),
@@ -304,8 +833,17 @@ fn make_pool(
         source_chain_id: msg.source_chain_id.clone(),
         destination_chain_id: msg.destination_chain_id.clone(),
         pool_price: 0,
+        curve: msg.curve.clone(),
+        amp_ramp: None,
+        dynamic_fee: msg.dynamic_fee.clone(),
     };
     POOLS.save(deps.storage, &pool_id, &interchain_pool)?;
+    POOLS_BY_PAIR.save(deps.storage, (&interchain_pool.pair_key(), &pool_id), &Empty {})?;
+    POOLS_BY_CHANNEL.save(
+        deps.storage,
+        (&interchain_pool.counter_party_channel, &pool_id),
+        &Empty {},
+    )?;
 
     // Instantiate token
     let config = CONFIG.load(deps.storage)?;
@@ -350,6 +888,8 @@ fn make_pool(
         multi_deposit_order_id: None,
         source_chain_id: None,
         shares: None,
+        amp_ramp: None,
+        protocol_fees: None,
     })?;
 
     let pool_data = to_binary(&msg)?;
@@ -363,22 +903,31 @@ fn make_pool(
     };
 
     
-    let ibc_msg = IbcMsg::SendPacket {
-        channel_id: source_channel,
-        data: to_binary(&ibc_packet_data)?,
-        timeout: IbcTimeout::from(
-            env.block
-                .time
-                .plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET),
-        ),
-    };
+    let timeout = packet_timeout(
+        &env,
+        &msg.destination_chain_id,
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        source_channel,
+        &ibc_packet_data,
+        timeout,
+        Escrow {
+            sender: info.sender.to_string(),
+            pool_id: pool_id.clone(),
+            funds: source_tokens,
+        },
+    )?;
 
     let res = Response::default()
         .add_attribute("pool_id", pool_id.clone())
         .add_attribute("action", "make_pool")
         .add_attribute("ics101-lp-instantiate", pool_id)
         .add_submessages(sub_msg)
-        .add_message(ibc_msg);
+        .add_submessage(ibc_msg);
     Ok(res)
 }
 
@@ -445,32 +994,30 @@ fn take_pool(
     }
 
     // check balance and funds sent handle error
-    let token = interchain_pool
-        .find_asset_by_side(PoolSide::SOURCE)
-        .map_err(|err| StdError::generic_err(format!("Failed to find asset: {}", err)))?;
+    let source_tokens: Vec<Coin> = interchain_pool
+        .find_assets_by_side(PoolSide::SOURCE)
+        .into_iter()
+        .map(|asset| asset.balance)
+        .collect();
     // check if given tokens are received here
-    let mut ok = false;
-    for asset in info.funds {
-        if asset.denom == token.balance.denom && asset.amount == token.balance.amount {
-            ok = true;
-        }
-    }
-    if !ok {
+    if !funds_match(&info.funds, &source_tokens) {
         return Err(ContractError::Std(StdError::generic_err(
             "Funds mismatch: Funds mismatched to with message and sent values: Take Pool"
                 .to_string(),
         )));
     }
 
-    let mut tokens: [Coin; 2] = Default::default();
-    tokens[0] = interchain_pool.assets[0].balance.clone();
-    tokens[1] = interchain_pool.assets[1].balance.clone();
+    let tokens: Vec<Coin> = interchain_pool
+        .assets
+        .iter()
+        .map(|asset| asset.balance.clone())
+        .collect();
 
     // find number of tokens to be minted
     // Create the interchain market maker (amm).
     let amm = InterchainMarketMaker {
         pool_id: msg.pool_id.clone(),
-        pool: interchain_pool.clone(),
+        pool: interchain_pool.at_time(env.block.time.seconds()),
         fee_rate: interchain_pool.swap_fee,
     };
 
@@ -490,6 +1037,8 @@ fn take_pool(
         multi_deposit_order_id: None,
         source_chain_id: None,
         shares: Some(new_shares),
+        amp_ramp: None,
+        protocol_fees: None,
     })?;
 
     let pool_data = to_binary(&msg).unwrap();
@@ -500,19 +1049,28 @@ fn take_pool(
         memo: msg.memo,
     };
 
-    let ibc_msg = IbcMsg::SendPacket {
-        channel_id: interchain_pool.counter_party_channel,
-        data: to_binary(&ibc_packet_data)?,
-        timeout: IbcTimeout::from(
-            env.block
-                .time
-                .plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET),
-        ),
-    };
+    let timeout = packet_timeout(
+        &env,
+        interchain_pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        interchain_pool.counter_party_channel,
+        &ibc_packet_data,
+        timeout,
+        Escrow {
+            sender: info.sender.to_string(),
+            pool_id: msg.pool_id.clone(),
+            funds: source_tokens,
+        },
+    )?;
 
     let res = Response::default()
         .add_submessages(sub_msg)
-        .add_message(ibc_msg)
+        .add_submessage(ibc_msg)
         .add_attribute("pool_id", msg.pool_id)
         .add_attribute("action", "take_pool");
     Ok(res)
@@ -554,18 +1112,27 @@ fn cancel_pool(
         memo: msg.memo,
     };
 
-    let ibc_msg = IbcMsg::SendPacket {
-        channel_id: interchain_pool.counter_party_channel,
-        data: to_binary(&ibc_packet_data)?,
-        timeout: IbcTimeout::from(
-            env.block
-                .time
-                .plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET),
-        ),
-    };
+    let timeout = packet_timeout(
+        &env,
+        interchain_pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        interchain_pool.counter_party_channel,
+        &ibc_packet_data,
+        timeout,
+        Escrow {
+            sender: info.sender.to_string(),
+            pool_id: msg.pool_id.clone(),
+            funds: vec![],
+        },
+    )?;
 
     let res = Response::default()
-        .add_message(ibc_msg)
+        .add_submessage(ibc_msg)
         .add_attribute("pool_id", msg.pool_id)
         .add_attribute("action", "take_pool");
     Ok(res)
@@ -598,8 +1165,54 @@ pub fn single_asset_deposit(
         )));
     }
 
-    let pool_id = msg.pool_id.clone();
-    let pool = POOLS.load(deps.storage, &pool_id)?;
+    let (pool, state_change) = quote_single_deposit(deps.storage, &env, &msg.pool_id, &msg.token)?;
+
+    let msg_data = to_binary(&msg).unwrap();
+    let state_change_data = to_binary(&state_change)?;
+    // Construct the IBC swap packet.
+    let packet_data = InterchainSwapPacketData {
+        r#type: InterchainMessageType::SingleAssetDeposit,
+        data: msg_data, // Use proper serialization for the `data` field.
+        state_change: Some(state_change_data),
+        memo: msg.memo,
+    };
+
+    // Send the IBC swap packet.
+    let timeout = packet_timeout(
+        &env,
+        pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        pool.counter_party_channel,
+        &packet_data,
+        timeout,
+        Escrow {
+            sender: msg.sender.clone(),
+            pool_id: msg.pool_id.clone(),
+            funds: vec![msg.token.clone()],
+        },
+    )?;
+
+    let res = Response::default()
+        .add_submessage(ibc_msg)
+        .add_attribute("pool_id", msg.pool_id)
+        .add_attribute("action", "single_asset_deposit");
+    Ok(res)
+}
+
+/// Prices a single asset deposit against a local pool. Shared by deposits and their
+/// simulation.
+pub(crate) fn quote_single_deposit(
+    storage: &dyn Storage,
+    env: &Env,
+    pool_id: &str,
+    token: &Coin,
+) -> Result<(InterchainLiquidityPool, StateChange), ContractError> {
+    let pool = POOLS.load(storage, pool_id)?;
 
     // If the pool is empty, then return a `Failure` response
     if pool.supply.amount.is_zero() {
@@ -614,18 +1227,17 @@ pub fn single_asset_deposit(
 
     // Create the interchain market maker (amm).
     let amm = InterchainMarketMaker {
-        pool_id,
-        pool: pool.clone(),
+        pool_id: pool_id.to_string(),
+        pool: pool.at_time(env.block.time.seconds()),
         fee_rate: pool.swap_fee,
     };
 
     // Deposit single asset to the AMM.
     let pool_token = amm
-        .deposit_single_asset(&msg.token)
+        .deposit_single_asset(token)
         .map_err(|err| StdError::generic_err(format!("Failed to deposit single asset: {}", err)))?;
 
-    let msg_data = to_binary(&msg).unwrap();
-    let state_change_data = to_binary(&StateChange {
+    let state_change = StateChange {
         in_tokens: None,
         out_tokens: None,
         pool_tokens: Some(vec![pool_token.clone()]),
@@ -633,73 +1245,47 @@ pub fn single_asset_deposit(
         multi_deposit_order_id: None,
         source_chain_id: None,
         shares: Some(pool_token.amount),
-    })?;
-    // Construct the IBC swap packet.
-    let packet_data = InterchainSwapPacketData {
-        r#type: InterchainMessageType::SingleAssetDeposit,
-        data: msg_data, // Use proper serialization for the `data` field.
-        state_change: Some(state_change_data),
-        memo: msg.memo,
-    };
-
-    // Send the IBC swap packet.
-    let ibc_msg = IbcMsg::SendPacket {
-        channel_id: pool.counter_party_channel,
-        data: to_binary(&packet_data)?,
-        timeout: IbcTimeout::from(
-            env.block
-                .time
-                .plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET),
-        ),
+        amp_ramp: None,
+        protocol_fees: None,
     };
+    Ok((pool, state_change))
+}
 
-    let res = Response::default()
-        .add_message(ibc_msg)
-        .add_attribute("pool_id", msg.pool_id)
-        .add_attribute("action", "single_asset_deposit");
-    Ok(res)
+/// A multi asset deposit order priced against the current pool.
+pub(crate) struct MultiDepositQuote {
+    pub pool: InterchainLiquidityPool,
+    pub order_id: String,
+    pub source_maker: String,
+    pub destination_taker: String,
+    pub tokens: Vec<Coin>,
+    pub state_change: StateChange,
 }
 
-fn make_multi_asset_deposit(
-    deps: DepsMut,
-    env: Env,
-    info: MessageInfo,
-    msg: MsgMakeMultiAssetDepositRequest,
-) -> Result<Response, ContractError> {
+/// Prices a multi asset deposit order made on this chain. Shared by making orders and their
+/// simulation.
+pub(crate) fn quote_multi_deposit(
+    storage: &dyn Storage,
+    env: &Env,
+    pool_id: &str,
+    deposits: &[DepositAsset],
+) -> Result<MultiDepositQuote, ContractError> {
     // load pool throw error if not found
-    let interchain_pool_temp = POOLS.may_load(deps.storage, &msg.pool_id)?;
+    let interchain_pool_temp = POOLS.may_load(storage, pool_id)?;
     let interchain_pool;
     if let Some(pool) = interchain_pool_temp {
         interchain_pool = pool
     } else {
         return Err(ContractError::Std(StdError::generic_err(format!(
             "Pool doesn't exist {}",
-            msg.pool_id
+            pool_id
         ))));
     }
     // TODO: deposit balance or any balance can't be zero
     // Add checks in every function
 
-    let mut tokens: [Coin; 2] = Default::default();
-    tokens[0] = msg.deposits[0].balance.clone();
-    tokens[1] = msg.deposits[1].balance.clone();
-
-    // check if given tokens are received here
-    let mut ok = false;
-    // First token in this chain only first token needs to be verified
-    for asset in info.funds {
-        if asset.denom == tokens[0].denom && asset.amount == tokens[0].amount
-            || (asset.denom == tokens[1].denom && asset.amount == tokens[1].amount)
-        {
-            ok = true;
-        }
-    }
-    if !ok {
-        return Err(ContractError::Std(StdError::generic_err(
-            "Funds mismatch: Funds mismatched to with message and sent values: Make Pool"
-                .to_string(),
-        )));
-    }
+    let (source_maker, destination_taker) =
+        get_depositors(&interchain_pool, deposits, PoolSide::SOURCE)?;
+    let tokens = get_coins_from_deposits(deposits.to_vec());
 
     // Check the pool status
     if interchain_pool.status != PoolStatus::Active {
@@ -709,63 +1295,93 @@ fn make_multi_asset_deposit(
     // Create the interchain market maker
     let amm = InterchainMarketMaker {
         pool_id: interchain_pool.clone().id,
-        pool: interchain_pool.clone(),
+        pool: interchain_pool.at_time(env.block.time.seconds()),
         fee_rate: interchain_pool.swap_fee,
     };
 
     // Deposit the assets into the interchain market maker
-    let pool_tokens = amm.deposit_multi_asset(&[
-        msg.deposits[0].balance.clone(),
-        msg.deposits[1].balance.clone(),
-    ])?;
+    let pool_tokens = amm.deposit_multi_asset(&tokens)?;
+
+    let config = CONFIG.load(storage)?;
+    let order_id = get_order_id(source_maker.clone(), config.counter + 1);
+    let state_change = StateChange {
+        in_tokens: None,
+        out_tokens: None,
+        pool_tokens: Some(pool_tokens),
+        pool_id: None,
+        multi_deposit_order_id: Some(order_id.clone()),
+        source_chain_id: None,
+        shares: None,
+        amp_ramp: None,
+        protocol_fees: None,
+    };
+    Ok(MultiDepositQuote {
+        pool: interchain_pool,
+        order_id,
+        source_maker,
+        destination_taker,
+        tokens,
+        state_change,
+    })
+}
 
+fn make_multi_asset_deposit(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    mut msg: MsgMakeMultiAssetDepositRequest,
+) -> Result<Response, ContractError> {
+    let quote = quote_multi_deposit(deps.storage, &env, &msg.pool_id, &msg.deposits)?;
+    let interchain_pool = quote.pool;
+
+    // check if given tokens are received here
+    // Only the assets on this chain (source side) need to be verified
+    let source_tokens = get_coins_by_side(&interchain_pool, &quote.tokens, PoolSide::SOURCE);
+    if !funds_match(&info.funds, &source_tokens) {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Funds mismatch: Funds mismatched to with message and sent values: Make Pool"
+                .to_string(),
+        )));
+    }
+
+    // The expiry travels with the packet so that both chains agree on it
+    let now = env.block.time.seconds();
+    let expires_at = msg.expires_at.unwrap_or(now + MULTI_DEPOSIT_DEFAULT_EXPIRY);
+    if expires_at <= now || expires_at > now + MULTI_DEPOSIT_MAX_EXPIRY {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Order expiry must be within {} seconds from now",
+            MULTI_DEPOSIT_MAX_EXPIRY
+        ))));
+    }
+    msg.expires_at = Some(expires_at);
+
+    // The quote named the order after the next counter value
     let mut config = CONFIG.load(deps.storage)?;
+    config.counter += 1;
 
-    let mut multi_asset_order = MultiAssetDepositOrder {
-        id: "".to_string(),
+    let multi_asset_order = MultiAssetDepositOrder {
+        id: quote.order_id,
         chain_id: msg.chain_id.clone(),
         pool_id: msg.pool_id.clone(),
-        source_maker: msg.deposits[0].sender.clone(),
-        destination_taker: msg.deposits[1].sender.clone(),
-        deposits: get_coins_from_deposits(msg.deposits.clone()),
+        source_maker: quote.source_maker.clone(),
+        destination_taker: quote.destination_taker.clone(),
+        deposits: quote.tokens,
         //pool_tokens: pool_tokens,
         status: OrderStatus::Pending,
         created_at: env.block.height,
+        expires_at,
     };
 
-    // load orders
-    // check for order, if exist throw error.
-
-    let ac_key = msg.deposits[0].sender.clone()
-        + "-"
-        + &msg.pool_id.clone()
-        + "-"
-        + &msg.deposits[1].sender.clone();
-    // let multi_asset_order_temp = ACTIVE_ORDERS.may_load(deps.storage, ac_key.clone())?;
-
-    // if let Some(_order) = multi_asset_order_temp {
-    //     return Err(ContractError::ErrPreviousOrderNotCompleted);
-    // }
-    config.counter += 1;
-    multi_asset_order.id = get_order_id(msg.deposits[0].sender.clone(), config.counter);
-    //}
-
     // save order in source chain
+    let ac_key = quote.source_maker + "-" + &msg.pool_id + "-" + &quote.destination_taker;
     let key = msg.pool_id.clone() + "-" + &multi_asset_order.id;
-    MULTI_ASSET_DEPOSIT_ORDERS.save(deps.storage, key, &multi_asset_order)?;
+    multi_asset_deposit_orders().save(deps.storage, key.clone(), &multi_asset_order)?;
     ACTIVE_ORDERS.save(deps.storage, ac_key, &multi_asset_order)?;
+    ORDER_EXPIRIES.save(deps.storage, (expires_at, key), &Empty {})?;
     CONFIG.save(deps.storage, &config)?;
 
     // Construct the IBC packet
-    let state_change_data = to_binary(&StateChange {
-        in_tokens: None,
-        out_tokens: None,
-        pool_tokens: Some(pool_tokens),
-        pool_id: None,
-        multi_deposit_order_id: Some(multi_asset_order.id),
-        source_chain_id: None,
-        shares: None,
-    })?;
+    let state_change_data = to_binary(&quote.state_change)?;
     let packet_data = InterchainSwapPacketData {
         r#type: InterchainMessageType::MakeMultiDeposit,
         data: to_binary(&msg)?,
@@ -773,18 +1389,27 @@ fn make_multi_asset_deposit(
         memo: msg.memo
     };
 
-    let ibc_msg = IbcMsg::SendPacket {
-        channel_id: interchain_pool.counter_party_channel,
-        data: to_binary(&packet_data)?,
-        timeout: IbcTimeout::from(
-            env.block
-                .time
-                .plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET),
-        ),
-    };
+    let timeout = packet_timeout(
+        &env,
+        interchain_pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        interchain_pool.counter_party_channel,
+        &packet_data,
+        timeout,
+        Escrow {
+            sender: info.sender.to_string(),
+            pool_id: msg.pool_id.clone(),
+            funds: source_tokens,
+        },
+    )?;
 
     let res = Response::default()
-        .add_message(ibc_msg)
+        .add_submessage(ibc_msg)
         .add_attribute("pool_id", msg.pool_id)
         .add_attribute("action", "make_multi_asset_deposit");
     Ok(res)
@@ -807,10 +1432,16 @@ fn cancel_multi_asset_deposit(
             msg.pool_id
         ))));
     }
+
+    // Check the pool status, orders of a frozen pool are refunded without a packet
+    if !matches!(interchain_pool.status, PoolStatus::Active | PoolStatus::Frozen) {
+        return Err(ContractError::NotReadyForSwap);
+    }
+
     // get order
     // load orders
     let key = msg.pool_id.clone() + "-" + &msg.order_id;
-    let multi_asset_order_temp = MULTI_ASSET_DEPOSIT_ORDERS.may_load(deps.storage, key)?;
+    let multi_asset_order_temp = multi_asset_deposit_orders().may_load(deps.storage, key)?;
     let multi_asset_order;
     if let Some(order) = multi_asset_order_temp {
         multi_asset_order = order;
@@ -826,6 +1457,31 @@ fn cancel_multi_asset_deposit(
         return Err(ContractError::ErrOrderAlreadyCompleted);
     }
 
+    let pool_id = msg.pool_id.clone();
+    if interchain_pool.status == PoolStatus::Frozen {
+        let sub_messages = refund_frozen_order(deps.storage, &interchain_pool, multi_asset_order)?;
+        return Ok(Response::default()
+            .add_submessages(sub_messages)
+            .add_attribute("pool_id", pool_id)
+            .add_attribute("action", "refund_frozen_order"));
+    }
+    let ibc_msg = send_cancel_order(deps.storage, &env, &interchain_pool, &multi_asset_order, msg)?;
+
+    let res = Response::default()
+        .add_submessage(ibc_msg)
+        .add_attribute("pool_id", pool_id)
+        .add_attribute("action", "cancel_multi_asset_deposit");
+    Ok(res)
+}
+
+/// Asks the counterparty to cancel a pending order. The maker is refunded on acknowledgement.
+fn send_cancel_order(
+    storage: &mut dyn Storage,
+    env: &Env,
+    interchain_pool: &InterchainLiquidityPool,
+    order: &MultiAssetDepositOrder,
+    msg: MsgCancelMultiAssetDepositRequest,
+) -> Result<SubMsg, ContractError> {
     let packet_data = InterchainSwapPacketData {
         r#type: InterchainMessageType::CancelMultiDeposit,
         data: to_binary(&msg)?,
@@ -833,21 +1489,170 @@ fn cancel_multi_asset_deposit(
         memo: msg.memo,
     };
 
-    let ibc_msg = IbcMsg::SendPacket {
-        channel_id: interchain_pool.counter_party_channel,
-        data: to_binary(&packet_data)?,
-        timeout: IbcTimeout::from(
-            env.block
-                .time
-                .plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET),
-        ),
-    };
+    let timeout = packet_timeout(
+        env,
+        interchain_pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    send_packet(
+        storage,
+        env,
+        interchain_pool.counter_party_channel.clone(),
+        &packet_data,
+        timeout,
+        Escrow {
+            sender: order.source_maker.clone(),
+            pool_id: msg.pool_id,
+            funds: vec![],
+        },
+    )
+}
 
-    let res = Response::default()
-        .add_message(ibc_msg)
-        .add_attribute("pool_id", msg.pool_id)
-        .add_attribute("action", "cancel_multi_asset_deposit");
-    Ok(res)
+/// Refunds the maker of a pending order on a frozen pool here. The closed channel can
+/// deliver neither a cancel nor the taker's deposit, so the order is cancelled locally.
+/// Only orders made on this chain, the ones queued for expiry, hold the maker's escrow.
+fn refund_frozen_order(
+    storage: &mut dyn Storage,
+    interchain_pool: &InterchainLiquidityPool,
+    mut order: MultiAssetDepositOrder,
+) -> Result<Vec<SubMsg>, ContractError> {
+    let key = order.pool_id.clone() + "-" + &order.id;
+    let expiry = (order.expires_at, key.clone());
+    if ORDER_EXPIRIES.has(storage, expiry.clone()) {
+        ORDER_EXPIRIES.remove(storage, expiry);
+    } else if DEFERRED_EXPIRIES.has(storage, (&order.pool_id, &key)) {
+        DEFERRED_EXPIRIES.remove(storage, (&order.pool_id, &key));
+    } else {
+        return Err(ContractError::InvalidChain);
+    }
+
+    let ac_key =
+        order.source_maker.clone() + "-" + &order.pool_id + "-" + &order.destination_taker;
+    ACTIVE_ORDERS.remove(storage, ac_key);
+    order.status = OrderStatus::Cancelled;
+    multi_asset_deposit_orders().save(storage, key, &order)?;
+
+    let mut sub_messages = vec![];
+    for deposit in get_coins_by_side(interchain_pool, &order.deposits, PoolSide::SOURCE) {
+        sub_messages.append(&mut send_tokens_coin(
+            &Addr::unchecked(order.source_maker.clone()),
+            deposit,
+        )?);
+    }
+    Ok(sub_messages)
+}
+
+/// Cancels pending orders made on this chain whose expiry has passed, oldest first. Orders
+/// that are no longer pending are dropped from the expiry queue and orders of frozen pools are
+/// refunded here. Orders of other pools that are not active are set aside by pool, and go back
+/// on the queue once their pool is active or frozen. A cancel that fails or times out puts its
+/// order back on the queue. At most `limit` entries are visited per call.
+fn sweep_expired_orders(
+    deps: DepsMut,
+    env: Env,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let limit = limit.unwrap_or(DEFAULT_SWEEP_LIMIT).min(MAX_SWEEP_LIMIT) as usize;
+    let now = env.block.time.seconds();
+    let visited = requeue_deferred_orders(deps.storage, limit)?;
+    let expired = ORDER_EXPIRIES
+        .keys(
+            deps.storage,
+            None,
+            Some(Bound::exclusive((now + 1, String::new()))),
+            Order::Ascending,
+        )
+        .take(limit - visited)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut sub_messages = vec![];
+    let mut cancelled = vec![];
+    for (expires_at, key) in expired {
+        let order = match multi_asset_deposit_orders().may_load(deps.storage, key.clone())? {
+            Some(order) if order.status == OrderStatus::Pending => order,
+            _ => {
+                ORDER_EXPIRIES.remove(deps.storage, (expires_at, key));
+                continue;
+            }
+        };
+        let interchain_pool = match POOLS.may_load(deps.storage, &order.pool_id)? {
+            Some(pool) if pool.status == PoolStatus::Active => pool,
+            Some(pool) if pool.status == PoolStatus::Frozen => {
+                cancelled.push(order.id.clone());
+                sub_messages.append(&mut refund_frozen_order(deps.storage, &pool, order)?);
+                continue;
+            }
+            Some(_) => {
+                ORDER_EXPIRIES.remove(deps.storage, (expires_at, key.clone()));
+                DEFERRED_EXPIRIES.save(deps.storage, (&order.pool_id, &key), &expires_at)?;
+                continue;
+            }
+            None => {
+                ORDER_EXPIRIES.remove(deps.storage, (expires_at, key));
+                continue;
+            }
+        };
+
+        let msg = MsgCancelMultiAssetDepositRequest {
+            sender: order.source_maker.clone(),
+            pool_id: order.pool_id.clone(),
+            order_id: order.id.clone(),
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+        };
+        sub_messages.push(send_cancel_order(deps.storage, &env, &interchain_pool, &order, msg)?);
+        ORDER_EXPIRIES.remove(deps.storage, (expires_at, key));
+        cancelled.push(order.id);
+    }
+
+    Ok(Response::new()
+        .add_submessages(sub_messages)
+        .add_attribute("action", "sweep_expired_orders")
+        .add_attribute("cancelled_orders", cancelled.join(",")))
+}
+
+/// Moves orders of resumed pools from the deferred orders back on the expiry queue. Returns
+/// how many entries were visited, a resumed pool with nothing left counting as one, which is
+/// at most `limit`.
+fn requeue_deferred_orders(storage: &mut dyn Storage, limit: usize) -> StdResult<usize> {
+    let mut visited = 0;
+    while visited < limit {
+        let pool_id = match RESUMED_POOLS.front(storage)? {
+            Some(pool_id) => pool_id,
+            None => break,
+        };
+        let room = limit - visited;
+        let deferred = DEFERRED_EXPIRIES
+            .prefix(&pool_id)
+            .range(storage, None, None, Order::Ascending)
+            .take(room + 1)
+            .collect::<StdResult<Vec<_>>>()?;
+        if deferred.len() <= room {
+            RESUMED_POOLS.pop_front(storage)?;
+        }
+        visited += deferred.len().clamp(1, room);
+        for (key, expires_at) in deferred.into_iter().take(room) {
+            DEFERRED_EXPIRIES.remove(storage, (&pool_id, &key));
+            ORDER_EXPIRIES.save(storage, (expires_at, key), &Empty {})?;
+        }
+    }
+    Ok(visited)
+}
+
+/// Lets the next sweeps pick up the orders set aside while `pool_id` was neither active nor
+/// frozen. Called whenever the pool becomes active or frozen.
+pub(crate) fn resume_deferred_orders(storage: &mut dyn Storage, pool_id: &str) -> StdResult<()> {
+    let deferred = DEFERRED_EXPIRIES
+        .prefix(pool_id)
+        .keys(storage, None, None, Order::Ascending)
+        .next()
+        .is_some();
+    if deferred {
+        RESUMED_POOLS.push_back(storage, &pool_id.to_string())?;
+    }
+    Ok(())
 }
 
 fn take_multi_asset_deposit(
@@ -870,7 +1675,7 @@ fn take_multi_asset_deposit(
     // get order
     // load orders
     let key = msg.pool_id.clone() + "-" + &msg.order_id;
-    let multi_asset_order_temp = MULTI_ASSET_DEPOSIT_ORDERS.may_load(deps.storage, key)?;
+    let multi_asset_order_temp = multi_asset_deposit_orders().may_load(deps.storage, key)?;
     let multi_asset_order;
     if let Some(order) = multi_asset_order_temp {
         multi_asset_order = order;
@@ -885,22 +1690,17 @@ fn take_multi_asset_deposit(
     if multi_asset_order.status == OrderStatus::Complete {
         return Err(ContractError::ErrOrderAlreadyCompleted);
     }
+    if multi_asset_order.is_expired(env.block.time.seconds()) {
+        return Err(ContractError::OrderExpired {
+            order_id: multi_asset_order.id,
+        });
+    }
 
-    let token = interchain_pool
-        .find_asset_by_side(PoolSide::SOURCE)
-        .map_err(|err| StdError::generic_err(format!("Failed to find asset: {}", err)))?;
     // check if given tokens are received here
-    let mut ok = false;
-    // First token in this chain only first token needs to be verified
-    for asset in info.funds {
-        if asset.denom == token.balance.denom
-            && multi_asset_order.deposits[1].amount == asset.amount
-            && asset.denom == multi_asset_order.deposits[1].denom
-        {
-            ok = true;
-        }
-    }
-    if !ok {
+    // Only the assets on this chain (source side) need to be verified
+    let source_tokens =
+        get_coins_by_side(&interchain_pool, &multi_asset_order.deposits, PoolSide::SOURCE);
+    if !funds_match(&info.funds, &source_tokens) {
         return Err(ContractError::Std(StdError::generic_err(
             "Funds mismatch: Funds mismatched to with message and sent values: Take Multi Asset"
                 .to_string(),
@@ -911,7 +1711,7 @@ fn take_multi_asset_deposit(
     // Create the interchain market maker (amm).
     let amm = InterchainMarketMaker {
         pool_id: msg.pool_id.clone(),
-        pool: interchain_pool.clone(),
+        pool: interchain_pool.at_time(env.block.time.seconds()),
         fee_rate: interchain_pool.swap_fee,
     };
 
@@ -930,6 +1730,8 @@ fn take_multi_asset_deposit(
         multi_deposit_order_id: None,
         source_chain_id: None,
         shares: Some(new_shares),
+        amp_ramp: None,
+        protocol_fees: None,
     })?;
     let packet_data = InterchainSwapPacketData {
         r#type: InterchainMessageType::TakeMultiDeposit,
@@ -938,23 +1740,134 @@ fn take_multi_asset_deposit(
         memo: msg.memo
     };
 
-    let ibc_msg = IbcMsg::SendPacket {
-        channel_id: interchain_pool.counter_party_channel,
-        data: to_binary(&packet_data)?,
-        timeout: IbcTimeout::from(
-            env.block
-                .time
-                .plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET),
-        ),
-    };
+    let timeout = packet_timeout(
+        &env,
+        interchain_pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        interchain_pool.counter_party_channel,
+        &packet_data,
+        timeout,
+        Escrow {
+            sender: info.sender.to_string(),
+            pool_id: msg.pool_id.clone(),
+            funds: source_tokens,
+        },
+    )?;
 
     let res = Response::default()
-        .add_message(ibc_msg)
+        .add_submessage(ibc_msg)
         .add_attribute("pool_id", msg.pool_id)
         .add_attribute("action", "take_multi_asset_deposit");
     Ok(res)
 }
 
+/// Prices a proportional withdraw of `pool_token` from this chain. Shared by withdraws and
+/// their simulation.
+pub(crate) fn quote_multi_withdraw(
+    storage: &dyn Storage,
+    env: &Env,
+    pool_id: &str,
+    pool_token: &Coin,
+) -> Result<(InterchainLiquidityPool, StateChange), ContractError> {
+    // Get liquidity pool
+    // load pool throw error if not found
+    let interchain_pool = POOLS.may_load(storage, pool_id)?.ok_or_else(|| {
+        ContractError::Std(StdError::generic_err(format!(
+            "Pool doesn't exist {}",
+            pool_id
+        )))
+    })?;
+
+    if interchain_pool.status == PoolStatus::Frozen {
+        return Err(ContractError::PoolFrozen {
+            pool_id: pool_id.to_string(),
+        });
+    }
+
+    // Create the interchain market maker
+    let amm = InterchainMarketMaker {
+        pool_id: interchain_pool.clone().id,
+        pool: interchain_pool.at_time(env.block.time.seconds()),
+        fee_rate: interchain_pool.swap_fee,
+    };
+
+    let refund_assets = amm
+        .multi_asset_withdraw(pool_token.clone())
+        .map_err(|err| StdError::generic_err(format!("Failed to withdraw multi asset: {}", err)))?;
+
+    let state_change = StateChange {
+        in_tokens: Some(vec![pool_token.clone()]),
+        out_tokens: Some(refund_assets),
+        pool_tokens: Some(vec![pool_token.clone()]),
+        pool_id: None,
+        multi_deposit_order_id: None,
+        source_chain_id: None,
+        shares: None,
+        amp_ramp: None,
+        protocol_fees: None,
+    };
+    Ok((interchain_pool, state_change))
+}
+
+/// Prices a withdraw of `pool_coin` into the single asset `denom_out`. Shared by withdraws and
+/// their simulation.
+pub(crate) fn quote_single_withdraw(
+    storage: &dyn Storage,
+    env: &Env,
+    pool_coin: &Coin,
+    denom_out: &str,
+) -> Result<(InterchainLiquidityPool, StateChange), ContractError> {
+    // Get liquidity pool
+    let pool_id = pool_coin.denom.clone();
+    let interchain_pool = POOLS.may_load(storage, &pool_id)?.ok_or_else(|| {
+        ContractError::Std(StdError::generic_err(format!(
+            "Pool doesn't exist {}",
+            pool_id
+        )))
+    })?;
+
+    if interchain_pool.status == PoolStatus::Frozen {
+        return Err(ContractError::PoolFrozen { pool_id });
+    }
+
+    // Only assets held on this chain can be released to the withdrawer
+    let asset_out = interchain_pool.find_asset_by_denom(denom_out)?;
+    if asset_out.side != PoolSide::SOURCE {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Only assets on this chain can be withdrawn".to_string(),
+        )));
+    }
+
+    // Create the interchain market maker
+    let amm = InterchainMarketMaker {
+        pool_id: interchain_pool.clone().id,
+        pool: interchain_pool.at_time(env.block.time.seconds()),
+        fee_rate: interchain_pool.swap_fee,
+    };
+
+    let token_out = amm
+        .withdraw_single_asset(pool_coin.clone(), denom_out)
+        .map_err(|err| StdError::generic_err(format!("Failed to withdraw single asset: {}", err)))?;
+
+    let state_change = StateChange {
+        in_tokens: Some(vec![pool_coin.clone()]),
+        out_tokens: Some(vec![token_out]),
+        pool_tokens: Some(vec![pool_coin.clone()]),
+        pool_id: Some(pool_id),
+        multi_deposit_order_id: None,
+        source_chain_id: None,
+        shares: None,
+        amp_ramp: None,
+        protocol_fees: None,
+    };
+    Ok((interchain_pool, state_change))
+}
+
 // Pass pool id asset i.e cw20
 fn multi_asset_withdraw(
     deps: DepsMut,
@@ -962,18 +1875,8 @@ fn multi_asset_withdraw(
     info: MessageInfo,
     msg: MsgMultiAssetWithdrawRequest,
 ) -> Result<Response, ContractError> {
-    // Get liquidity pool
-    // load pool throw error if not found
-    let interchain_pool_temp = POOLS.may_load(deps.storage, &msg.pool_id)?;
-    let interchain_pool;
-    if let Some(pool) = interchain_pool_temp {
-        interchain_pool = pool
-    } else {
-        return Err(ContractError::Std(StdError::generic_err(format!(
-            "Pool doesn't exist {}",
-            msg.pool_id
-        ))));
-    }
+    let (interchain_pool, state_change) =
+        quote_multi_withdraw(deps.storage, &env, &msg.pool_id, &msg.pool_token)?;
 
     let sub_messages: Vec<SubMsg>;
     if let Some(lp_token) = POOL_TOKENS_LIST.may_load(deps.storage, &msg.pool_id)? {
@@ -997,75 +1900,114 @@ fn multi_asset_withdraw(
         )));
     }
 
-    // Create the interchain market maker
-    let amm = InterchainMarketMaker {
-        pool_id: interchain_pool.clone().id,
-        pool: interchain_pool.clone(),
-        fee_rate: interchain_pool.swap_fee,
+    let state_change_data = to_binary(&state_change)?;
+
+    let packet = InterchainSwapPacketData {
+        r#type: InterchainMessageType::MultiWithdraw,
+        data: to_binary(&msg)?,
+        state_change: Some(state_change_data),
+        memo: msg.memo,
     };
 
-    let refund_assets = amm
-        .multi_asset_withdraw(msg.pool_token.clone())
-        .map_err(|err| StdError::generic_err(format!("Failed to withdraw multi asset: {}", err)))?;
+    let timeout = packet_timeout(
+        &env,
+        interchain_pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        interchain_pool.counter_party_channel,
+        &packet,
+        timeout,
+        Escrow {
+            sender: info.sender.to_string(),
+            pool_id: msg.pool_id.clone(),
+            funds: vec![msg.pool_token.clone()],
+        },
+    )?;
 
-    let source_denom = interchain_pool
-        .find_asset_by_side(PoolSide::SOURCE)
-        .map_err(|err| StdError::generic_err(format!("Failed to find asset: {}", err)))?;
+    let res = Response::default()
+        .add_submessages(sub_messages)
+        .add_submessage(ibc_msg)
+        .add_attribute("pool_id", msg.pool_id)
+        .add_attribute("action", "multi_asset_withdraw");
+    Ok(res)
+}
 
-    let destination_denom = interchain_pool
-        .find_asset_by_side(PoolSide::DESTINATION)
-        .map_err(|err| StdError::generic_err(format!("Failed to find asset: {}", err)))?;
+// Pass pool id asset i.e cw20, the pool coin denom is the pool id
+fn single_asset_withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: MsgSingleAssetWithdrawRequest,
+) -> Result<Response, ContractError> {
+    if msg.sender != info.sender {
+        return Err(ContractError::InvalidSender);
+    }
 
-    let mut source_out = Coin {
-        denom: "mock".to_string(),
-        amount: Uint128::zero(),
-    };
-    let mut destination_out = Coin {
-        denom: "mock".to_string(),
-        amount: Uint128::zero(),
-    };
+    let pool_id = msg.pool_coin.denom.clone();
+    let (interchain_pool, state_change) =
+        quote_single_withdraw(deps.storage, &env, &msg.pool_coin, &msg.denom_out)?;
+    let token_out = state_change.out_tokens.clone().unwrap_or_default().remove(0);
 
-    for asset in refund_assets {
-        if &asset.denom == &source_denom.balance.denom {
-            source_out = asset.clone();
-        }
-        if &asset.denom == &destination_denom.balance.denom {
-            destination_out = asset;
-        }
+    let sub_messages: Vec<SubMsg>;
+    if let Some(lp_token) = POOL_TOKENS_LIST.may_load(deps.storage, &pool_id)? {
+        // Transfer tokens from user account to contract
+        let msg = Cw20ExecuteMsg::TransferFrom {
+            owner: info.sender.to_string(),
+            recipient: env.contract.address.to_string(),
+            amount: msg.pool_coin.amount,
+        };
+        let exec = WasmMsg::Execute {
+            contract_addr: lp_token,
+            msg: to_binary(&msg)?,
+            funds: vec![],
+        };
+        sub_messages = vec![SubMsg::new(exec)];
+    } else {
+        // throw error token not found, initialization is done in make_pool and
+        // take_pool
+        return Err(ContractError::Std(StdError::generic_err(
+            "LP Token is not initialized".to_string(),
+        )));
     }
 
-    let state_change_data = to_binary(&StateChange {
-        in_tokens: Some(vec![msg.pool_token.clone()]),
-        out_tokens: Some(vec![source_out, destination_out]),
-        pool_tokens: Some(vec![msg.pool_token.clone()]),
-        pool_id: None,
-        multi_deposit_order_id: None,
-        source_chain_id: None,
-        shares: None,
-    })?;
+    let state_change_data = to_binary(&state_change)?;
 
     let packet = InterchainSwapPacketData {
-        r#type: InterchainMessageType::MultiWithdraw,
+        r#type: InterchainMessageType::SingleAssetWithdraw,
         data: to_binary(&msg)?,
         state_change: Some(state_change_data),
         memo: msg.memo,
     };
 
-    let ibc_msg = IbcMsg::SendPacket {
-        channel_id: interchain_pool.counter_party_channel,
-        data: to_binary(&packet)?,
-        timeout: IbcTimeout::from(
-            env.block
-                .time
-                .plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET),
-        ),
-    };
+    let timeout = packet_timeout(
+        &env,
+        interchain_pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        interchain_pool.counter_party_channel,
+        &packet,
+        timeout,
+        Escrow {
+            sender: msg.sender.clone(),
+            pool_id: pool_id.clone(),
+            funds: vec![msg.pool_coin.clone()],
+        },
+    )?;
 
     let res = Response::default()
         .add_submessages(sub_messages)
-        .add_message(ibc_msg)
-        .add_attribute("pool_id", msg.pool_id)
-        .add_attribute("action", "multi_asset_withdraw");
+        .add_submessage(ibc_msg)
+        .add_attribute("pool_id", pool_id)
+        .add_attribute("token_out", token_out.to_string())
+        .add_attribute("action", "single_asset_withdraw");
     Ok(res)
 }
 
@@ -1075,123 +2017,554 @@ fn swap(
     info: MessageInfo,
     msg: MsgSwapRequest,
 ) -> Result<Response, ContractError> {
+    // check if given tokens are received here
+    let mut ok = false;
+    // First token in this chain only first token needs to be verified
+    for asset in info.funds {
+        if asset.denom == msg.token_in.denom && asset.amount == msg.token_in.amount {
+            ok = true;
+        }
+    }
+    if !ok {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Funds mismatch: Funds mismatched to with message and sent values: Swap".to_string(),
+        )));
+    }
+
+    // Forwarding is resolved on the counterparty, reject memos it could not honor now
+    if let Some(memo) = &msg.memo {
+        Memo::from_memo(memo)?;
+    }
+
+    let (ibc_msg, attributes) = swap_packet(deps, &env, msg)?;
+
+    let res = Response::default()
+        .add_submessage(ibc_msg)
+        .add_attributes(attributes)
+        .add_attribute("action", "swap");
+    Ok(res)
+}
+
+/// A swap priced against the current pool, with the state change its packet carries.
+pub(crate) struct SwapQuote {
+    pub pool: InterchainLiquidityPool,
+    pub packet_type: InterchainMessageType,
+    pub fee_rate: u32,
+    pub swap_fee: Coin,
+    pub state_change: StateChange,
+}
+
+/// Prices a swap against a local pool. Shared by swaps and their simulation.
+pub(crate) fn quote_swap(
+    storage: &dyn Storage,
+    env: &Env,
+    pool_id: &str,
+    swap_type: &SwapMsgType,
+    token_in: &Coin,
+    token_out: &Coin,
+    slippage: u64,
+) -> Result<SwapQuote, ContractError> {
     // Get liquidity pool
     // load pool throw error if not found
-    let interchain_pool_temp = POOLS.may_load(deps.storage, &msg.pool_id)?;
+    let interchain_pool_temp = POOLS.may_load(storage, pool_id)?;
     let interchain_pool;
     if let Some(pool) = interchain_pool_temp {
         interchain_pool = pool
     } else {
         return Err(ContractError::Std(StdError::generic_err(format!(
             "Pool doesn't exist {}",
-            msg.pool_id
+            pool_id
         ))));
     }
+    quote_swap_on(
+        storage,
+        env,
+        interchain_pool,
+        swap_type,
+        token_in,
+        token_out,
+        slippage,
+    )
+}
 
+/// Prices a swap against the given copy of a pool, which may hold fills not settled yet.
+pub(crate) fn quote_swap_on(
+    storage: &dyn Storage,
+    env: &Env,
+    interchain_pool: InterchainLiquidityPool,
+    swap_type: &SwapMsgType,
+    token_in: &Coin,
+    token_out: &Coin,
+    slippage: u64,
+) -> Result<SwapQuote, ContractError> {
     // Check the pool status
     if interchain_pool.status != PoolStatus::Active {
         return Err(ContractError::NotReadyForSwap);
     }
 
-    // check if given tokens are received here
-    let mut ok = false;
-    // First token in this chain only first token needs to be verified
-    for asset in info.funds {
-        if asset.denom == msg.token_in.denom && asset.amount == msg.token_in.amount {
-            ok = true;
-        }
-    }
-    if !ok {
-        return Err(ContractError::Std(StdError::generic_err(
-            "Funds mismatch: Funds mismatched to with message and sent values: Swap".to_string(),
-        )));
-    }
-
     // Create the interchain market maker
     let amm = InterchainMarketMaker {
         pool_id: interchain_pool.clone().id,
-        pool: interchain_pool.clone(),
+        pool: interchain_pool.at_time(env.block.time.seconds()),
         fee_rate: interchain_pool.swap_fee,
     };
 
-    // Construct the IBC data packet
-    let swap_data = to_binary(&msg)?;
-    let token_out: Coin;
+    let computed: Coin;
     let msg_type: InterchainMessageType;
+    let fee_rate: u32;
 
-    match msg.swap_type {
+    match swap_type {
         SwapMsgType::LEFT => {
             msg_type = InterchainMessageType::LeftSwap;
-            token_out = amm.compute_swap(msg.token_in.clone(), &msg.token_out.denom)?;
+            fee_rate = amm.left_swap_fee_rate(token_in, &token_out.denom)?;
+            computed = amm
+                .with_fee_rate(fee_rate)
+                .compute_swap(token_in.clone(), &token_out.denom)?;
         }
         SwapMsgType::RIGHT => {
             msg_type = InterchainMessageType::RightSwap;
-            token_out = amm.compute_offer_amount(msg.token_in.clone(), msg.token_out.clone())?;
+            fee_rate = amm.right_swap_fee_rate(&token_in.denom, token_out)?;
+            computed = amm
+                .with_fee_rate(fee_rate)
+                .compute_offer_amount(token_in.clone(), token_out.clone())?;
+            // The escrowed input caps the offer, any excess is returned on acknowledgement
+            if computed.amount > token_in.amount {
+                return Err(ContractError::FailedOnSwapReceived {
+                    err: format!(
+                        "offer exceeds the input! offer: {}, input: {}",
+                        computed, token_in
+                    ),
+                });
+            }
         }
     }
+    let amm = amm.with_fee_rate(fee_rate);
 
     // Slippage checking
-    let factor = MAXIMUM_SLIPPAGE - msg.slippage;
-    let expected = msg
-        .token_out
+    let factor = MAXIMUM_SLIPPAGE.saturating_sub(slippage);
+    let expected = token_out
         .amount
         .mul(Uint128::from(factor))
         .div(Uint128::from(MAXIMUM_SLIPPAGE));
-    if token_out.amount.lt(&expected) {
+    if computed.amount.lt(&expected) {
         return Err(ContractError::FailedOnSwapReceived {
             err: format!(
                 "slippage check failed! expected: {}, output: {:?}, factor: {}",
-                expected, token_out, factor
+                expected, computed, factor
             ),
         });
     }
 
+    // The protocol keeps a share of the fee charged on the input asset
+    let config = CONFIG.load(storage)?;
+    let fee_base = match swap_type {
+        SwapMsgType::LEFT => token_in.amount,
+        SwapMsgType::RIGHT => computed.amount,
+    };
+    let protocol_fee = Coin {
+        denom: token_in.denom.clone(),
+        amount: amm.protocol_fee(fee_base, config.protocol_fee_rate),
+    };
+    let swap_fee = Coin {
+        denom: token_in.denom.clone(),
+        amount: fee_base.multiply_ratio(fee_rate, FEE_PRECISION),
+    };
+
+    Ok(SwapQuote {
+        pool: interchain_pool,
+        packet_type: msg_type,
+        fee_rate,
+        swap_fee,
+        state_change: StateChange {
+            in_tokens: None,
+            out_tokens: Some(vec![computed]),
+            pool_tokens: None,
+            pool_id: None,
+            multi_deposit_order_id: None,
+            source_chain_id: None,
+            shares: None,
+            amp_ramp: None,
+            protocol_fees: Some(vec![protocol_fee]),
+        },
+    })
+}
+
+/// Prices a swap against a local pool and builds the packet settling it on the
+/// counterparty chain. The input tokens must already be held by this contract.
+pub(crate) fn swap_packet(
+    deps: DepsMut,
+    env: &Env,
+    msg: MsgSwapRequest,
+) -> Result<(SubMsg, Vec<Attribute>), ContractError> {
+    let quote = quote_swap(
+        deps.storage,
+        env,
+        &msg.pool_id,
+        &msg.swap_type,
+        &msg.token_in,
+        &msg.token_out,
+        msg.slippage,
+    )?;
+    send_swap_packet(deps.storage, env, msg, quote)
+}
+
+/// Builds the packet settling an already priced swap on the counterparty chain.
+pub(crate) fn send_swap_packet(
+    storage: &mut dyn Storage,
+    env: &Env,
+    msg: MsgSwapRequest,
+    quote: SwapQuote,
+) -> Result<(SubMsg, Vec<Attribute>), ContractError> {
+    // Construct the IBC data packet
+    let packet = InterchainSwapPacketData {
+        r#type: quote.packet_type,
+        data: to_binary(&msg)?,
+        state_change: Some(to_binary(&quote.state_change)?),
+        memo: msg.memo,
+    };
+
+    let timeout = packet_timeout(
+        env,
+        quote.pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        storage,
+        env,
+        quote.pool.counter_party_channel,
+        &packet,
+        timeout,
+        Escrow {
+            sender: msg.sender.clone(),
+            pool_id: msg.pool_id.clone(),
+            funds: vec![msg.token_in.clone()],
+        },
+    )?;
+
+    let attributes = vec![
+        attr("pool_id", msg.pool_id),
+        attr("swap_fee_rate", quote.fee_rate.to_string()),
+        attr("swap_fee", quote.swap_fee.to_string()),
+    ];
+    Ok((ibc_msg, attributes))
+}
+
+fn ramp_amp(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: MsgRampAmpRequest,
+) -> Result<Response, ContractError> {
+    ensure_admin(deps.storage, &info.sender)?;
+
+    let interchain_pool = POOLS.may_load(deps.storage, &msg.pool_id)?.ok_or_else(|| {
+        ContractError::Std(StdError::generic_err(format!(
+            "Pool doesn't exist {}",
+            msg.pool_id
+        )))
+    })?;
+    if interchain_pool.status != PoolStatus::Active {
+        return Err(ContractError::InvalidStatus);
+    }
+
+    let now = env.block.time.seconds();
+    let current_amp = match interchain_pool.curve_at(now) {
+        PoolCurve::Stable { amp } => amp,
+        PoolCurve::Weighted => {
+            return Err(ContractError::Std(StdError::generic_err(
+                "Pool does not use the stable curve",
+            )))
+        }
+    };
+
+    if let Some(ramp) = &interchain_pool.amp_ramp {
+        if ramp.future_time > now {
+            return Err(ContractError::Std(StdError::generic_err(
+                "Amplification ramp already in progress",
+            )));
+        }
+    }
+    if msg.future_time < now + MIN_RAMP_TIME {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Ramp must last at least {} seconds",
+            MIN_RAMP_TIME
+        ))));
+    }
+    if msg.future_amp == 0 || msg.future_amp > MAX_AMP {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Amplification must be between 1 and {}",
+            MAX_AMP
+        ))));
+    }
+    if msg.future_amp > current_amp * MAX_AMP_CHANGE
+        || msg.future_amp * MAX_AMP_CHANGE < current_amp
+    {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Amplification can change by at most {}x per ramp",
+            MAX_AMP_CHANGE
+        ))));
+    }
+
+    // Both chains apply the same absolute ramp so they quote the same curve at any block time
+    let amp_ramp = AmpRamp {
+        initial_amp: current_amp,
+        future_amp: msg.future_amp,
+        initial_time: now,
+        future_time: msg.future_time,
+    };
+
     let state_change_data = to_binary(&StateChange {
         in_tokens: None,
-        out_tokens: Some(vec![token_out]),
+        out_tokens: None,
         pool_tokens: None,
-        pool_id: None,
+        pool_id: Some(msg.pool_id.clone()),
         multi_deposit_order_id: None,
         source_chain_id: None,
         shares: None,
+        amp_ramp: Some(amp_ramp),
+        protocol_fees: None,
+    })?;
+
+    let packet = InterchainSwapPacketData {
+        r#type: InterchainMessageType::RampAmp,
+        data: to_binary(&msg)?,
+        state_change: Some(state_change_data),
+        memo: msg.memo,
+    };
+
+    let timeout = packet_timeout(
+        &env,
+        interchain_pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        interchain_pool.counter_party_channel,
+        &packet,
+        timeout,
+        Escrow {
+            sender: info.sender.to_string(),
+            pool_id: msg.pool_id.clone(),
+            funds: vec![],
+        },
+    )?;
+
+    let res = Response::default()
+        .add_submessage(ibc_msg)
+        .add_attribute("pool_id", msg.pool_id)
+        .add_attribute("future_amp", msg.future_amp.to_string())
+        .add_attribute("future_time", msg.future_time.to_string())
+        .add_attribute("action", "ramp_amp");
+    Ok(res)
+}
+
+fn stop_ramp_amp(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: MsgStopRampAmpRequest,
+) -> Result<Response, ContractError> {
+    ensure_admin(deps.storage, &info.sender)?;
+
+    let interchain_pool = POOLS.may_load(deps.storage, &msg.pool_id)?.ok_or_else(|| {
+        ContractError::Std(StdError::generic_err(format!(
+            "Pool doesn't exist {}",
+            msg.pool_id
+        )))
+    })?;
+
+    let now = env.block.time.seconds();
+    let current_amp = match (&interchain_pool.amp_ramp, interchain_pool.curve_at(now)) {
+        (Some(ramp), PoolCurve::Stable { amp }) if ramp.future_time > now => amp,
+        _ => {
+            return Err(ContractError::Std(StdError::generic_err(
+                "No amplification ramp in progress",
+            )))
+        }
+    };
+
+    // A ramp that ends where it starts pins the amplification on both chains
+    let amp_ramp = AmpRamp {
+        initial_amp: current_amp,
+        future_amp: current_amp,
+        initial_time: now,
+        future_time: now,
+    };
+
+    let state_change_data = to_binary(&StateChange {
+        in_tokens: None,
+        out_tokens: None,
+        pool_tokens: None,
+        pool_id: Some(msg.pool_id.clone()),
+        multi_deposit_order_id: None,
+        source_chain_id: None,
+        shares: None,
+        amp_ramp: Some(amp_ramp),
+        protocol_fees: None,
+    })?;
+
+    let packet = InterchainSwapPacketData {
+        r#type: InterchainMessageType::StopRampAmp,
+        data: to_binary(&msg)?,
+        state_change: Some(state_change_data),
+        memo: msg.memo,
+    };
+
+    let timeout = packet_timeout(
+        &env,
+        interchain_pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        interchain_pool.counter_party_channel,
+        &packet,
+        timeout,
+        Escrow {
+            sender: info.sender.to_string(),
+            pool_id: msg.pool_id.clone(),
+            funds: vec![],
+        },
+    )?;
+
+    let res = Response::default()
+        .add_submessage(ibc_msg)
+        .add_attribute("pool_id", msg.pool_id)
+        .add_attribute("amp", current_amp.to_string())
+        .add_attribute("action", "stop_ramp_amp");
+    Ok(res)
+}
+
+/// Sends a pool status change to the counterparty. Each chain applies it once it is
+/// received or acknowledged, so both halves of the pool end up in the same status.
+fn set_pool_status(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: MsgSetPoolStatusRequest,
+) -> Result<Response, ContractError> {
+    let interchain_pool = POOLS.may_load(deps.storage, &msg.pool_id)?.ok_or_else(|| {
+        ContractError::Std(StdError::generic_err(format!(
+            "Pool doesn't exist {}",
+            msg.pool_id
+        )))
     })?;
 
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.admin
+        && info.sender != interchain_pool.source_creator
+        && info.sender != interchain_pool.destination_creator
+    {
+        return Err(ContractError::Unauthorized);
+    }
+    if !interchain_pool.status.can_transition_to(&msg.status) {
+        return Err(ContractError::InvalidStatus);
+    }
+
     let packet = InterchainSwapPacketData {
-        r#type: msg_type,
-        data: swap_data,
-        state_change: Some(state_change_data),
-        memo: msg.memo,
+        r#type: InterchainMessageType::SetPoolStatus,
+        data: to_binary(&msg)?,
+        state_change: None,
+        memo: msg.memo.clone(),
     };
 
-    let ibc_msg = IbcMsg::SendPacket {
-        channel_id: interchain_pool.counter_party_channel,
-        data: to_binary(&packet)?,
-        timeout: IbcTimeout::from(
-            env.block
-                .time
-                .plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET),
-        ),
-    };
+    let timeout = packet_timeout(
+        &env,
+        interchain_pool.counterparty_chain_id(&env.block.chain_id),
+        msg.timeout_height,
+        msg.timeout_timestamp,
+    )?;
+    let ibc_msg = send_packet(
+        deps.storage,
+        &env,
+        interchain_pool.counter_party_channel,
+        &packet,
+        timeout,
+        Escrow {
+            sender: info.sender.to_string(),
+            pool_id: msg.pool_id.clone(),
+            funds: vec![],
+        },
+    )?;
 
     let res = Response::default()
-        .add_message(ibc_msg)
+        .add_submessage(ibc_msg)
         .add_attribute("pool_id", msg.pool_id)
-        .add_attribute("action", "swap");
+        .add_attribute("status", format!("{:?}", msg.status))
+        .add_attribute("action", "set_pool_status");
     Ok(res)
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {} => to_binary(&query_config(deps)?),
-        QueryMsg::InterchainPool { pool_id } => to_binary(&query_interchain_pool(deps, pool_id)?),
+        QueryMsg::Ownership {} => to_binary(&query_ownership(deps)?),
+        QueryMsg::InterchainPool { pool_id } => {
+            to_binary(&query_interchain_pool(deps, env, pool_id)?)
+        }
         QueryMsg::InterchainPoolList { start_after, limit } => {
             to_binary(&query_interchain_pool_list(deps, start_after, limit)?)
         }
+        QueryMsg::InterchainPools(req) => to_binary(&query_interchain_pools(deps, env, req)?),
+        QueryMsg::PoolsByPair {
+            denoms,
+            start_after,
+            limit,
+        } => to_binary(&query_pools_by_pair(deps, denoms, start_after, limit)?),
         QueryMsg::Order { pool_id, order_id } => to_binary(&query_order(deps, pool_id, order_id)?),
         QueryMsg::OrderList { start_after, limit } => {
             to_binary(&query_orders(deps, start_after, limit)?)
         }
+        QueryMsg::OrdersByMaker {
+            source_maker,
+            status,
+            start_after,
+            limit,
+        } => to_binary(&query_orders_by(
+            deps,
+            &multi_asset_deposit_orders().idx.maker,
+            source_maker,
+            status,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::OrdersByTaker {
+            destination_taker,
+            status,
+            start_after,
+            limit,
+        } => to_binary(&query_orders_by(
+            deps,
+            &multi_asset_deposit_orders().idx.taker,
+            destination_taker,
+            status,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::OrdersByPool {
+            pool_id,
+            status,
+            start_after,
+            limit,
+        } => to_binary(&query_orders_by(
+            deps,
+            &multi_asset_deposit_orders().idx.pool,
+            pool_id,
+            status,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::OrdersByStatus {
+            status,
+            start_after,
+            limit,
+        } => to_binary(&query_orders_by_status(deps, status, start_after, limit)?),
         QueryMsg::PoolAddressByToken { pool_id } => to_binary(&query_pool_address(deps, pool_id)?),
         QueryMsg::PoolTokenList { start_after, limit } => {
             to_binary(&query_pool_list(deps, start_after, limit)?)
@@ -1200,12 +2573,12 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             pool_id,
             token_in,
             token_out,
-        } => to_binary(&query_left_swap(deps, pool_id, token_in, token_out)?),
+        } => to_binary(&query_left_swap(deps, env, pool_id, token_in, token_out)?),
         QueryMsg::RightSwap {
             pool_id,
             token_in,
             token_out,
-        } => to_binary(&query_right_swap(deps, pool_id, token_in, token_out)?),
+        } => to_binary(&query_right_swap(deps, env, pool_id, token_in, token_out)?),
         QueryMsg::QueryActiveOrders {
             source_maker,
             destination_taker,
@@ -1216,7 +2589,76 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             source_maker,
             destination_taker,
         )?),
-        QueryMsg::Rate { pool_id, amount } => to_binary(&query_rate(deps, pool_id, amount)?),
+        QueryMsg::Rate { pool_id, amount } => to_binary(&query_rate(deps, env, pool_id, amount)?),
+        QueryMsg::ProtocolFees { pool_id } => to_binary(&query_protocol_fees(deps, pool_id)?),
+        QueryMsg::Twap {
+            pool_id,
+            start,
+            end,
+        } => to_binary(&query_twap(deps, env, pool_id, start, end)?),
+        QueryMsg::LimitOrder { order_id } => {
+            to_binary(&orderbook::query_limit_order(deps, order_id)?)
+        }
+        QueryMsg::LimitOrders {
+            pool_id,
+            denom_in,
+            limit,
+        } => to_binary(&orderbook::query_limit_orders(deps, pool_id, denom_in, limit)?),
+        QueryMsg::TwapOrder { order_id } => {
+            to_binary(&orderbook::query_twap_order(deps, order_id)?)
+        }
+        QueryMsg::PendingPacket {
+            channel_id,
+            sequence,
+        } => to_binary(&ledger::query_pending_packet(deps, channel_id, sequence)?),
+        QueryMsg::PendingPacketsBySender {
+            sender,
+            start_after,
+            limit,
+        } => to_binary(&ledger::query_pending_packets_by_sender(
+            deps,
+            sender,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::PendingPacketsByPool {
+            pool_id,
+            start_after,
+            limit,
+        } => to_binary(&ledger::query_pending_packets_by_pool(
+            deps,
+            pool_id,
+            start_after,
+            limit,
+        )?),
+        QueryMsg::ChainChannel { chain_id } => {
+            to_binary(&channels::query_chain_channel(deps, chain_id)?)
+        }
+        QueryMsg::ChainChannels { start_after, limit } => {
+            to_binary(&channels::query_chain_channels(deps, start_after, limit)?)
+        }
+        QueryMsg::FrozenPools { start_after, limit } => {
+            to_binary(&channels::query_frozen_pools(deps, start_after, limit)?)
+        }
+        QueryMsg::SimulateSwap {
+            pool_id,
+            swap_type,
+            token_in,
+            token_out,
+            slippage,
+        } => to_binary(&simulation::simulate_swap(
+            deps, env, pool_id, swap_type, token_in, token_out, slippage,
+        )?),
+        QueryMsg::SimulateSingleDeposit { pool_id, token } => {
+            to_binary(&simulation::simulate_single_deposit(deps, env, pool_id, token)?)
+        }
+        QueryMsg::SimulateMultiDeposit { pool_id, deposits } => {
+            to_binary(&simulation::simulate_multi_deposit(deps, env, pool_id, deposits)?)
+        }
+        QueryMsg::SimulateWithdraw {
+            pool_token,
+            denom_out,
+        } => to_binary(&simulation::simulate_withdraw(deps, env, pool_token, denom_out)?),
     }
 }
 
@@ -1230,6 +2672,20 @@ fn query_config(deps: Deps) -> StdResult<QueryConfigResponse> {
     Ok(QueryConfigResponse {
         counter: config.counter,
         token_code_id: config.token_code_id,
+        protocol_fee_rate: config.protocol_fee_rate,
+        fee_collector: config.fee_collector,
+        guardian: config.guardian,
+        paused: config.paused,
+        disabled_messages: config.disabled_messages,
+    })
+}
+
+fn query_ownership(deps: Deps) -> StdResult<OwnershipResponse> {
+    let config = CONFIG.load(deps.storage)?;
+
+    Ok(OwnershipResponse {
+        admin: Some(config.admin).filter(|admin| !admin.is_empty()),
+        pending_admin: PENDING_ADMIN.may_load(deps.storage)?,
     })
 }
 
@@ -1240,18 +2696,33 @@ pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, C
     if ver.contract != CONTRACT_NAME {
         return Err(StdError::generic_err("Can only upgrade from same type").into());
     }
-    // note: better to do proper semver compare, but string compare *usually* works
-    if ver.version.as_str() >= CONTRACT_VERSION {
-        return Err(StdError::generic_err("Cannot upgrade from a newer version").into());
+    let stored: Version = ver
+        .version
+        .parse()
+        .map_err(|_| StdError::generic_err(format!("Invalid stored version {}", ver.version)))?;
+    let current: Version = CONTRACT_VERSION.parse().unwrap();
+    if stored > current {
+        return Err(ContractError::MigrationDowngrade {
+            from: ver.version,
+            to: CONTRACT_VERSION.to_string(),
+        });
     }
 
+    // rewrite the state written by older releases
+    let state_version = migrations::migrate_state(deps.storage)?;
+
     // set the new version
     cw2::set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
 
-    Ok(Response::default())
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("from_version", ver.version)
+        .add_attribute("to_version", CONTRACT_VERSION)
+        .add_attribute("from_state_version", state_version.to_string())
+        .add_attribute("to_state_version", CURRENT_STATE_VERSION.to_string()))
 }
 
-fn query_interchain_pool(deps: Deps, pool_id: String) -> StdResult<InterchainPoolResponse> {
+fn query_interchain_pool(deps: Deps, env: Env, pool_id: String) -> StdResult<InterchainPoolResponse> {
     // load pool throw error if found
     let interchain_pool_temp = POOLS.may_load(deps.storage, &pool_id)?;
     let interchain_pool;
@@ -1260,6 +2731,7 @@ fn query_interchain_pool(deps: Deps, pool_id: String) -> StdResult<InterchainPoo
     } else {
         return Err(StdError::generic_err("Pool not found".to_string()));
     }
+    let curve = interchain_pool.curve_at(env.block.time.seconds());
 
     Ok(InterchainPoolResponse {
         id: interchain_pool.id,
@@ -1273,6 +2745,9 @@ fn query_interchain_pool(deps: Deps, pool_id: String) -> StdResult<InterchainPoo
         counter_party_port: interchain_pool.counter_party_port,
         source_chain_id: interchain_pool.source_chain_id,
         destination_chain_id: interchain_pool.destination_chain_id,
+        curve,
+        amp_ramp: interchain_pool.amp_ramp,
+        dynamic_fee: interchain_pool.dynamic_fee,
     })
 }
 
@@ -1296,9 +2771,110 @@ fn query_interchain_pool_list(
     Ok(InterchainListResponse { pools: list })
 }
 
+fn query_pools_by_pair(
+    deps: Deps,
+    denoms: Vec<String>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<InterchainListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.as_deref().map(Bound::exclusive);
+    let pools = POOLS_BY_PAIR
+        .prefix(&get_pair_key(denoms))
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|pool_id| POOLS.load(deps.storage, &pool_id?))
+        .collect::<StdResult<_>>()?;
+
+    Ok(InterchainListResponse { pools })
+}
+
+fn pool_matches(
+    pool: &InterchainLiquidityPool,
+    req: &QueryAllInterchainLiquidityPoolRequest,
+    chain_id: &str,
+) -> bool {
+    if let Some(denom) = &req.denom {
+        if !pool.assets.iter().any(|asset| asset.balance.denom == *denom) {
+            return false;
+        }
+    }
+    if let Some(chain) = &req.counterparty_chain_id {
+        if pool.counterparty_chain_id(chain_id) != chain {
+            return false;
+        }
+    }
+    if let Some(status) = &req.status {
+        if pool.status != *status {
+            return false;
+        }
+    }
+    if let Some(creator) = &req.creator {
+        if pool.source_creator != *creator && pool.destination_creator != *creator {
+            return false;
+        }
+    }
+    true
+}
+
+/// Filters need every pool to be looked at, so matching pools are loaded and sorted before
+/// the page is cut. `pagination.key` is the id of the first pool of a page, as returned in
+/// `next_key`, and takes precedence over `offset`.
+fn query_interchain_pools(
+    deps: Deps,
+    env: Env,
+    req: QueryAllInterchainLiquidityPoolRequest,
+) -> StdResult<QueryAllInterchainLiquidityPoolResponse> {
+    let mut pools = vec![];
+    for item in POOLS.range(deps.storage, None, None, Order::Ascending) {
+        let (_, pool) = item?;
+        if pool_matches(&pool, &req, &env.block.chain_id) {
+            pools.push(pool);
+        }
+    }
+    if req.order_by == PoolOrderBy::Liquidity {
+        let mut ranked = pools
+            .into_iter()
+            .map(|pool| Ok((pool.liquidity()?, pool)))
+            .collect::<StdResult<Vec<_>>>()?;
+        // stable, so pools with equal liquidity stay ordered by id
+        ranked.sort_by_key(|(liquidity, _)| *liquidity);
+        pools = ranked.into_iter().map(|(_, pool)| pool).collect();
+    }
+
+    let page = req.pagination;
+    if page.reverse {
+        pools.reverse();
+    }
+    let total = if page.count_total { pools.len() as u64 } else { 0 };
+    let start = if page.key.is_empty() {
+        page.offset as usize
+    } else {
+        pools
+            .iter()
+            .position(|pool| pool.id.as_bytes() == page.key.as_slice())
+            .ok_or_else(|| StdError::generic_err("Pagination key does not match a pool"))?
+    };
+    let limit = match page.limit {
+        0 => DEFAULT_LIMIT as usize,
+        limit => limit.min(MAX_LIMIT as u64) as usize,
+    };
+
+    let mut pools: Vec<_> = pools.into_iter().skip(start).take(limit + 1).collect();
+    let next_key = if pools.len() > limit {
+        pools.pop().unwrap().id.into_bytes()
+    } else {
+        vec![]
+    };
+    Ok(QueryAllInterchainLiquidityPoolResponse {
+        interchain_liquidity_pool: pools,
+        pagination: PageResponse { next_key, total },
+    })
+}
+
 fn query_order(deps: Deps, pool_id: String, order_id: String) -> StdResult<MultiAssetDepositOrder> {
     let key = pool_id + "-" + &order_id;
-    let multi_asset_order_temp = MULTI_ASSET_DEPOSIT_ORDERS.may_load(deps.storage, key)?;
+    let multi_asset_order_temp = multi_asset_deposit_orders().may_load(deps.storage, key)?;
     let multi_asset_order;
     if let Some(order) = multi_asset_order_temp {
         multi_asset_order = order;
@@ -1316,7 +2892,7 @@ fn query_orders(
 ) -> StdResult<OrderListResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let start = start_after.map(|denom| Bound::ExclusiveRaw(denom.into_bytes()));
-    let list = MULTI_ASSET_DEPOSIT_ORDERS
+    let list = multi_asset_deposit_orders()
         .range(deps.storage, start, None, Order::Ascending)
         .take(limit)
         .map(
@@ -1329,6 +2905,63 @@ fn query_orders(
     Ok(OrderListResponse { orders: list })
 }
 
+/// Pages through the orders of one maker, taker or pool. Without a status, orders are listed
+/// grouped by status and `start_after` resumes from the status its order has now.
+fn query_orders_by(
+    deps: Deps,
+    index: &MultiIndex<(String, u8), MultiAssetDepositOrder, String>,
+    owner: String,
+    status: Option<OrderStatus>,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<OrderListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let orders = match status {
+        Some(status) => index
+            .prefix((owner, status as u8))
+            .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+            .take(limit)
+            .map(|item| item.map(|(_, order)| order))
+            .collect::<StdResult<_>>()?,
+        None => {
+            let start = match start_after {
+                Some(key) => {
+                    let order = multi_asset_deposit_orders().load(deps.storage, key.clone())?;
+                    Some(Bound::exclusive((order.status as u8, key)))
+                }
+                None => None,
+            };
+            index
+                .sub_prefix(owner)
+                .range(deps.storage, start, None, Order::Ascending)
+                .take(limit)
+                .map(|item| item.map(|(_, order)| order))
+                .collect::<StdResult<_>>()?
+        }
+    };
+
+    Ok(OrderListResponse { orders })
+}
+
+fn query_orders_by_status(
+    deps: Deps,
+    status: OrderStatus,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<OrderListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let orders = multi_asset_deposit_orders()
+        .idx
+        .status
+        .prefix(status as u8)
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, order)| order))
+        .collect::<StdResult<_>>()?;
+
+    Ok(OrderListResponse { orders })
+}
+
 fn query_pool_address(deps: Deps, pool_id: String) -> StdResult<String> {
     let res;
     if let Some(lp_token) = POOL_TOKENS_LIST.may_load(deps.storage, &pool_id)? {
@@ -1362,10 +2995,11 @@ fn query_pool_list(
 
 fn query_left_swap(
     deps: Deps,
+    env: Env,
     pool_id: String,
     token_in: Coin,
     token_out: Coin,
-) -> StdResult<Coin> {
+) -> StdResult<QuerySwapResponse> {
     // Get liquidity pool
     // load pool throw error if not found
     let interchain_pool_temp = POOLS.may_load(deps.storage, &pool_id)?;
@@ -1389,19 +3023,30 @@ fn query_left_swap(
     // Create the interchain market maker
     let amm = InterchainMarketMaker {
         pool_id: interchain_pool.clone().id,
-        pool: interchain_pool.clone(),
+        pool: interchain_pool.at_time(env.block.time.seconds()),
         fee_rate: interchain_pool.swap_fee,
     };
-    let result = amm.compute_swap(token_in, &token_out.denom)?;
-    Ok(result)
+    let fee_rate = amm.left_swap_fee_rate(&token_in, &token_out.denom)?;
+    let result = amm
+        .with_fee_rate(fee_rate)
+        .compute_swap(token_in.clone(), &token_out.denom)?;
+    Ok(QuerySwapResponse {
+        amount: result,
+        fee: Coin {
+            denom: token_in.denom,
+            amount: token_in.amount.multiply_ratio(fee_rate, FEE_PRECISION),
+        },
+        fee_rate,
+    })
 }
 
 fn query_right_swap(
     deps: Deps,
+    env: Env,
     pool_id: String,
     token_in: Coin,
     token_out: Coin,
-) -> StdResult<Coin> {
+) -> StdResult<QuerySwapResponse> {
     // Get liquidity pool
     // load pool throw error if not found
     let interchain_pool_temp = POOLS.may_load(deps.storage, &pool_id)?;
@@ -1425,11 +3070,21 @@ fn query_right_swap(
     // Create the interchain market maker
     let amm = InterchainMarketMaker {
         pool_id: interchain_pool.clone().id,
-        pool: interchain_pool.clone(),
+        pool: interchain_pool.at_time(env.block.time.seconds()),
         fee_rate: interchain_pool.swap_fee,
     };
-    let result = amm.compute_offer_amount(token_in, token_out)?;
-    Ok(result)
+    let fee_rate = amm.right_swap_fee_rate(&token_in.denom, &token_out)?;
+    let result = amm
+        .with_fee_rate(fee_rate)
+        .compute_offer_amount(token_in, token_out)?;
+    Ok(QuerySwapResponse {
+        fee: Coin {
+            denom: result.denom.clone(),
+            amount: result.amount.multiply_ratio(fee_rate, FEE_PRECISION),
+        },
+        amount: result,
+        fee_rate,
+    })
 }
 
 fn query_active_orders(
@@ -1450,7 +3105,7 @@ fn query_active_orders(
     Ok(multi_asset_order)
 }
 
-fn query_rate(deps: Deps, pool_id: String, amount: Uint128) -> StdResult<Vec<Coin>> {
+fn query_rate(deps: Deps, env: Env, pool_id: String, amount: Uint128) -> StdResult<Vec<Coin>> {
     // Get liquidity pool
     // load pool throw error if not found
     let interchain_pool_temp = POOLS.may_load(deps.storage, &pool_id)?;
@@ -1467,7 +3122,7 @@ fn query_rate(deps: Deps, pool_id: String, amount: Uint128) -> StdResult<Vec<Coi
     // Create the interchain market maker
     let amm = InterchainMarketMaker {
         pool_id: interchain_pool.clone().id,
-        pool: interchain_pool.clone(),
+        pool: interchain_pool.at_time(env.block.time.seconds()),
         fee_rate: interchain_pool.swap_fee,
     };
 
@@ -1477,19 +3132,827 @@ fn query_rate(deps: Deps, pool_id: String, amount: Uint128) -> StdResult<Vec<Coi
     })
 }
 
+fn query_protocol_fees(deps: Deps, pool_id: String) -> StdResult<Vec<Coin>> {
+    PROTOCOL_FEES
+        .prefix(&pool_id)
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+        .collect()
+}
+
+fn query_twap(
+    deps: Deps,
+    env: Env,
+    pool_id: String,
+    start: u64,
+    end: u64,
+) -> StdResult<TwapResponse> {
+    let interchain_pool = POOLS
+        .may_load(deps.storage, &pool_id)?
+        .ok_or_else(|| StdError::generic_err(format!("Pool doesn't exist {}", pool_id)))?;
+
+    oracle::query_twap(
+        deps.storage,
+        &interchain_pool,
+        start,
+        end,
+        env.block.time.seconds(),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing::{mock_pair_pool, mock_pool};
     use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
 
+    /// Instantiates with "admin" as admin and "guardian" as guardian.
+    fn instantiate_contract(deps: DepsMut, protocol_fee_rate: Option<u32>) {
+        let instantiate_msg = InstantiateMsg {
+            token_code_id: 1,
+            router: "".to_string(),
+            protocol_fee_rate,
+            fee_collector: None,
+            guardian: Some("guardian".to_string()),
+        };
+        instantiate(deps, mock_env(), mock_info("admin", &[]), instantiate_msg).unwrap();
+    }
+
     #[test]
     fn test_instantiate() {
         let mut deps = mock_dependencies();
 
         // Instantiate an empty contract
-        let instantiate_msg = InstantiateMsg { token_code_id: 1, router: "".to_string() };
+        let instantiate_msg = InstantiateMsg {
+            token_code_id: 1,
+            router: "".to_string(),
+            protocol_fee_rate: None,
+            fee_collector: None,
+            guardian: None,
+        };
         let info = mock_info("anyone", &[]);
         let res = instantiate(deps.as_mut(), mock_env(), info, instantiate_msg).unwrap();
         assert_eq!(0, res.messages.len());
     }
+
+    #[test]
+    fn test_packet_timeout() {
+        let env = mock_env();
+        let now = env.block.time;
+
+        assert_eq!(
+            packet_timeout(&env, "side-1", 0, 0).unwrap(),
+            IbcTimeout::with_timestamp(now.plus_seconds(DEFAULT_TIMEOUT_TIMESTAMP_OFFSET))
+        );
+        let timestamp = now.plus_seconds(60);
+        assert_eq!(
+            packet_timeout(&env, "side-1", 0, timestamp.nanos()).unwrap(),
+            IbcTimeout::with_timestamp(timestamp)
+        );
+        // Heights are on the counterparty's revision, bounded by the horizon
+        assert_eq!(
+            packet_timeout(&env, "osmosis-4", 500, 0).unwrap(),
+            IbcTimeout::with_both(
+                IbcTimeoutBlock {
+                    revision: 4,
+                    height: 500
+                },
+                now.plus_seconds(MAX_TIMEOUT_TIMESTAMP_OFFSET)
+            )
+        );
+        assert_eq!(
+            packet_timeout(&env, "testchain", 500, timestamp.nanos()).unwrap(),
+            IbcTimeout::with_both(
+                IbcTimeoutBlock {
+                    revision: 0,
+                    height: 500
+                },
+                timestamp
+            )
+        );
+
+        assert!(packet_timeout(&env, "side-1", 0, now.nanos()).is_err());
+        let too_late = now.plus_seconds(MAX_TIMEOUT_TIMESTAMP_OFFSET + 1);
+        assert!(packet_timeout(&env, "side-1", 0, too_late.nanos()).is_err());
+    }
+
+    #[test]
+    fn test_channel_close_freezes_pools() {
+        use cosmwasm_std::{coin, BankMsg, CosmosMsg};
+
+        let mut deps = mock_dependencies();
+        let pool = |id: &str, channel: &str, status| InterchainLiquidityPool {
+            counter_party_channel: channel.to_string(),
+            status,
+            ..mock_pair_pool(id, 1_000_000)
+        };
+        let storage = deps.as_mut().storage;
+        for (pool_id, channel) in [("pool", "channel-0"), ("other", "channel-1")] {
+            POOLS.save(storage, pool_id, &pool(pool_id, channel, PoolStatus::Active)).unwrap();
+            POOLS_BY_CHANNEL.save(storage, (channel, pool_id), &Empty {}).unwrap();
+        }
+        POOL_TOKENS_LIST.save(storage, "pool", &"lp".to_string()).unwrap();
+
+        let frozen = channels::close_channel(storage, "channel-0", 100).unwrap();
+        assert_eq!(frozen, vec!["pool".to_string()]);
+        let frozen = channels::query_frozen_pools(deps.as_ref(), None, None).unwrap();
+        assert_eq!(frozen.pools.len(), 1);
+        assert_eq!(frozen.pools[0].reason, "channel channel-0 closed");
+        let other = POOLS.load(deps.as_ref().storage, "other").unwrap();
+        assert_eq!(other.status, PoolStatus::Active);
+
+        // A quarter of the pool tokens redeem a quarter of the local side only
+        let res = withdraw_frozen(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("lp_holder", &[]),
+            "pool".to_string(),
+            Uint128::new(500_000),
+        )
+        .unwrap();
+        assert_eq!(
+            res.messages[2].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "lp_holder".to_string(),
+                amount: vec![coin(250_000, "aside")],
+            })
+        );
+        let pool = POOLS.load(deps.as_ref().storage, "pool").unwrap();
+        assert_eq!(pool.supply.amount, Uint128::new(1_500_000));
+        assert_eq!(pool.assets[0].balance.amount, Uint128::new(750_000));
+        assert_eq!(pool.assets[1].balance.amount, Uint128::new(1_000_000));
+    }
+
+    #[test]
+    fn test_frozen_pool_refunds_pending_orders() {
+        use crate::msg::DepositAsset;
+        use cosmwasm_std::{coin, BankMsg, CosmosMsg};
+
+        let mut deps = mock_dependencies();
+        instantiate_contract(deps.as_mut(), None);
+        let storage = deps.as_mut().storage;
+        POOLS.save(storage, "pool", &mock_pair_pool("pool", 1_000_000)).unwrap();
+        POOLS_BY_CHANNEL.save(storage, ("channel-0", "pool"), &Empty {}).unwrap();
+        let make = |deps: DepsMut, maker: &str| {
+            let deposit = |sender: &str, balance| DepositAsset {
+                sender: sender.to_string(),
+                balance,
+            };
+            let msg = MsgMakeMultiAssetDepositRequest {
+                pool_id: "pool".to_string(),
+                deposits: vec![
+                    deposit(maker, coin(10, "aside")),
+                    deposit("taker", coin(10, "bside")),
+                ],
+                chain_id: "chain-a".to_string(),
+                timeout_height: 0,
+                timeout_timestamp: 0,
+                memo: None,
+                expires_at: None,
+            };
+            let info = mock_info(maker, &[coin(10, "aside")]);
+            make_multi_asset_deposit(deps, mock_env(), info, msg).unwrap();
+        };
+        make(deps.as_mut(), "maker");
+        make(deps.as_mut(), "late_maker");
+        let order_of = |deps: Deps, maker: &str| {
+            multi_asset_deposit_orders()
+                .range(deps.storage, None, None, Order::Ascending)
+                .map(|item| item.unwrap().1)
+                .find(|order| order.source_maker == maker)
+                .unwrap()
+        };
+        let cancel = |deps: DepsMut, order: &MultiAssetDepositOrder| {
+            let msg = MsgCancelMultiAssetDepositRequest {
+                sender: order.source_maker.clone(),
+                pool_id: "pool".to_string(),
+                order_id: order.id.clone(),
+                timeout_height: 0,
+                timeout_timestamp: 0,
+                memo: None,
+            };
+            let info = mock_info(&order.source_maker, &[]);
+            cancel_multi_asset_deposit(deps, mock_env(), info, msg)
+        };
+        let refund = |to: &str| {
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: to.to_string(),
+                amount: vec![coin(10, "aside")],
+            })
+        };
+
+        let frozen = channels::close_channel(deps.as_mut().storage, "channel-0", 100).unwrap();
+        assert_eq!(frozen, vec!["pool".to_string()]);
+
+        // The maker gets the escrow back without a packet, once
+        let order = order_of(deps.as_ref(), "maker");
+        let res = cancel(deps.as_mut(), &order).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].msg, refund("maker"));
+        let order = order_of(deps.as_ref(), "maker");
+        assert_eq!(order.status, OrderStatus::Cancelled);
+        assert!(!ACTIVE_ORDERS.has(deps.as_ref().storage, "maker-pool-taker".to_string()));
+        let err = cancel(deps.as_mut(), &order).unwrap_err();
+        assert_eq!(err, ContractError::ErrOrderAlreadyCompleted);
+
+        // The sweep refunds expired orders of the frozen pool the same way
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(MULTI_DEPOSIT_DEFAULT_EXPIRY);
+        let res = sweep_expired_orders(deps.as_mut(), later.clone(), None).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].msg, refund("late_maker"));
+        let res = sweep_expired_orders(deps.as_mut(), later, None).unwrap();
+        assert!(res.messages.is_empty());
+
+        // A copy of an order made on the other chain holds no escrow here
+        let mut remote = order_of(deps.as_ref(), "maker");
+        remote.id = "remote".to_string();
+        remote.status = OrderStatus::Pending;
+        let key = "pool-remote".to_string();
+        multi_asset_deposit_orders().save(deps.as_mut().storage, key, &remote).unwrap();
+        assert_eq!(cancel(deps.as_mut(), &remote).unwrap_err(), ContractError::InvalidChain);
+    }
+
+    #[test]
+    fn test_two_step_ownership() {
+        let mut deps = mock_dependencies();
+        let instantiate_msg = InstantiateMsg {
+            token_code_id: 1,
+            router: "".to_string(),
+            protocol_fee_rate: None,
+            fee_collector: None,
+            guardian: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), instantiate_msg).unwrap();
+        let execute_as = |deps: DepsMut, sender: &str, msg: ExecuteMsg| {
+            execute(deps, mock_env(), mock_info(sender, &[]), msg)
+        };
+        let ownership = |deps: Deps| query_ownership(deps).unwrap();
+
+        let transfer = ExecuteMsg::TransferOwnership {
+            new_admin: "new_admin".to_string(),
+        };
+        let err = execute_as(deps.as_mut(), "new_admin", transfer.clone()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        execute_as(deps.as_mut(), "admin", transfer).unwrap();
+        assert_eq!(
+            ownership(deps.as_ref()),
+            OwnershipResponse {
+                admin: Some("admin".to_string()),
+                pending_admin: Some("new_admin".to_string()),
+            }
+        );
+
+        let err = execute_as(deps.as_mut(), "other", ExecuteMsg::AcceptOwnership {}).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        execute_as(deps.as_mut(), "new_admin", ExecuteMsg::AcceptOwnership {}).unwrap();
+        let set_router = ExecuteMsg::SetRouter {
+            address: "router".to_string(),
+        };
+        let err = execute_as(deps.as_mut(), "admin", set_router.clone()).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        execute_as(deps.as_mut(), "new_admin", set_router.clone()).unwrap();
+
+        // Renouncing while paused would leave nobody able to resume the contract
+        execute_as(deps.as_mut(), "new_admin", ExecuteMsg::Pause {}).unwrap();
+        let err = execute_as(deps.as_mut(), "new_admin", ExecuteMsg::RenounceOwnership {});
+        assert_eq!(err.unwrap_err(), ContractError::Paused);
+        let err = execute_as(deps.as_mut(), "admin", ExecuteMsg::Unpause {}).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        execute_as(deps.as_mut(), "new_admin", ExecuteMsg::Unpause {}).unwrap();
+
+        execute_as(deps.as_mut(), "new_admin", ExecuteMsg::RenounceOwnership {}).unwrap();
+        assert_eq!(
+            ownership(deps.as_ref()),
+            OwnershipResponse {
+                admin: None,
+                pending_admin: None,
+            }
+        );
+        let err = execute_as(deps.as_mut(), "new_admin", set_router).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+
+        // Without an admin the guardian, the instantiator here, can still resume
+        execute_as(deps.as_mut(), "admin", ExecuteMsg::Pause {}).unwrap();
+        let err = execute_as(deps.as_mut(), "new_admin", ExecuteMsg::Unpause {}).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        execute_as(deps.as_mut(), "admin", ExecuteMsg::Unpause {}).unwrap();
+        assert!(!CONFIG.load(deps.as_ref().storage).unwrap().paused);
+    }
+
+    #[test]
+    fn test_sweep_expired_orders() {
+        use crate::ibc::ibc_packet_timeout;
+        use crate::msg::{DepositAsset, LPAllocation};
+        use crate::state::in_flight_packets;
+        use cosmwasm_std::{coin, Event, IbcEndpoint, IbcMsg, IbcPacket, IbcPacketTimeoutMsg};
+        use cosmwasm_std::{Reply, SubMsgResponse, SubMsgResult};
+
+        let mut deps = mock_dependencies();
+        let instantiate_msg = InstantiateMsg {
+            token_code_id: 1,
+            router: "".to_string(),
+            protocol_fee_rate: None,
+            fee_collector: None,
+            guardian: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), instantiate_msg).unwrap();
+        let pool = mock_pair_pool("pool", 1_000_000);
+        POOLS.save(deps.as_mut().storage, "pool", &pool).unwrap();
+
+        let make = MsgMakeMultiAssetDepositRequest {
+            pool_id: "pool".to_string(),
+            deposits: vec![
+                DepositAsset {
+                    sender: "maker".to_string(),
+                    balance: coin(10, "aside"),
+                },
+                DepositAsset {
+                    sender: "taker".to_string(),
+                    balance: coin(10, "bside"),
+                },
+            ],
+            chain_id: "a".to_string(),
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+            expires_at: None,
+        };
+        let info = mock_info("maker", &[coin(10, "aside")]);
+        make_multi_asset_deposit(deps.as_mut(), mock_env(), info, make).unwrap();
+        let (_, order) = multi_asset_deposit_orders()
+            .range(deps.as_ref().storage, None, None, Order::Ascending)
+            .next()
+            .unwrap()
+            .unwrap();
+        let now = mock_env().block.time.seconds();
+        assert_eq!(order.expires_at, now + MULTI_DEPOSIT_DEFAULT_EXPIRY);
+
+        // Nothing has expired yet
+        let res = sweep_expired_orders(deps.as_mut(), mock_env(), None).unwrap();
+        assert!(res.messages.is_empty());
+
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(MULTI_DEPOSIT_DEFAULT_EXPIRY);
+        let take = MsgTakeMultiAssetDepositRequest {
+            sender: "taker".to_string(),
+            pool_id: "pool".to_string(),
+            order_id: order.id.clone(),
+            lp_allocation: LPAllocation::MakerChain,
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+        };
+        let info = mock_info("taker", &[coin(10, "aside")]);
+        let err = take_multi_asset_deposit(deps.as_mut(), later.clone(), info, take).unwrap_err();
+        assert_eq!(err, ContractError::OrderExpired { order_id: order.id });
+
+        // An older order of a paused pool is set aside without holding up the queue
+        let paused = InterchainLiquidityPool {
+            id: "paused".to_string(),
+            status: PoolStatus::Paused,
+            ..pool
+        };
+        POOLS.save(deps.as_mut().storage, "paused", &paused).unwrap();
+        let waiting = MultiAssetDepositOrder {
+            id: "1".to_string(),
+            pool_id: "paused".to_string(),
+            expires_at: now,
+            ..order
+        };
+        let waiting_key = (now, "paused-1".to_string());
+        let storage = deps.as_mut().storage;
+        multi_asset_deposit_orders().save(storage, "paused-1".to_string(), &waiting).unwrap();
+        ORDER_EXPIRIES.save(storage, waiting_key.clone(), &Empty {}).unwrap();
+
+        let res = sweep_expired_orders(deps.as_mut(), later.clone(), Some(1)).unwrap();
+        assert!(res.messages.is_empty());
+        assert!(!ORDER_EXPIRIES.has(deps.as_ref().storage, waiting_key.clone()));
+        let deferred = DEFERRED_EXPIRIES.load(deps.as_ref().storage, ("paused", "paused-1"));
+        assert_eq!(deferred.unwrap(), now);
+
+        // The expired order is cancelled once, its maker escrows the cancel packet
+        let res = sweep_expired_orders(deps.as_mut(), later.clone(), Some(1)).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        let res_again = sweep_expired_orders(deps.as_mut(), later.clone(), None).unwrap();
+        assert!(res_again.messages.is_empty());
+
+        // A cancel that times out is not held for a retry, its order goes back on the queue
+        let sent = |deps: DepsMut, sequence: u64| {
+            let event =
+                Event::new("send_packet").add_attribute("packet_sequence", sequence.to_string());
+            let result = SubMsgResult::Ok(SubMsgResponse {
+                events: vec![event],
+                data: None,
+            });
+            let msg = Reply {
+                id: PACKET_SENT_REPLY_ID,
+                result,
+            };
+            reply(deps, mock_env(), msg).unwrap();
+        };
+        sent(deps.as_mut(), 1);
+        sent(deps.as_mut(), 2);
+        let data = match &res.messages[0].msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket { data, .. }) => data.clone(),
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        let endpoint = IbcEndpoint {
+            port_id: "port".to_string(),
+            channel_id: "channel-0".to_string(),
+        };
+        let timeout = IbcTimeout::with_timestamp(later.block.time);
+        let packet = IbcPacket::new(data, endpoint.clone(), endpoint, 2, timeout);
+        let msg = IbcPacketTimeoutMsg::new(packet);
+        ibc_packet_timeout(deps.as_mut(), later.clone(), msg).unwrap();
+        let key = ("channel-0".to_string(), 2);
+        assert!(!in_flight_packets().has(deps.as_ref().storage, key));
+        let res = sweep_expired_orders(deps.as_mut(), later.clone(), None).unwrap();
+        assert_eq!(res.messages.len(), 1);
+
+        // Once its pool is active again the order set aside is cancelled as well
+        let mut active = POOLS.load(deps.as_ref().storage, "paused").unwrap();
+        active.status = PoolStatus::Active;
+        POOLS.save(deps.as_mut().storage, "paused", &active).unwrap();
+        resume_deferred_orders(deps.as_mut().storage, "paused").unwrap();
+        let res = sweep_expired_orders(deps.as_mut(), later.clone(), Some(1)).unwrap();
+        assert!(res.messages.is_empty());
+        assert!(ORDER_EXPIRIES.has(deps.as_ref().storage, waiting_key));
+        let res = sweep_expired_orders(deps.as_mut(), later, Some(1)).unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert!(RESUMED_POOLS.is_empty(deps.as_ref().storage).unwrap());
+    }
+
+    #[test]
+    fn test_query_orders_by_index() {
+        use cosmwasm_std::coin;
+
+        let mut deps = mock_dependencies();
+        let order = |id: &str, maker: &str, status| MultiAssetDepositOrder {
+            id: id.to_string(),
+            pool_id: "pool".to_string(),
+            chain_id: "a".to_string(),
+            source_maker: maker.to_string(),
+            destination_taker: "taker".to_string(),
+            deposits: vec![coin(10, "aside"), coin(10, "bside")],
+            status,
+            created_at: 0,
+            expires_at: 0,
+        };
+        let orders = [
+            order("1", "maker", OrderStatus::Pending),
+            order("2", "maker", OrderStatus::Complete),
+            order("3", "other", OrderStatus::Pending),
+            order("4", "maker", OrderStatus::Pending),
+        ];
+        for order in &orders {
+            let key = format!("pool-{}", order.id);
+            multi_asset_deposit_orders().save(deps.as_mut().storage, key, order).unwrap();
+        }
+        let ids = |res: OrderListResponse| {
+            res.orders.into_iter().map(|order| order.id).collect::<Vec<_>>()
+        };
+        let by_maker = |deps: Deps, status, start_after: Option<&str>| {
+            let index = &multi_asset_deposit_orders().idx.maker;
+            let start_after = start_after.map(|key| key.to_string());
+            query_orders_by(deps, index, "maker".to_string(), status, start_after, Some(2))
+        };
+
+        let pending = by_maker(deps.as_ref(), Some(OrderStatus::Pending), None).unwrap();
+        assert_eq!(ids(pending), vec!["1", "4"]);
+        // Pages run across statuses when none is given
+        let page = by_maker(deps.as_ref(), None, None).unwrap();
+        assert_eq!(ids(page), vec!["1", "4"]);
+        let page = by_maker(deps.as_ref(), None, Some("pool-4")).unwrap();
+        assert_eq!(ids(page), vec!["2"]);
+
+        // Indexes follow status changes
+        let mut taken = orders[0].clone();
+        taken.status = OrderStatus::Complete;
+        let storage = deps.as_mut().storage;
+        multi_asset_deposit_orders().save(storage, "pool-1".to_string(), &taken).unwrap();
+        let complete =
+            query_orders_by_status(deps.as_ref(), OrderStatus::Complete, None, None).unwrap();
+        assert_eq!(ids(complete), vec!["1", "2"]);
+        let index = &multi_asset_deposit_orders().idx.pool;
+        let pool = "pool".to_string();
+        let pending =
+            query_orders_by(deps.as_ref(), index, pool, Some(OrderStatus::Pending), None, None)
+                .unwrap();
+        assert_eq!(ids(pending), vec!["3", "4"]);
+    }
+
+    #[test]
+    fn test_query_interchain_pools() {
+        use crate::msg::PageRequest;
+
+        let mut deps = mock_dependencies();
+        let pool = |id: &str, denom: &str, amount: u128, chain: &str| InterchainLiquidityPool {
+            destination_chain_id: chain.to_string(),
+            source_chain_id: mock_env().block.chain_id,
+            ..mock_pool(
+                id,
+                &[
+                    (PoolSide::SOURCE, denom, amount, 50),
+                    (PoolSide::DESTINATION, "bside", 1_000_000, 50),
+                ],
+            )
+        };
+        let storage = deps.as_mut().storage;
+        POOLS.save(storage, "pool1", &pool("pool1", "atom", 5_000_000, "b")).unwrap();
+        POOLS.save(storage, "pool2", &pool("pool2", "osmo", 1_000_000, "b")).unwrap();
+        POOLS.save(storage, "pool3", &pool("pool3", "atom", 3_000_000, "b")).unwrap();
+        POOLS.save(storage, "pool4", &pool("pool4", "atom", 9_000_000, "c")).unwrap();
+
+        let request = |key: &[u8], order_by| QueryAllInterchainLiquidityPoolRequest {
+            pagination: PageRequest {
+                key: key.to_vec(),
+                offset: 0,
+                limit: 1,
+                count_total: true,
+                reverse: true,
+            },
+            denom: Some("atom".to_string()),
+            counterparty_chain_id: Some("b".to_string()),
+            status: None,
+            creator: Some("taker".to_string()),
+            order_by,
+        };
+        let ids = |res: &QueryAllInterchainLiquidityPoolResponse| {
+            res.interchain_liquidity_pool.iter().map(|pool| pool.id.clone()).collect::<Vec<_>>()
+        };
+
+        // Largest atom pool shared with chain b first
+        let req = request(&[], PoolOrderBy::Liquidity);
+        let res = query_interchain_pools(deps.as_ref(), mock_env(), req).unwrap();
+        assert_eq!(ids(&res), vec!["pool1"]);
+        assert_eq!(res.pagination.total, 2);
+        assert_eq!(res.pagination.next_key, b"pool3".to_vec());
+        let req = request(&res.pagination.next_key, PoolOrderBy::Liquidity);
+        let res = query_interchain_pools(deps.as_ref(), mock_env(), req).unwrap();
+        assert_eq!(ids(&res), vec!["pool3"]);
+        assert!(res.pagination.next_key.is_empty());
+
+        let req = request(&[], PoolOrderBy::Id);
+        let res = query_interchain_pools(deps.as_ref(), mock_env(), req).unwrap();
+        assert_eq!(ids(&res), vec!["pool3"]);
+    }
+
+    #[test]
+    fn test_pool_ids_by_weights_and_fee() {
+        use crate::market::PoolAsset;
+        use cosmwasm_std::coin;
+
+        let asset = |side, denom: &str, weight| PoolAsset {
+            side,
+            balance: coin(1_000_000, denom),
+            weight,
+            decimal: 6,
+        };
+        let assets = |atom_weight| {
+            vec![
+                asset(PoolSide::SOURCE, "atom", atom_weight),
+                asset(PoolSide::DESTINATION, "osmo", 100 - atom_weight),
+            ]
+        };
+        let id = |assets: &[PoolAsset], fee, curve: &PoolCurve| {
+            get_pool_id(assets, fee, curve, "a".to_string(), "b".to_string())
+        };
+
+        let even = id(&assets(50), 30, &PoolCurve::Weighted);
+        // The counterparty sees the assets and chains the other way round
+        let mut flipped = assets(50);
+        flipped.reverse();
+        assert_eq!(
+            get_pool_id(&flipped, 30, &PoolCurve::Weighted, "b".to_string(), "a".to_string()),
+            even
+        );
+        let skewed = id(&assets(80), 30, &PoolCurve::Weighted);
+        let other_fee = id(&assets(50), 100, &PoolCurve::Weighted);
+        let stable = id(&assets(50), 30, &PoolCurve::Stable { amp: 100 });
+        assert_ne!(even, skewed);
+        assert_ne!(even, other_fee);
+        assert_ne!(even, stable);
+
+        let mut deps = mock_dependencies();
+        for (pool_id, weight) in [(&even, 50), (&skewed, 80)] {
+            let pool = InterchainLiquidityPool {
+                assets: assets(weight),
+                supply: coin(0, pool_id),
+                ..mock_pool(pool_id, &[])
+            };
+            let storage = deps.as_mut().storage;
+            POOLS.save(storage, pool_id, &pool).unwrap();
+            POOLS_BY_PAIR.save(storage, (&pool.pair_key(), pool_id), &Empty {}).unwrap();
+        }
+        let denoms = vec!["osmo".to_string(), "atom".to_string()];
+        let res = query_pools_by_pair(deps.as_ref(), denoms, None, None).unwrap();
+        let mut expected = vec![even, skewed];
+        expected.sort();
+        assert_eq!(res.pools.into_iter().map(|pool| pool.id).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_ramp_amp_bounds() {
+        let mut deps = mock_dependencies();
+        instantiate_contract(deps.as_mut(), None);
+        let pool = InterchainLiquidityPool {
+            curve: PoolCurve::Stable { amp: 100 },
+            ..mock_pair_pool("pool", 1_000_000)
+        };
+        POOLS.save(deps.as_mut().storage, "pool", &pool).unwrap();
+
+        let now = mock_env().block.time.seconds();
+        let ramp = |deps: DepsMut, sender: &str, future_amp, future_time| {
+            let msg = MsgRampAmpRequest {
+                pool_id: "pool".to_string(),
+                future_amp,
+                future_time,
+                timeout_height: 0,
+                timeout_timestamp: 0,
+                memo: None,
+            };
+            ramp_amp(deps, mock_env(), mock_info(sender, &[]), msg)
+        };
+        let end = now + MIN_RAMP_TIME;
+        let err = ramp(deps.as_mut(), "anyone", 200, end).unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized);
+        assert!(ramp(deps.as_mut(), "admin", 200, end - 1).is_err());
+        assert!(ramp(deps.as_mut(), "admin", 100 * MAX_AMP_CHANGE + 1, end).is_err());
+        assert!(ramp(deps.as_mut(), "admin", 100 / MAX_AMP_CHANGE - 1, end).is_err());
+        assert!(ramp(deps.as_mut(), "admin", 0, end).is_err());
+        ramp(deps.as_mut(), "admin", 100 * MAX_AMP_CHANGE, end).unwrap();
+        ramp(deps.as_mut(), "admin", 100 / MAX_AMP_CHANGE, end).unwrap();
+
+        // Once a ramp is acknowledged no other starts until it ends
+        let mut pool = POOLS.load(deps.as_ref().storage, "pool").unwrap();
+        pool.apply_amp_ramp(AmpRamp {
+            initial_amp: 100,
+            future_amp: 1_000,
+            initial_time: now,
+            future_time: end,
+        })
+        .unwrap();
+        POOLS.save(deps.as_mut().storage, "pool", &pool).unwrap();
+        assert!(ramp(deps.as_mut(), "admin", 200, end + MIN_RAMP_TIME).is_err());
+        let weighted = mock_pair_pool("pool", 1_000_000);
+        POOLS.save(deps.as_mut().storage, "pool", &weighted).unwrap();
+        assert!(ramp(deps.as_mut(), "admin", 200, end).is_err());
+    }
+
+    #[test]
+    fn test_protocol_fee_rate_and_claim() {
+        use cosmwasm_std::{coin, BankMsg, CosmosMsg};
+
+        let mut deps = mock_dependencies();
+        instantiate_contract(deps.as_mut(), None);
+        POOLS.save(deps.as_mut().storage, "pool", &mock_pair_pool("pool", 1_000_000)).unwrap();
+        let protocol_fee = |deps: Deps| {
+            let (token_in, token_out) = (coin(100_000, "aside"), coin(0, "bside"));
+            let swap_type = SwapMsgType::LEFT;
+            let env = mock_env();
+            let quote = quote_swap(deps.storage, &env, "pool", &swap_type, &token_in, &token_out, 0);
+            quote.unwrap().state_change.protocol_fees.unwrap()
+        };
+        let set_rate = |deps: DepsMut, protocol_fee_rate| {
+            let msg = ExecuteMsg::SetProtocolFee {
+                protocol_fee_rate,
+                fee_collector: "collector".to_string(),
+            };
+            execute(deps, mock_env(), mock_info("admin", &[]), msg)
+        };
+
+        // A 30 bps swap fee on 100_000 is 300, the protocol keeps none of it by default
+        assert_eq!(protocol_fee(deps.as_ref()), vec![coin(0, "aside")]);
+        set_rate(deps.as_mut(), 5_000).unwrap();
+        assert_eq!(protocol_fee(deps.as_ref()), vec![coin(150, "aside")]);
+        set_rate(deps.as_mut(), FEE_PRECISION.into()).unwrap();
+        assert_eq!(protocol_fee(deps.as_ref()), vec![coin(300, "aside")]);
+        assert!(set_rate(deps.as_mut(), u32::from(FEE_PRECISION) + 1).is_err());
+
+        // Only the collector claims, and each accrued fee is paid once
+        PROTOCOL_FEES.save(deps.as_mut().storage, ("pool", "aside"), &Uint128::new(150)).unwrap();
+        PROTOCOL_FEES.save(deps.as_mut().storage, ("pool", "bside"), &Uint128::zero()).unwrap();
+        let claim = |deps: DepsMut, sender: &str| {
+            let msg = ExecuteMsg::ClaimProtocolFees {
+                pool_id: "pool".to_string(),
+            };
+            execute(deps, mock_env(), mock_info(sender, &[]), msg)
+        };
+        assert_eq!(claim(deps.as_mut(), "admin").unwrap_err(), ContractError::Unauthorized);
+        let res = claim(deps.as_mut(), "collector").unwrap();
+        assert_eq!(
+            res.messages.into_iter().map(|msg| msg.msg).collect::<Vec<_>>(),
+            vec![CosmosMsg::Bank(BankMsg::Send {
+                to_address: "collector".to_string(),
+                amount: vec![coin(150, "aside")],
+            })]
+        );
+        let res = claim(deps.as_mut(), "collector").unwrap();
+        assert!(res.messages.is_empty());
+    }
+
+    #[test]
+    fn test_forwards_settle_on_transfer_outcome() {
+        use crate::types::{Forward, TRANSFER_PORT};
+        use cosmwasm_std::{coin, BankMsg, Event, SubMsgResponse};
+
+        let mut deps = mock_dependencies();
+        instantiate_contract(deps.as_mut(), None);
+        let pending = |recipient: &str, token, retries| PendingForward {
+            recipient: recipient.to_string(),
+            token,
+            forward: Forward {
+                receiver: "osmo1receiver".to_string(),
+                port: TRANSFER_PORT.to_string(),
+                channel: "channel-7".to_string(),
+                timeout: "".to_string(),
+                retries,
+                next: None,
+            },
+            attempts_left: retries,
+        };
+        let sent = |deps: DepsMut, sequence: u64| {
+            let event =
+                Event::new("send_packet").add_attribute("packet_sequence", sequence.to_string());
+            let response = SubMsgResponse {
+                events: vec![event],
+                data: None,
+            };
+            let msg = Reply {
+                id: FORWARD_REPLY_ID,
+                result: SubMsgResult::Ok(response),
+            };
+            reply(deps, mock_env(), msg).unwrap()
+        };
+        let settle = |deps: DepsMut, outcome| {
+            let msg = SudoMsg::IbcLifecycleComplete(outcome);
+            let res = sudo(deps, mock_env(), msg).unwrap();
+            res.messages.into_iter().map(|msg| msg.msg).collect::<Vec<_>>()
+        };
+        let bank_send = |to: &str, token| {
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: to.to_string(),
+                amount: vec![token],
+            })
+        };
+        let timeout = |sequence| IbcLifecycleComplete::IbcTimeout {
+            channel: "channel-7".to_string(),
+            sequence,
+        };
+        let ack = |sequence, success| IbcLifecycleComplete::IbcAck {
+            channel: "channel-7".to_string(),
+            sequence,
+            ack: "".to_string(),
+            success,
+        };
+
+        // Two forwards in one transaction are filed under their own transfer sequences
+        let (first, second) = (coin(100, "aside"), coin(200, "bside"));
+        let storage = deps.as_mut().storage;
+        let msg = forward_tokens(storage, &mock_env(), pending("alice", first.clone(), 1)).unwrap();
+        forward_tokens(storage, &mock_env(), pending("bob", second.clone(), 0)).unwrap();
+        match msg.msg {
+            CosmosMsg::Stargate { type_url, value } => {
+                assert_eq!(type_url, MSG_TRANSFER_TYPE_URL);
+                let memo = r#"{"ibc_callback":"cosmos2contract"}"#;
+                assert!(String::from_utf8_lossy(value.as_slice()).contains(memo));
+            }
+            msg => panic!("unexpected message {:?}", msg),
+        }
+        sent(deps.as_mut(), 1);
+        sent(deps.as_mut(), 2);
+        let filed = |deps: Deps, sequence| {
+            PENDING_FORWARDS.may_load(deps.storage, ("channel-7", sequence)).unwrap()
+        };
+        assert_eq!(filed(deps.as_ref(), 1).unwrap().recipient, "alice");
+        assert_eq!(filed(deps.as_ref(), 2).unwrap().recipient, "bob");
+
+        // A timeout is retried while retries are left, then paid out here
+        let res = sudo(deps.as_mut(), mock_env(), SudoMsg::IbcLifecycleComplete(timeout(1)));
+        assert_eq!(res.unwrap().messages[0].id, FORWARD_REPLY_ID);
+        assert_eq!(filed(deps.as_ref(), 1), None);
+        sent(deps.as_mut(), 3);
+        assert_eq!(filed(deps.as_ref(), 3).unwrap().attempts_left, 0);
+        assert_eq!(settle(deps.as_mut(), timeout(3)), vec![bank_send("alice", first.clone())]);
+
+        // An error ack pays out here, a successful one only drops the record
+        assert_eq!(settle(deps.as_mut(), ack(2, false)), vec![bank_send("bob", second)]);
+        assert_eq!(settle(deps.as_mut(), ack(2, false)), vec![]);
+        forward_tokens(deps.as_mut().storage, &mock_env(), pending("alice", first.clone(), 0))
+            .unwrap();
+        sent(deps.as_mut(), 4);
+        assert_eq!(settle(deps.as_mut(), ack(4, true)), vec![]);
+        assert_eq!(filed(deps.as_ref(), 4), None);
+
+        // A transfer rejected when dispatched is paid out right away
+        forward_tokens(deps.as_mut().storage, &mock_env(), pending("alice", first.clone(), 1))
+            .unwrap();
+        let msg = Reply {
+            id: FORWARD_REPLY_ID,
+            result: SubMsgResult::Err("invalid channel".to_string()),
+        };
+        let res = reply(deps.as_mut(), mock_env(), msg).unwrap();
+        assert_eq!(res.messages[0].msg, bank_send("alice", first));
+    }
 }
@@ -51,6 +51,36 @@ pub enum ContractError {
     #[error("Pool is not ready for swap!")]
     NotReadyForSwap,
 
+    #[error("Invalid packet timeout: {reason}")]
+    InvalidTimeout { reason: String },
+
+    #[error("No channel registered for chain {chain_id}")]
+    UnknownChain { chain_id: String },
+
+    #[error("Channel mismatch: expected {expected}, got {actual}")]
+    ChannelMismatch { expected: String, actual: String },
+
+    #[error("Pool {pool_id} already exists")]
+    PoolAlreadyExists { pool_id: String },
+
+    #[error("Pool {pool_id} is frozen")]
+    PoolFrozen { pool_id: String },
+
+    #[error("Unauthorized")]
+    Unauthorized,
+
+    #[error("The contract is temporarily paused")]
+    Paused,
+
+    #[error("Sending {message_type} packets is disabled")]
+    MessageDisabled { message_type: String },
+
+    #[error("Order {order_id} has expired")]
+    OrderExpired { order_id: String },
+
+    #[error("Cannot migrate from {from} to the older {to}")]
+    MigrationDowngrade { from: String, to: String },
+
     #[error("Only supports channel with ibc version ics100-1, got {version}")]
     InvalidIbcVersion { version: String },
 
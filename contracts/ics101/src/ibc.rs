@@ -1,10 +1,12 @@
 // use cw20::{Balance, Cw20ExecuteMsg};
 
 use crate::{
+    channels::close_channel,
     error::{ContractError, Never},
     interchainswap_handler::{
         ack_fail, do_ibc_packet_receive, on_packet_failure, on_packet_success,
     },
+    ledger::{hold_timed_out_packet, load_packet, settle_packet},
     utils::{enforce_order_and_version, try_get_ack_error},
 };
 use cosmwasm_std::{
@@ -51,14 +53,20 @@ pub fn ibc_channel_connect(
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
+/// Freezes the pools on a closing channel. Their liquidity can only be withdrawn locally
+/// from then on. Packets still in flight are settled by their timeouts.
 pub fn ibc_channel_close(
-    _deps: DepsMut,
-    _env: Env,
-    _channel: IbcChannelCloseMsg,
+    deps: DepsMut,
+    env: Env,
+    msg: IbcChannelCloseMsg,
 ) -> Result<IbcBasicResponse, ContractError> {
-    // TODO: what to do here?
-    // we will have locked funds that need to be returned somehow
-    unimplemented!();
+    let channel_id = msg.channel().endpoint.channel_id.clone();
+    let frozen = close_channel(deps.storage, &channel_id, env.block.time.seconds())?;
+
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "channel_close")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("frozen_pools", frozen.join(",")))
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -86,13 +94,15 @@ pub fn ibc_packet_receive(
 // check if success or failure and update balance, or return funds
 pub fn ibc_packet_ack(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     msg: IbcPacketAckMsg,
 ) -> Result<IbcBasicResponse, ContractError> {
+    let record = load_packet(deps.storage, &msg.original_packet)?;
+    settle_packet(deps.storage, &msg.original_packet)?;
     if let Some(error) = try_get_ack_error(&msg.acknowledgement) {
-        on_packet_failure(deps, msg.original_packet, error)
+        on_packet_failure(deps, msg.original_packet, error, record)
     } else {
-        on_packet_success(deps, msg.original_packet)
+        on_packet_success(deps, env, msg.original_packet)
     }
 }
 
@@ -104,5 +114,13 @@ pub fn ibc_packet_timeout(
     msg: IbcPacketTimeoutMsg,
 ) -> Result<IbcBasicResponse, ContractError> {
     let packet = msg.packet;
-    on_packet_failure(deps, packet, "timeout".to_string())
+    let record = load_packet(deps.storage, &packet)?;
+    // Packets that can be sent again keep their escrow until the sender retries or refunds
+    if hold_timed_out_packet(deps.storage, &packet)? {
+        return Ok(IbcBasicResponse::new()
+            .add_attribute("action", "timeout")
+            .add_attribute("sequence", packet.sequence.to_string())
+            .add_attribute("retryable", "true"));
+    }
+    on_packet_failure(deps, packet, "timeout".to_string(), record)
 }
@@ -3,12 +3,14 @@ use std::vec;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use crate::market::FEE_PRECISION;
 use crate::msg::LPAllocation;
 use crate::msg::LogExecuteMsg::LogObservation;
 use crate::msg::RouterExecuteMsg::MultiSwap;
 use crate::{
+    channels::{check_chain_channel, check_pool_channel, ensure_channel},
+    contract::{forward_tokens, resume_deferred_orders, swap_packet},
     error::ContractError,
+    oracle::record_observation,
     market::{
         InterchainLiquidityPool, PoolSide,
         PoolStatus::{Active, Cancelled, Initialized},
@@ -16,24 +18,29 @@ use crate::{
     msg::{
         MsgCancelMultiAssetDepositRequest, MsgCancelPoolRequest, MsgMakeMultiAssetDepositRequest,
         MsgMakePoolRequest, MsgMultiAssetWithdrawRequest, MsgSingleAssetDepositRequest,
-        MsgSwapRequest, MsgTakeMultiAssetDepositRequest, MsgTakePoolRequest,
+        MsgRampAmpRequest, MsgSetPoolStatusRequest, MsgSingleAssetWithdrawRequest,
+        MsgStopRampAmpRequest, MsgSwapRequest,
+        MsgTakeMultiAssetDepositRequest, MsgTakePoolRequest, SwapMsgType, SwapRoute,
     },
     state::{
-        ACTIVE_ORDERS, CONFIG, LOG_VOLUME, MULTI_ASSET_DEPOSIT_ORDERS, POOLS, POOL_TOKENS_LIST,
+        multi_asset_deposit_orders, InFlightPacket, PendingForward, RoutedOutput, ACTIVE_ORDERS,
+        CONFIG, DEFERRED_EXPIRIES, LOG_VOLUME, ORDER_EXPIRIES, POOLS, POOLS_BY_CHANNEL,
+        POOLS_BY_PAIR, POOL_TOKENS_LIST, PROTOCOL_FEES, ROUTED_OUTPUTS,
     },
     types::{
-        InterchainMessageType, InterchainSwapPacketData, MultiAssetDepositOrder, OrderStatus,
-        StateChange,
+        InterchainMessageType, InterchainSwapPacketData, Memo, MultiAssetDepositOrder,
+        OrderStatus, StateChange,
     },
     utils::{
-        burn_tokens_cw20, get_coins_from_deposits, get_pool_id_with_tokens, mint_tokens_cw20,
-        send_tokens_coin, send_tokens_cw20,
+        burn_tokens_cw20, get_coins_by_side, get_coins_from_deposits, get_depositors,
+        get_pool_id, mint_tokens_cw20, send_tokens_coin, send_tokens_cw20, ROUTER_REPLY_ID,
     },
 };
 
 use cosmwasm_std::{
-    attr, from_binary, from_slice, to_binary, Addr, Binary, Coin, DepsMut, Env, IbcBasicResponse,
-    IbcPacket, IbcReceiveResponse, StdError, SubMsg, Uint128, WasmMsg,
+    attr, from_binary, from_slice, to_binary, Addr, Binary, Coin, DepsMut, Empty, Env,
+    IbcBasicResponse, IbcPacket, IbcReceiveResponse, StdError, StdResult, Storage, SubMsg,
+    Uint128, WasmMsg,
 };
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
@@ -73,51 +80,99 @@ pub(crate) fn do_ibc_packet_receive(
         // Save pool data
         InterchainMessageType::MakePool => {
             let msg: MsgMakePoolRequest = from_slice(&packet_data.data)?;
+            check_chain_channel(deps.storage, &msg.source_chain_id, packet)?;
+            ensure_channel(&msg.counterparty_channel, &packet.dest.channel_id)?;
             on_received_make_pool(deps, env, packet, msg)
         }
         InterchainMessageType::TakePool => {
             let msg: MsgTakePoolRequest = from_slice(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
             let state_change_data: StateChange = from_slice(&packet_data.state_change.unwrap())?;
             on_received_take_pool(deps, env, packet, msg, state_change_data)
         }
         InterchainMessageType::CancelPool => {
             let msg: MsgCancelPoolRequest = from_slice(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
             on_received_cancel_pool(deps, env, packet, msg)
         }
         InterchainMessageType::SingleAssetDeposit => {
             let msg: MsgSingleAssetDepositRequest = from_slice(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
+            ensure_active(deps.storage, &msg.pool_id)?;
             let state_change_data: StateChange = from_slice(&packet_data.state_change.unwrap())?;
             on_received_single_deposit(deps, env, packet, msg, state_change_data)
         }
         InterchainMessageType::MakeMultiDeposit => {
             let msg: MsgMakeMultiAssetDepositRequest = from_slice(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
+            ensure_active(deps.storage, &msg.pool_id)?;
             let state_change_data: StateChange = from_slice(&packet_data.state_change.unwrap())?;
             on_received_make_multi_deposit(deps, env, packet, msg, state_change_data)
         }
         InterchainMessageType::TakeMultiDeposit => {
             let msg: MsgTakeMultiAssetDepositRequest = from_slice(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
+            ensure_active(deps.storage, &msg.pool_id)?;
             let state_change_data: StateChange = from_slice(&packet_data.state_change.unwrap())?;
             on_received_take_multi_deposit(deps, env, packet, msg, state_change_data)
         }
         InterchainMessageType::CancelMultiDeposit => {
             let msg: MsgCancelMultiAssetDepositRequest = from_slice(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
             on_received_cancel_multi_deposit(deps, env, packet, msg)
         }
         InterchainMessageType::MultiWithdraw => {
             let msg: MsgMultiAssetWithdrawRequest = from_slice(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
             let state_change_data: StateChange = from_slice(&packet_data.state_change.unwrap())?;
             on_received_multi_withdraw(deps, env, packet, msg, state_change_data)
         }
         InterchainMessageType::LeftSwap => {
             let msg: MsgSwapRequest = from_binary(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
+            ensure_active(deps.storage, &msg.pool_id)?;
             let state_change_data: StateChange = from_slice(&packet_data.state_change.unwrap())?;
             on_received_swap(deps, env, packet, msg, state_change_data)
         }
         InterchainMessageType::RightSwap => {
             let msg: MsgSwapRequest = from_binary(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
+            ensure_active(deps.storage, &msg.pool_id)?;
             let state_change_data: StateChange = from_slice(&packet_data.state_change.unwrap())?;
             on_received_swap(deps, env, packet, msg, state_change_data)
         }
+        InterchainMessageType::SingleAssetWithdraw => {
+            let msg: MsgSingleAssetWithdrawRequest = from_binary(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_coin.denom, packet)?;
+            let state_change_data: StateChange = from_slice(&packet_data.state_change.unwrap())?;
+            on_received_single_withdraw(deps, env, packet, msg, state_change_data)
+        }
+        InterchainMessageType::RampAmp => {
+            let msg: MsgRampAmpRequest = from_binary(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
+            let state_change_data: StateChange = from_slice(&packet_data.state_change.unwrap())?;
+            on_received_amp_ramp(deps, env, packet, msg.pool_id, state_change_data)
+        }
+        InterchainMessageType::StopRampAmp => {
+            let msg: MsgStopRampAmpRequest = from_binary(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
+            let state_change_data: StateChange = from_slice(&packet_data.state_change.unwrap())?;
+            on_received_amp_ramp(deps, env, packet, msg.pool_id, state_change_data)
+        }
+        InterchainMessageType::SetPoolStatus => {
+            let msg: MsgSetPoolStatusRequest = from_binary(&packet_data.data)?;
+            check_pool_channel(deps.storage, &msg.pool_id, packet)?;
+            on_received_set_pool_status(deps, env, packet, msg)
+        }
+    }
+}
+
+/// Swaps and deposits only apply to active pools. One that arrives after its pool was
+/// paused or deprecated is rejected and refunded on the sending chain.
+fn ensure_active(storage: &dyn Storage, pool_id: &str) -> Result<(), ContractError> {
+    match POOLS.may_load(storage, pool_id)? {
+        Some(pool) if pool.status != Active => Err(ContractError::NotReadyForSwap),
+        _ => Ok(()),
     }
 }
 
@@ -135,22 +190,16 @@ pub(crate) fn on_received_make_pool(
         ))));
     }
 
-    let mut tokens: [Coin; 2] = Default::default();
-    tokens[0] = msg.liquidity[0].balance.clone();
-    tokens[1] = msg.liquidity[1].balance.clone();
-
-    let pool_id = get_pool_id_with_tokens(
-        &tokens,
+    let pool_id = get_pool_id(
+        &msg.liquidity,
+        msg.swap_fee,
+        &msg.curve,
         msg.source_chain_id.clone(),
         msg.destination_chain_id.clone(),
     );
 
-    //load pool throw error if found
-    let interchain_pool_temp = POOLS.may_load(deps.storage, &pool_id)?;
-    if let Some(_pool) = interchain_pool_temp {
-        return Err(ContractError::Std(StdError::generic_err(
-            "Pool already exists".to_string(),
-        )));
+    if POOLS.has(deps.storage, &pool_id) {
+        return Err(ContractError::PoolAlreadyExists { pool_id });
     }
 
     let mut liquidity = vec![];
@@ -180,9 +229,18 @@ pub(crate) fn on_received_make_pool(
         source_chain_id: msg.source_chain_id,
         destination_chain_id: msg.destination_chain_id,
         pool_price: 0,
+        curve: msg.curve,
+        amp_ramp: None,
+        dynamic_fee: msg.dynamic_fee,
     };
 
     POOLS.save(deps.storage, &pool_id, &interchain_pool)?;
+    POOLS_BY_PAIR.save(deps.storage, (&interchain_pool.pair_key(), &pool_id), &Empty {})?;
+    POOLS_BY_CHANNEL.save(
+        deps.storage,
+        (&interchain_pool.counter_party_channel, &pool_id),
+        &Empty {},
+    )?;
 
     let res = IbcReceiveResponse::new()
         .add_attribute("pool_id", pool_id.clone())
@@ -197,7 +255,7 @@ pub(crate) fn on_received_make_pool(
 
 pub(crate) fn on_received_take_pool(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     _packet: &IbcPacket,
     msg: MsgTakePoolRequest,
     state_change: StateChange,
@@ -228,13 +286,9 @@ pub(crate) fn on_received_take_pool(
             }
             LPAllocation::Split => {
                 // split shares
-                let token = interchain_pool
-                    .find_asset_by_side(PoolSide::SOURCE)
-                    .map_err(|err| {
-                        StdError::generic_err(format!("Failed to find asset: {}", err))
-                    })?;
-                let splitted_shares =
-                    (new_shares * Uint128::from(token.weight)) / Uint128::from(100u64);
+                let splitted_shares = (new_shares
+                    * Uint128::from(interchain_pool.side_weight(PoolSide::SOURCE)))
+                    / Uint128::from(100u64);
                 sub_message = mint_tokens_cw20(msg.counter_creator, lp_token, splitted_shares)?;
             }
         }
@@ -255,6 +309,7 @@ pub(crate) fn on_received_take_pool(
     interchain_pool.status = Active;
 
     POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+    record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
 
     let res = IbcReceiveResponse::new()
         .set_ack(ack_success())
@@ -284,6 +339,10 @@ pub(crate) fn on_received_cancel_pool(
     }
     interchain_pool.status = Cancelled;
     POOLS.remove(deps.storage, &msg.pool_id);
+    POOLS_BY_CHANNEL.remove(
+        deps.storage,
+        (&interchain_pool.counter_party_channel, &msg.pool_id),
+    );
 
     let res = IbcReceiveResponse::new()
         .set_ack(ack_success())
@@ -296,7 +355,7 @@ pub(crate) fn on_received_cancel_pool(
 
 pub(crate) fn on_received_single_deposit(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     _packet: &IbcPacket,
     msg: MsgSingleAssetDepositRequest,
     state_change: StateChange,
@@ -334,13 +393,9 @@ pub(crate) fn on_received_single_deposit(
             }
             LPAllocation::Split => {
                 // split shares
-                let token = interchain_pool
-                    .find_asset_by_side(PoolSide::SOURCE)
-                    .map_err(|err| {
-                        StdError::generic_err(format!("Failed to find asset: {}", err))
-                    })?;
-                let splitted_shares =
-                    (new_shares * Uint128::from(token.weight)) / Uint128::from(100u64);
+                let splitted_shares = (new_shares
+                    * Uint128::from(interchain_pool.side_weight(PoolSide::SOURCE)))
+                    / Uint128::from(100u64);
                 sub_message = mint_tokens_cw20(msg.lp_taker, lp_token, splitted_shares)?;
             }
         }
@@ -361,6 +416,7 @@ pub(crate) fn on_received_single_deposit(
 
     // save pool.
     POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+    record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
 
     let res = IbcReceiveResponse::new()
         .add_submessages(sub_message)
@@ -381,14 +437,19 @@ pub(crate) fn on_received_make_multi_deposit(
 ) -> Result<IbcReceiveResponse, ContractError> {
     // load pool throw error if found
     let interchain_pool_temp = POOLS.may_load(deps.storage, &msg.pool_id)?;
-    if let Some(_pool) = interchain_pool_temp {
-        // Do nothing
+    let interchain_pool;
+    if let Some(pool) = interchain_pool_temp {
+        interchain_pool = pool;
     } else {
         return Err(ContractError::Std(StdError::generic_err(
             "Pool not found".to_string(),
         )));
     }
 
+    // The maker's assets sit on the destination side of the pool on this chain
+    let (source_maker, destination_taker) =
+        get_depositors(&interchain_pool, &msg.deposits, PoolSide::DESTINATION)?;
+
     let mut config = CONFIG.load(deps.storage)?;
     config.counter += 1;
 
@@ -396,20 +457,17 @@ pub(crate) fn on_received_make_multi_deposit(
         id: state_change.multi_deposit_order_id.unwrap(),
         chain_id: msg.chain_id.clone(),
         pool_id: msg.pool_id.clone(),
-        source_maker: msg.deposits[0].sender.clone(),
-        destination_taker: msg.deposits[1].sender.clone(),
+        source_maker: source_maker.clone(),
+        destination_taker: destination_taker.clone(),
         deposits: get_coins_from_deposits(msg.deposits.clone()),
         status: OrderStatus::Pending,
         created_at: env.block.height,
+        expires_at: msg.expires_at.unwrap_or_default(),
     };
     let key = msg.pool_id.clone() + "-" + &multi_asset_order.id;
 
-    MULTI_ASSET_DEPOSIT_ORDERS.save(deps.storage, key, &multi_asset_order)?;
-    let ac_key = msg.deposits[0].sender.clone()
-        + "-"
-        + &msg.pool_id.clone()
-        + "-"
-        + &msg.deposits[1].sender.clone();
+    multi_asset_deposit_orders().save(deps.storage, key, &multi_asset_order)?;
+    let ac_key = source_maker + "-" + &msg.pool_id + "-" + &destination_taker;
     ACTIVE_ORDERS.save(deps.storage, ac_key, &multi_asset_order)?;
     CONFIG.save(deps.storage, &config)?;
 
@@ -424,7 +482,7 @@ pub(crate) fn on_received_make_multi_deposit(
 
 pub(crate) fn on_received_take_multi_deposit(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     _packet: &IbcPacket,
     msg: MsgTakeMultiAssetDepositRequest,
     state_change: StateChange,
@@ -444,17 +502,11 @@ pub(crate) fn on_received_take_multi_deposit(
     // get order
     // load orders
     let key = msg.pool_id.clone() + "-" + &msg.order_id;
-    let multi_asset_order_temp = MULTI_ASSET_DEPOSIT_ORDERS.may_load(deps.storage, key.clone())?;
+    let multi_asset_order_temp = multi_asset_deposit_orders().may_load(deps.storage, key.clone())?;
     let mut multi_asset_order;
     if let Some(order) = multi_asset_order_temp {
         multi_asset_order = order;
         multi_asset_order.status = OrderStatus::Complete;
-        let ac_key = multi_asset_order.source_maker.clone()
-            + "-"
-            + &msg.pool_id
-            + "-"
-            + &multi_asset_order.destination_taker;
-        ACTIVE_ORDERS.remove(deps.storage, ac_key);
     } else {
         return Err(ContractError::ErrOrderNotFound);
     }
@@ -474,13 +526,9 @@ pub(crate) fn on_received_take_multi_deposit(
             }
             LPAllocation::Split => {
                 // split shares
-                let token = interchain_pool
-                    .find_asset_by_side(PoolSide::SOURCE)
-                    .map_err(|err| {
-                        StdError::generic_err(format!("Failed to find asset: {}", err))
-                    })?;
-                let splitted_shares =
-                    (new_shares * Uint128::from(token.weight)) / Uint128::from(100u64);
+                let splitted_shares = (new_shares
+                    * Uint128::from(interchain_pool.side_weight(PoolSide::SOURCE)))
+                    / Uint128::from(100u64);
                 sub_message = mint_tokens_cw20(
                     multi_asset_order.source_maker.clone(),
                     lp_token,
@@ -511,8 +559,15 @@ pub(crate) fn on_received_take_multi_deposit(
         )));
     }
 
-    MULTI_ASSET_DEPOSIT_ORDERS.save(deps.storage, key, &multi_asset_order)?;
+    let ac_key = multi_asset_order.source_maker.clone()
+        + "-"
+        + &msg.pool_id
+        + "-"
+        + &multi_asset_order.destination_taker;
+    ACTIVE_ORDERS.remove(deps.storage, ac_key);
+    multi_asset_deposit_orders().save(deps.storage, key, &multi_asset_order)?;
     POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+    record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
 
     let res = IbcReceiveResponse::new()
         .set_ack(ack_success())
@@ -543,10 +598,14 @@ pub(crate) fn on_received_cancel_multi_deposit(
     // get order
     // load orders
     let key = msg.pool_id.clone() + "-" + &msg.order_id;
-    let multi_asset_order_temp = MULTI_ASSET_DEPOSIT_ORDERS.may_load(deps.storage, key.clone())?;
+    let multi_asset_order_temp = multi_asset_deposit_orders().may_load(deps.storage, key.clone())?;
     let mut multi_asset_order;
     if let Some(order) = multi_asset_order_temp {
         multi_asset_order = order;
+        // A taken order can no longer be cancelled, the maker is not refunded then
+        if multi_asset_order.status != OrderStatus::Pending {
+            return Err(ContractError::ErrOrderAlreadyCompleted);
+        }
         multi_asset_order.status = OrderStatus::Cancelled;
         let ac_key = multi_asset_order.source_maker.clone()
             + "-"
@@ -558,7 +617,7 @@ pub(crate) fn on_received_cancel_multi_deposit(
         return Err(ContractError::ErrOrderNotFound);
     }
 
-    MULTI_ASSET_DEPOSIT_ORDERS.save(deps.storage, key, &multi_asset_order)?;
+    multi_asset_deposit_orders().save(deps.storage, key, &multi_asset_order)?;
 
     let res = IbcReceiveResponse::new()
         .set_ack(ack_success())
@@ -571,7 +630,7 @@ pub(crate) fn on_received_cancel_multi_deposit(
 
 pub(crate) fn on_received_multi_withdraw(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     _packet: &IbcPacket,
     msg: MsgMultiAssetWithdrawRequest,
     state_change: StateChange,
@@ -589,19 +648,20 @@ pub(crate) fn on_received_multi_withdraw(
 
     let out_assets = state_change.out_tokens.unwrap();
     let pool_tokens = state_change.pool_tokens.unwrap();
-    let token = interchain_pool
-        .find_asset_by_side(PoolSide::SOURCE)
-        .map_err(|err| StdError::generic_err(format!("Failed to find asset: {}", err)))?;
+    let source_assets = interchain_pool.find_assets_by_side(PoolSide::SOURCE);
     let mut sub_messages = vec![];
 
     // Update pool status by subtracting the supplied pool coin and output token
     for pool_asset in out_assets {
-        if token.balance.denom == pool_asset.denom {
+        if source_assets
+            .iter()
+            .any(|asset| asset.balance.denom == pool_asset.denom)
+        {
             // Unlock tokens for this chain
-            sub_messages = send_tokens_coin(
+            sub_messages.append(&mut send_tokens_coin(
                 &Addr::unchecked(msg.counterparty_receiver.clone()),
                 pool_asset.clone(),
-            )?;
+            )?);
         }
         interchain_pool
             .subtract_asset(pool_asset.clone())
@@ -616,6 +676,7 @@ pub(crate) fn on_received_multi_withdraw(
 
     // Save pool
     POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+    record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
 
     let res = IbcReceiveResponse::new()
         .set_ack(ack_success())
@@ -627,9 +688,46 @@ pub(crate) fn on_received_multi_withdraw(
     Ok(res)
 }
 
-pub(crate) fn on_received_swap(
+pub(crate) fn on_received_single_withdraw(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
+    _packet: &IbcPacket,
+    msg: MsgSingleAssetWithdrawRequest,
+    state_change: StateChange,
+) -> Result<IbcReceiveResponse, ContractError> {
+    let pool_id = msg.pool_coin.denom.clone();
+    // load pool throw error if found
+    let mut interchain_pool = POOLS.may_load(deps.storage, &pool_id)?.ok_or_else(|| {
+        ContractError::Std(StdError::generic_err("Pool not found".to_string()))
+    })?;
+
+    // The withdrawn asset lives on the sender chain, only mirror the balances here
+    for token_out in state_change.out_tokens.unwrap() {
+        interchain_pool
+            .subtract_asset(token_out)
+            .map_err(|err| StdError::generic_err(format!("Failed to subtract asset: {}", err)))?;
+    }
+    for pool_token in state_change.pool_tokens.unwrap() {
+        interchain_pool
+            .subtract_supply(pool_token)
+            .map_err(|err| StdError::generic_err(format!("Failed to subtract supply: {}", err)))?;
+    }
+
+    POOLS.save(deps.storage, &pool_id, &interchain_pool)?;
+    record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
+
+    let res = IbcReceiveResponse::new()
+        .set_ack(ack_success())
+        .add_attribute("pool_id", pool_id)
+        .add_attribute("action", "single_asset_withdraw")
+        .add_attribute("success", "true");
+
+    Ok(res)
+}
+
+pub(crate) fn on_received_swap(
+    mut deps: DepsMut,
+    env: Env,
     _packet: &IbcPacket,
     msg: MsgSwapRequest,
     state_change: StateChange,
@@ -646,70 +744,139 @@ pub(crate) fn on_received_swap(
     }
 
     let token_out = state_change.out_tokens.unwrap();
+    let protocol_fee = net_protocol_fee(&state_change.protocol_fees);
     let cfg = CONFIG.load(deps.storage)?;
-    let mut sub_messages: Vec<SubMsg>;
-    // Deduct fees
-    let fee_charged = token_out.get(0).unwrap().clone().amount.checked_div(FEE_PRECISION.into()).unwrap().checked_mul(interchain_pool.swap_fee.into()).unwrap();
-    let output_token = Coin {
-        denom: token_out.get(0).unwrap().clone().denom,
-        amount: token_out.get(0).unwrap().clone().amount.checked_sub(fee_charged).unwrap(),
+    let mut sub_messages: Vec<SubMsg> = vec![];
+    // Swap fees were already charged on the input asset by the sending chain.
+    // A right swap pays out exactly the requested token, its state change holds the offer.
+    let output_token = match msg.swap_type {
+        crate::msg::SwapMsgType::LEFT => token_out.get(0).unwrap().clone(),
+        crate::msg::SwapMsgType::RIGHT => msg.token_out.clone(),
     };
-    sub_messages = send_tokens_coin(
-        &Addr::unchecked(cfg.admin),
-        Coin { denom: output_token.denom.clone(), amount: fee_charged },
-    )?;
-
-    // Handle routing here
-    if let Some(route) = msg.route {
-        let route_msg = MultiSwap {
-            requests: route.requests, offer_amount: output_token.amount,
-            receiver: Some(Addr::unchecked(msg.recipient)),
-            minimum_receive: route.minimum_receive 
-        };
-    
-        // router message
-        sub_messages.push(SubMsg::new(WasmMsg::Execute {
-            contract_addr: cfg.router,
-            msg: to_binary(&route_msg)?,
-            funds: vec![output_token],
-        }));
-    } else {
-        // send tokens
-        let send_tokens_msg = send_tokens_coin(
-            &Addr::unchecked(msg.recipient),
-            output_token,
-        )?;
-        sub_messages.append(&mut send_tokens_msg.clone());
-    }
 
     let log_token_1;
     let log_token_2;
     // Update pool status by subtracting output token and adding input token
     match msg.swap_type {
         crate::msg::SwapMsgType::LEFT => {
+            // The protocol fee stays on the sending chain and never enters the pool
             interchain_pool
-                .add_asset(msg.token_in.clone())
+                .add_asset(Coin {
+                    denom: msg.token_in.denom.clone(),
+                    amount: msg.token_in.amount - protocol_fee,
+                })
                 .map_err(|err| StdError::generic_err(format!("Failed to add asset: {}", err)))?;
             interchain_pool
                 .subtract_asset(token_out.get(0).unwrap().clone())
                 .map_err(|err| StdError::generic_err(format!("Failed to add asset: {}", err)))?;
-            log_token_1 = msg.token_in;
+            log_token_1 = msg.token_in.clone();
             log_token_2 = token_out.get(0).unwrap().clone();
         }
         crate::msg::SwapMsgType::RIGHT => {
             // token_out here is offer amount that is needed to get msg.token_out
+            let offer = token_out.get(0).unwrap().clone();
             interchain_pool
-                .add_asset(token_out.get(0).unwrap().clone())
+                .add_asset(Coin {
+                    denom: offer.denom,
+                    amount: offer.amount - protocol_fee,
+                })
                 .map_err(|err| StdError::generic_err(format!("Failed to add asset: {}", err)))?;
             interchain_pool
                 .subtract_asset(msg.token_out.clone())
                 .map_err(|err| StdError::generic_err(format!("Failed to add asset: {}", err)))?;
-            log_token_1 = msg.token_out;
+            log_token_1 = msg.token_out.clone();
             log_token_2 = token_out.get(0).unwrap().clone()
         }
     }
 
+    // Handle routing before saving the pool, a failing hop must leave no state behind
+    let mut next_hop_attributes = vec![];
+    match msg.route.clone() {
+        Some(route) if !route.requests.is_empty() => {
+            let next_hop = route.requests[0].clone();
+            if let Some(next_pool) = POOLS.may_load(deps.storage, &next_hop.pool_id)? {
+                // Continue the route over IBC from this chain. The output stays escrowed
+                // here and is refunded to this hop's recipient if the next hop fails.
+                if next_pool.find_asset_by_denom(&next_hop.asset_in)?.side != PoolSide::SOURCE {
+                    return Err(ContractError::Std(StdError::generic_err(format!(
+                        "Route hop input {} is not held on this chain",
+                        next_hop.asset_in
+                    ))));
+                }
+                let next_msg = next_hop_request(&msg, output_token, route)?;
+                let (ibc_msg, attributes) = swap_packet(deps.branch(), &env, next_msg)?;
+                sub_messages.push(ibc_msg);
+                next_hop_attributes = attributes
+                    .into_iter()
+                    .map(|attribute| attr(format!("next_hop_{}", attribute.key), attribute.value))
+                    .collect();
+            } else {
+                let route_msg = MultiSwap {
+                    requests: route.requests,
+                    offer_amount: output_token.amount,
+                    receiver: Some(Addr::unchecked(msg.recipient.clone())),
+                    minimum_receive: route.minimum_receive,
+                };
+
+                // The router may reject the route, the output is then paid out here
+                ROUTED_OUTPUTS.push_back(
+                    deps.storage,
+                    &RoutedOutput {
+                        recipient: msg.recipient,
+                        token: output_token.clone(),
+                    },
+                )?;
+                sub_messages.push(SubMsg::reply_always(
+                    WasmMsg::Execute {
+                        contract_addr: cfg.router,
+                        msg: to_binary(&route_msg)?,
+                        funds: vec![output_token],
+                    },
+                    ROUTER_REPLY_ID,
+                ));
+            }
+        }
+        route => {
+            // Final hop, enforce the route's minimum output end to end
+            if let Some(minimum_receive) = route.and_then(|route| route.minimum_receive) {
+                if output_token.amount < minimum_receive {
+                    return Err(ContractError::FailedOnSwapReceived {
+                        err: format!(
+                            "route minimum not met! expected: {}, output: {}",
+                            minimum_receive, output_token
+                        ),
+                    });
+                }
+            }
+            match msg.memo.as_ref().map(Memo::from_memo).transpose()?.flatten() {
+                // Forward the output over ICS-20 as the memo instructs
+                Some(memo) => {
+                    let attempts_left = memo.forward.retries;
+                    sub_messages.push(forward_tokens(
+                        deps.storage,
+                        &env,
+                        PendingForward {
+                            recipient: msg.recipient,
+                            token: output_token,
+                            forward: memo.forward,
+                            attempts_left,
+                        },
+                    )?);
+                }
+                None => {
+                    // send tokens
+                    let send_tokens_msg = send_tokens_coin(
+                        &Addr::unchecked(msg.recipient),
+                        output_token,
+                    )?;
+                    sub_messages.append(&mut send_tokens_msg.clone());
+                }
+            }
+        }
+    }
+
     POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+    record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
 
     // Log swap values
     let log_volume = LOG_VOLUME.may_load(deps.storage, msg.pool_id.clone())?;
@@ -732,14 +899,117 @@ pub(crate) fn on_received_swap(
         .add_submessages(sub_messages)
         .add_attribute("pool_id", msg.pool_id)
         .add_attribute("action", "swap_asset")
-        .add_attribute("success", "true");
+        .add_attribute("success", "true")
+        .add_attributes(next_hop_attributes);
     Ok(res)
 }
 
+/// Builds the swap for the next hop of a route, offering the output of the `previous` hop.
+/// Intermediate hops are unbounded, the final one must return the route minimum.
+fn next_hop_request(
+    previous: &MsgSwapRequest,
+    offer: Coin,
+    mut route: SwapRoute,
+) -> Result<MsgSwapRequest, ContractError> {
+    let hop = route.requests.remove(0);
+    if hop.asset_in != offer.denom {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Route hop input {} does not match previous output {}",
+            hop.asset_in, offer.denom
+        ))));
+    }
+
+    let minimum_out = if route.requests.is_empty() {
+        route.minimum_receive.unwrap_or_default()
+    } else {
+        Uint128::zero()
+    };
+
+    Ok(MsgSwapRequest {
+        swap_type: SwapMsgType::LEFT,
+        // The route's sender has no account on this chain. The hop is sent on behalf of the
+        // previous recipient, who holds its input here and is refunded if it fails.
+        sender: previous.recipient.clone(),
+        pool_id: hop.pool_id,
+        token_in: offer,
+        token_out: Coin {
+            denom: hop.asset_out,
+            amount: minimum_out,
+        },
+        slippage: 0,
+        recipient: hop.recipient.unwrap_or_else(|| previous.recipient.clone()),
+        timeout_height: 0,
+        timeout_timestamp: 0,
+        route: Some(route),
+        // Any forward applies to the output of the final hop
+        memo: previous.memo.clone(),
+    })
+}
+
 // update the balance stored on this (channel, denom) index
 // acknowledgement
+pub(crate) fn on_received_amp_ramp(
+    deps: DepsMut,
+    env: Env,
+    _packet: &IbcPacket,
+    pool_id: String,
+    state_change: StateChange,
+) -> Result<IbcReceiveResponse, ContractError> {
+    let mut interchain_pool = POOLS.may_load(deps.storage, &pool_id)?.ok_or_else(|| {
+        ContractError::Std(StdError::generic_err("Pool not found".to_string()))
+    })?;
+
+    let amp_ramp = state_change
+        .amp_ramp
+        .ok_or_else(|| StdError::generic_err("Missing amplification ramp"))?;
+    interchain_pool.apply_amp_ramp(amp_ramp)?;
+    POOLS.save(deps.storage, &pool_id, &interchain_pool)?;
+    record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
+
+    let res = IbcReceiveResponse::new()
+        .set_ack(ack_success())
+        .add_attribute("pool_id", pool_id)
+        .add_attribute("action", "amp_ramp_receive")
+        .add_attribute("success", "true");
+
+    Ok(res)
+}
+
+pub(crate) fn on_received_set_pool_status(
+    deps: DepsMut,
+    _env: Env,
+    _packet: &IbcPacket,
+    msg: MsgSetPoolStatusRequest,
+) -> Result<IbcReceiveResponse, ContractError> {
+    let mut interchain_pool = POOLS.may_load(deps.storage, &msg.pool_id)?.ok_or_else(|| {
+        ContractError::Std(StdError::generic_err("Pool not found".to_string()))
+    })?;
+
+    // A change already made here by the other side's own request is acknowledged as is
+    if interchain_pool.status != msg.status {
+        if !interchain_pool.status.can_transition_to(&msg.status) {
+            return Err(ContractError::InvalidStatus);
+        }
+        interchain_pool.status = msg.status.clone();
+        POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+        if msg.status == Active {
+            resume_deferred_orders(deps.storage, &msg.pool_id)?;
+        }
+    }
+
+    let res = IbcReceiveResponse::new()
+        .set_ack(ack_success())
+        .add_attribute("pool_id", msg.pool_id)
+        .add_attribute("status", format!("{:?}", msg.status))
+        .add_attribute("action", "set_pool_status_receive")
+        .add_attribute("success", "true");
+
+    Ok(res)
+}
+
 pub(crate) fn on_packet_success(
     deps: DepsMut,
+    env: Env,
     packet: IbcPacket,
 ) -> Result<IbcBasicResponse, ContractError> {
     let packet_data: InterchainSwapPacketData = from_binary(&packet.data)?;
@@ -788,13 +1058,9 @@ pub(crate) fn on_packet_success(
                     }
                     LPAllocation::Split => {
                         // split shares
-                        let token = interchain_pool
-                            .find_asset_by_side(PoolSide::SOURCE)
-                            .map_err(|err| {
-                                StdError::generic_err(format!("Failed to find asset: {}", err))
-                            })?;
-                        let splitted_shares =
-                            (new_shares * Uint128::from(token.weight)) / Uint128::from(100u64);
+                        let splitted_shares = (new_shares
+                            * Uint128::from(interchain_pool.side_weight(PoolSide::SOURCE)))
+                            / Uint128::from(100u64);
                         sub_message = mint_tokens_cw20(msg.creator, lp_token, splitted_shares)?;
                     }
                 }
@@ -815,6 +1081,7 @@ pub(crate) fn on_packet_success(
 
             interchain_pool.status = Active;
             POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+            record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
 
             Ok(IbcBasicResponse::new()
                 .add_submessages(sub_message)
@@ -837,19 +1104,23 @@ pub(crate) fn on_packet_success(
             interchain_pool.status = Cancelled;
 
             // Refund tokens
-            let token = interchain_pool
-                .find_asset_by_side(PoolSide::SOURCE)
-                .map_err(|err| StdError::generic_err(format!("Failed to find asset: {}", err)))?;
-
-            send_tokens_coin(
-                &Addr::unchecked(interchain_pool.source_creator),
-                token.balance,
-            )?;
+            let mut sub_messages = vec![];
+            for asset in interchain_pool.find_assets_by_side(PoolSide::SOURCE) {
+                sub_messages.append(&mut send_tokens_coin(
+                    &Addr::unchecked(interchain_pool.source_creator.clone()),
+                    asset.balance,
+                )?);
+            }
 
             POOL_TOKENS_LIST.remove(deps.storage, &msg.pool_id);
             POOLS.remove(deps.storage, &msg.pool_id);
+            POOLS_BY_CHANNEL.remove(
+                deps.storage,
+                (&interchain_pool.counter_party_channel, &msg.pool_id),
+            );
 
             Ok(IbcBasicResponse::new()
+                .add_submessages(sub_messages)
                 .add_attribute("pool_id", msg.pool_id)
                 .add_attribute("action", "cancel_pool_acknowledged")
                 .add_attributes(attributes))
@@ -883,13 +1154,9 @@ pub(crate) fn on_packet_success(
                         sub_message = mint_tokens_cw20(msg.sender, lp_token, new_shares)?;
                     }
                     LPAllocation::Split => {
-                        let token = interchain_pool
-                            .find_asset_by_side(PoolSide::SOURCE)
-                            .map_err(|err| {
-                                StdError::generic_err(format!("Failed to find asset: {}", err))
-                            })?;
-                        let splitted_shares =
-                            (new_shares * Uint128::from(token.weight)) / Uint128::from(100u64);
+                        let splitted_shares = (new_shares
+                            * Uint128::from(interchain_pool.side_weight(PoolSide::SOURCE)))
+                            / Uint128::from(100u64);
                         sub_message = mint_tokens_cw20(msg.sender, lp_token, splitted_shares)?;
                     }
                 }
@@ -909,6 +1176,7 @@ pub(crate) fn on_packet_success(
                 .map_err(|err| StdError::generic_err(format!("Failed to add supply: {}", err)))?;
 
             POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+            record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
 
             Ok(IbcBasicResponse::new()
                 .add_attribute("pool_id", msg.pool_id)
@@ -943,7 +1211,7 @@ pub(crate) fn on_packet_success(
             // load orders
             let key = msg.pool_id.clone() + "-" + &msg.order_id;
             let multi_asset_order_temp =
-                MULTI_ASSET_DEPOSIT_ORDERS.may_load(deps.storage, key.clone())?;
+                multi_asset_deposit_orders().may_load(deps.storage, key.clone())?;
             let mut multi_asset_order;
             if let Some(order) = multi_asset_order_temp {
                 multi_asset_order = order;
@@ -973,13 +1241,9 @@ pub(crate) fn on_packet_success(
                             mint_tokens_cw20(msg.sender, lp_token, state_change.shares.unwrap())?;
                     }
                     LPAllocation::Split => {
-                        let token = interchain_pool
-                            .find_asset_by_side(PoolSide::SOURCE)
-                            .map_err(|err| {
-                                StdError::generic_err(format!("Failed to find asset: {}", err))
-                            })?;
-                        let splitted_shares =
-                            (new_shares * Uint128::from(token.weight)) / Uint128::from(100u64);
+                        let splitted_shares = (new_shares
+                            * Uint128::from(interchain_pool.side_weight(PoolSide::SOURCE)))
+                            / Uint128::from(100u64);
                         sub_message = mint_tokens_cw20(msg.sender, lp_token, splitted_shares)?;
                     }
                 }
@@ -1008,8 +1272,9 @@ pub(crate) fn on_packet_success(
                 )));
             }
 
-            MULTI_ASSET_DEPOSIT_ORDERS.save(deps.storage, key, &multi_asset_order)?;
+            multi_asset_deposit_orders().save(deps.storage, key, &multi_asset_order)?;
             POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+            record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
             Ok(IbcBasicResponse::new()
                 .add_submessages(sub_message)
                 .add_attribute("pool_id", msg.pool_id)
@@ -1034,7 +1299,7 @@ pub(crate) fn on_packet_success(
             // load orders
             let key = msg.pool_id.clone() + "-" + &msg.order_id;
             let multi_asset_order_temp =
-                MULTI_ASSET_DEPOSIT_ORDERS.may_load(deps.storage, key.clone())?;
+                multi_asset_deposit_orders().may_load(deps.storage, key.clone())?;
             let mut multi_asset_order;
             if let Some(order) = multi_asset_order_temp {
                 multi_asset_order = order;
@@ -1050,21 +1315,21 @@ pub(crate) fn on_packet_success(
             }
 
             // Refund tokens
-            let token = interchain_pool
-                .find_asset_by_side(PoolSide::SOURCE)
-                .map_err(|err| StdError::generic_err(format!("Failed to find asset: {}", err)))?;
-
-            for asset in multi_asset_order.deposits.clone() {
-                if asset.denom == token.balance.denom {
-                    send_tokens_coin(
-                        &Addr::unchecked(multi_asset_order.source_maker.clone()),
-                        asset,
-                    )?;
-                }
+            let mut sub_messages = vec![];
+            for asset in get_coins_by_side(
+                &interchain_pool,
+                &multi_asset_order.deposits,
+                PoolSide::SOURCE,
+            ) {
+                sub_messages.append(&mut send_tokens_coin(
+                    &Addr::unchecked(multi_asset_order.source_maker.clone()),
+                    asset,
+                )?);
             }
 
-            MULTI_ASSET_DEPOSIT_ORDERS.save(deps.storage, key, &multi_asset_order)?;
+            multi_asset_deposit_orders().save(deps.storage, key, &multi_asset_order)?;
             Ok(IbcBasicResponse::new()
+                .add_submessages(sub_messages)
                 .add_attribute("pool_id", msg.pool_id)
                 .add_attribute("action", "cancel_multi_deposit_acknowledged")
                 .add_attributes(attributes))
@@ -1088,19 +1353,20 @@ pub(crate) fn on_packet_success(
 
             let out_assets = state_change.out_tokens.unwrap();
             let pool_tokens = state_change.pool_tokens.unwrap();
-            let token = interchain_pool
-                .find_asset_by_side(PoolSide::SOURCE)
-                .map_err(|err| StdError::generic_err(format!("Failed to find asset: {}", err)))?;
+            let source_assets = interchain_pool.find_assets_by_side(PoolSide::SOURCE);
             let mut sub_messages = vec![];
 
             // Update pool status by subtracting the supplied pool coin and output token
             for pool_asset in out_assets {
-                if token.balance.denom == pool_asset.denom {
+                if source_assets
+                    .iter()
+                    .any(|asset| asset.balance.denom == pool_asset.denom)
+                {
                     // Unlock tokens for this chain
-                    sub_messages = send_tokens_coin(
+                    sub_messages.append(&mut send_tokens_coin(
                         &Addr::unchecked(msg.receiver.clone()),
                         pool_asset.clone(),
-                    )?;
+                    )?);
                 }
                 interchain_pool
                     .subtract_asset(pool_asset.clone())
@@ -1127,6 +1393,7 @@ pub(crate) fn on_packet_success(
             }
             // Save pool
             POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+            record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
 
             Ok(IbcBasicResponse::new()
                 .add_attribute("pool_id", msg.pool_id)
@@ -1134,6 +1401,50 @@ pub(crate) fn on_packet_success(
                 .add_attributes(attributes)
                 .add_submessages(sub_messages))
         }
+        InterchainMessageType::SingleAssetWithdraw => {
+            let msg: MsgSingleAssetWithdrawRequest = from_binary(&packet_data.data)?;
+            let state_change: StateChange = from_slice(&packet_data.state_change.unwrap())?;
+            let pool_id = msg.pool_coin.denom.clone();
+
+            // load pool throw error if found
+            let mut interchain_pool = POOLS.may_load(deps.storage, &pool_id)?.ok_or_else(|| {
+                ContractError::Std(StdError::generic_err("Pool not found".to_string()))
+            })?;
+
+            let mut sub_messages = vec![];
+            for token_out in state_change.out_tokens.unwrap() {
+                // Unlock tokens for this chain
+                sub_messages.append(&mut send_tokens_coin(
+                    &Addr::unchecked(msg.sender.clone()),
+                    token_out.clone(),
+                )?);
+                interchain_pool.subtract_asset(token_out).map_err(|err| {
+                    StdError::generic_err(format!("Failed to subtract asset: {}", err))
+                })?;
+            }
+            for pool_token in state_change.pool_tokens.unwrap() {
+                interchain_pool.subtract_supply(pool_token).map_err(|err| {
+                    StdError::generic_err(format!("Failed to subtract supply: {}", err))
+                })?;
+            }
+
+            // Burn the escrowed pool tokens
+            if let Some(lp_token) = POOL_TOKENS_LIST.may_load(deps.storage, &pool_id)? {
+                sub_messages.push(burn_tokens_cw20(lp_token, msg.pool_coin.amount)?);
+            } else {
+                return Err(ContractError::Std(StdError::generic_err(
+                    "LP Token is not initialized: Error".to_string(),
+                )));
+            }
+            POOLS.save(deps.storage, &pool_id, &interchain_pool)?;
+            record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
+
+            Ok(IbcBasicResponse::new()
+                .add_attribute("pool_id", pool_id)
+                .add_attribute("action", "single_asset_withdraw_acknowledged")
+                .add_attributes(attributes)
+                .add_submessages(sub_messages))
+        }
         InterchainMessageType::LeftSwap => {
             let msg: MsgSwapRequest = from_binary(&packet_data.data)?;
             let state_change: StateChange = from_slice(&packet_data.state_change.unwrap())?;
@@ -1167,15 +1478,24 @@ pub(crate) fn on_packet_success(
                 }));
             }
 
-            // Update pool status by subtracting output token and adding input token
+            // Update pool status by subtracting output token and adding input token net of protocol fee
+            let protocol_fee = accrue_protocol_fees(
+                deps.storage,
+                &msg.pool_id,
+                state_change.protocol_fees.unwrap_or_default(),
+            )?;
             interchain_pool
-                .add_asset(msg.token_in)
+                .add_asset(Coin {
+                    denom: msg.token_in.denom,
+                    amount: msg.token_in.amount - protocol_fee,
+                })
                 .map_err(|err| StdError::generic_err(format!("Failed to add asset: {}", err)))?;
             interchain_pool
                 .subtract_asset(token_out.get(0).unwrap().clone())
                 .map_err(|err| StdError::generic_err(format!("Failed to add asset: {}", err)))?;
 
             POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+            record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
 
             Ok(IbcBasicResponse::new()
                 .add_submessages(sub_messages)
@@ -1218,30 +1538,115 @@ pub(crate) fn on_packet_success(
 
             // Update pool status by subtracting output token and adding input token
             // token_out here is offer amount that is needed to get msg.token_out
+            let protocol_fee = accrue_protocol_fees(
+                deps.storage,
+                &msg.pool_id,
+                state_change.protocol_fees.unwrap_or_default(),
+            )?;
+            let offer = token_out.get(0).unwrap().clone();
+            // The sender escrowed the full input, return what the offer did not use
+            let unused = msg.token_in.amount.saturating_sub(offer.amount);
+            if !unused.is_zero() {
+                sub_messages.append(&mut send_tokens_coin(
+                    &Addr::unchecked(msg.sender.clone()),
+                    Coin {
+                        denom: msg.token_in.denom.clone(),
+                        amount: unused,
+                    },
+                )?);
+            }
             interchain_pool
-                .add_asset(token_out.get(0).unwrap().clone())
+                .add_asset(Coin {
+                    denom: offer.denom,
+                    amount: offer.amount - protocol_fee,
+                })
                 .map_err(|err| StdError::generic_err(format!("Failed to add asset: {}", err)))?;
             interchain_pool
                 .subtract_asset(msg.token_out)
                 .map_err(|err| StdError::generic_err(format!("Failed to add asset: {}", err)))?;
 
             POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+            record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
             Ok(IbcBasicResponse::new()
                 .add_submessages(sub_messages)
                 .add_attribute("pool_id", msg.pool_id)
                 .add_attribute("action", "swap_asset_acknowledged")
                 .add_attributes(attributes))
         }
+        InterchainMessageType::RampAmp | InterchainMessageType::StopRampAmp => {
+            let state_change: StateChange = from_slice(&packet_data.state_change.unwrap())?;
+            let pool_id = state_change.pool_id.unwrap();
+
+            let mut interchain_pool = POOLS.may_load(deps.storage, &pool_id)?.ok_or_else(|| {
+                ContractError::Std(StdError::generic_err("Pool not found".to_string()))
+            })?;
+            interchain_pool.apply_amp_ramp(state_change.amp_ramp.unwrap())?;
+            POOLS.save(deps.storage, &pool_id, &interchain_pool)?;
+            record_observation(deps.storage, env.block.time.seconds(), &interchain_pool)?;
+
+            Ok(IbcBasicResponse::new()
+                .add_attribute("pool_id", pool_id)
+                .add_attribute("action", "amp_ramp_acknowledged")
+                .add_attributes(attributes))
+        }
+        InterchainMessageType::SetPoolStatus => {
+            let msg: MsgSetPoolStatusRequest = from_binary(&packet_data.data)?;
+            let mut interchain_pool = POOLS.may_load(deps.storage, &msg.pool_id)?.ok_or_else(|| {
+                ContractError::Std(StdError::generic_err("Pool not found".to_string()))
+            })?;
+            // A concurrent change from the counterparty may have superseded this one, in which
+            // case both chains already agree on the newer status
+            if interchain_pool.status.can_transition_to(&msg.status) {
+                interchain_pool.status = msg.status;
+                POOLS.save(deps.storage, &msg.pool_id, &interchain_pool)?;
+                if interchain_pool.status == Active {
+                    resume_deferred_orders(deps.storage, &msg.pool_id)?;
+                }
+            }
+
+            Ok(IbcBasicResponse::new()
+                .add_attribute("pool_id", msg.pool_id)
+                .add_attribute("status", format!("{:?}", interchain_pool.status))
+                .add_attribute("action", "set_pool_status_acknowledged")
+                .add_attributes(attributes))
+        }
+    }
+}
+
+/// Returns the total protocol fee reported in a swap state change.
+fn net_protocol_fee(protocol_fees: &Option<Vec<Coin>>) -> Uint128 {
+    protocol_fees
+        .iter()
+        .flatten()
+        .fold(Uint128::zero(), |acc, fee| acc + fee.amount)
+}
+
+/// Books protocol fees held by this chain and returns their total.
+fn accrue_protocol_fees(
+    storage: &mut dyn Storage,
+    pool_id: &str,
+    protocol_fees: Vec<Coin>,
+) -> Result<Uint128, ContractError> {
+    let mut total = Uint128::zero();
+    for fee in protocol_fees {
+        PROTOCOL_FEES.update(storage, (pool_id, &fee.denom), |accrued| -> StdResult<_> {
+            Ok(accrued.unwrap_or_default() + fee.amount)
+        })?;
+        total += fee.amount;
     }
+    Ok(total)
 }
 
+/// Refunds a packet that was not applied. `record` is its ledger entry, taken before the
+/// entry was settled.
 pub(crate) fn on_packet_failure(
     deps: DepsMut,
     packet: IbcPacket,
     err: String,
+    record: Option<InFlightPacket>,
 ) -> Result<IbcBasicResponse, ContractError> {
     let packet_data: InterchainSwapPacketData = from_binary(&packet.data)?;
-    let submsg = refund_packet_token(deps, packet_data)?;
+    let submsg = refund_packet_token(deps, packet_data, record.as_ref())?;
 
     let res = IbcBasicResponse::new()
         .add_submessages(submsg)
@@ -1252,51 +1657,62 @@ pub(crate) fn on_packet_failure(
     Ok(res)
 }
 
+/// Undoes the local side of a packet that the counterparty did not apply, either because
+/// it timed out or because the counterparty acknowledged an error. Escrowed funds go back
+/// exactly as they were taken and local state written when sending is rolled back.
+/// `escrow` is the packet's ledger entry, which knows who escrowed funds the packet data
+/// does not name.
 pub(crate) fn refund_packet_token(
     deps: DepsMut,
     packet: InterchainSwapPacketData,
+    escrow: Option<&InFlightPacket>,
 ) -> Result<Vec<SubMsg>, ContractError> {
     match packet.r#type {
         InterchainMessageType::Unspecified => Ok(vec![]),
         InterchainMessageType::MakePool => {
             // remove from map and refund make tokens
             let msg: MsgMakePoolRequest = from_binary(&packet.data)?;
-            let mut tokens: [Coin; 2] = Default::default();
-            tokens[0] = msg.liquidity[0].balance.clone();
-            tokens[1] = msg.liquidity[1].balance.clone();
+            let state_change: StateChange = from_slice(&packet.state_change.unwrap())?;
+            let pool_id = state_change.pool_id.unwrap();
 
-            let pool_id =
-                get_pool_id_with_tokens(&tokens, msg.source_chain_id, msg.destination_chain_id);
-            let sub_messages = send_tokens_coin(&Addr::unchecked(msg.creator), tokens[0].clone())?;
+            let mut sub_messages = vec![];
+            for asset in msg.liquidity {
+                if asset.side == PoolSide::SOURCE {
+                    sub_messages.append(&mut send_tokens_coin(
+                        &Addr::unchecked(msg.creator.clone()),
+                        asset.balance,
+                    )?);
+                }
+            }
 
-            POOLS.remove(deps.storage, &pool_id);
-            POOL_TOKENS_LIST.remove(deps.storage, &pool_id);
+            // The pool was only ever initialized here
+            if let Some(pool) = POOLS.may_load(deps.storage, &pool_id)? {
+                if pool.status == Initialized {
+                    POOLS.remove(deps.storage, &pool_id);
+                    POOLS_BY_CHANNEL.remove(deps.storage, (&pool.counter_party_channel, &pool_id));
+                    POOL_TOKENS_LIST.remove(deps.storage, &pool_id);
+                }
+            }
 
             Ok(sub_messages)
         }
         InterchainMessageType::TakePool => {
+            // The pool is untouched until the take is acknowledged, return what the taker escrowed
             let msg: MsgTakePoolRequest = from_binary(&packet.data)?;
-            // load pool throw error if found
-            let interchain_pool_temp = POOLS.may_load(deps.storage, &msg.pool_id)?;
-            let interchain_pool;
-            if let Some(pool) = interchain_pool_temp {
-                interchain_pool = pool;
-            } else {
-                return Err(ContractError::Std(StdError::generic_err(
-                    "Pool not found".to_string(),
-                )));
-            }
+            let interchain_pool = POOLS.load(deps.storage, &msg.pool_id)?;
 
-            let mut tokens: [Coin; 2] = Default::default();
-            tokens[0] = interchain_pool.assets[0].balance.clone();
-            tokens[1] = interchain_pool.assets[1].balance.clone();
-
-            let sub_messages = send_tokens_coin(&Addr::unchecked(msg.creator), tokens[1].clone())?;
+            let mut sub_messages = vec![];
+            for asset in interchain_pool.find_assets_by_side(PoolSide::SOURCE) {
+                sub_messages.append(&mut send_tokens_coin(
+                    &Addr::unchecked(interchain_pool.destination_creator.clone()),
+                    asset.balance,
+                )?);
+            }
 
             Ok(sub_messages)
         }
         InterchainMessageType::CancelPool => {
-            // do nothing
+            // Nothing is escrowed when cancelling, the creator is refunded on acknowledgement
             Ok(vec![])
         }
         InterchainMessageType::SingleAssetDeposit => {
@@ -1307,78 +1723,695 @@ pub(crate) fn refund_packet_token(
         }
         InterchainMessageType::MakeMultiDeposit => {
             let msg: MsgMakeMultiAssetDepositRequest = from_binary(&packet.data)?;
-            let sub_messages = send_tokens_coin(
-                &Addr::unchecked(msg.deposits[0].clone().sender),
-                msg.deposits.get(0).unwrap().clone().balance,
-            )?;
-            let ac_key = msg.deposits[0].sender.clone()
-                + "-"
-                + &msg.pool_id.clone()
-                + "-"
-                + &msg.deposits[1].sender.clone();
-
             let state_change: StateChange = from_slice(&packet.state_change.unwrap())?;
-            let key = msg.pool_id + &state_change.multi_deposit_order_id.unwrap();
+            let key = msg.pool_id.clone() + "-" + &state_change.multi_deposit_order_id.unwrap();
+            let interchain_pool = POOLS.load(deps.storage, &msg.pool_id)?;
+            let (source_maker, destination_taker) =
+                get_depositors(&interchain_pool, &msg.deposits, PoolSide::SOURCE)?;
 
-            let mut config = CONFIG.load(deps.storage)?;
-            config.counter -= 1;
-            MULTI_ASSET_DEPOSIT_ORDERS.remove(deps.storage, key);
-
-            if let Ok(Some(_active_order)) = ACTIVE_ORDERS.may_load(deps.storage, ac_key.clone()) {
-                ACTIVE_ORDERS.remove(deps.storage, ac_key);
+            let mut sub_messages = vec![];
+            for deposit in get_coins_by_side(
+                &interchain_pool,
+                &get_coins_from_deposits(msg.deposits.clone()),
+                PoolSide::SOURCE,
+            ) {
+                sub_messages.append(&mut send_tokens_coin(
+                    &Addr::unchecked(source_maker.clone()),
+                    deposit,
+                )?);
             }
-            CONFIG.save(deps.storage, &config)?;
+
+            // Order ids are never reused, so the counter is left as is
+            let ac_key = source_maker + "-" + &msg.pool_id + "-" + &destination_taker;
+            multi_asset_deposit_orders().remove(deps.storage, key)?;
+            ACTIVE_ORDERS.remove(deps.storage, ac_key);
             Ok(sub_messages)
         }
         InterchainMessageType::TakeMultiDeposit => {
             let msg: MsgTakeMultiAssetDepositRequest = from_binary(&packet.data)?;
 
             let key = msg.pool_id.clone() + "-" + &msg.order_id;
-            let multi_asset_order_temp = MULTI_ASSET_DEPOSIT_ORDERS.may_load(deps.storage, key)?;
+            let multi_asset_order_temp = multi_asset_deposit_orders().may_load(deps.storage, key)?;
             let multi_asset_order;
             if let Some(order) = multi_asset_order_temp {
                 multi_asset_order = order;
-                // multi_asset_order.status = OrderStatus::Complete;
             } else {
                 return Err(ContractError::ErrOrderNotFound);
             }
 
-            let sub_messages = send_tokens_coin(
-                &Addr::unchecked(msg.sender),
-                multi_asset_order.deposits.get(1).unwrap().clone(),
-            )?;
+            // The order stays pending here, return what the taker escrowed
+            let interchain_pool = POOLS.load(deps.storage, &msg.pool_id)?;
+            let mut sub_messages = vec![];
+            for deposit in get_coins_by_side(
+                &interchain_pool,
+                &multi_asset_order.deposits,
+                PoolSide::SOURCE,
+            ) {
+                sub_messages.append(&mut send_tokens_coin(
+                    &Addr::unchecked(multi_asset_order.destination_taker.clone()),
+                    deposit,
+                )?);
+            }
 
             Ok(sub_messages)
         }
         InterchainMessageType::CancelMultiDeposit => {
-            // do nothing
+            // Nothing is escrowed when cancelling, the maker is refunded on acknowledgement.
+            // A sweep takes the order off the expiry queue when sending the cancel, so it is
+            // queued again to be retried by a later sweep.
+            let msg: MsgCancelMultiAssetDepositRequest = from_binary(&packet.data)?;
+            let key = msg.pool_id.clone() + "-" + &msg.order_id;
+            let deferred = DEFERRED_EXPIRIES.has(deps.storage, (&msg.pool_id, &key));
+            if let Some(order) = multi_asset_deposit_orders().may_load(deps.storage, key.clone())? {
+                if order.status == OrderStatus::Pending && order.expires_at != 0 && !deferred {
+                    ORDER_EXPIRIES.save(deps.storage, (order.expires_at, key), &Empty {})?;
+                }
+            }
             Ok(vec![])
         }
         InterchainMessageType::MultiWithdraw => {
             let msg: MsgMultiAssetWithdrawRequest = from_binary(&packet.data)?;
-            // Send tokens (cw20) to the sender
-            let lp_token = POOL_TOKENS_LIST
-                .may_load(deps.storage, &msg.pool_id)?
-                .unwrap();
-            let sub_message = send_tokens_cw20(msg.receiver, lp_token, msg.pool_token.amount)?;
+            // Return the escrowed pool tokens (cw20) to the owner they were transferred from.
+            // Packets sent before the ledger existed have no record, the receiver is all
+            // that is left to go by.
+            let (owner, amount) = match escrow {
+                Some(record) => (
+                    record.sender.clone(),
+                    record
+                        .escrow
+                        .iter()
+                        .filter(|token| token.denom == msg.pool_token.denom)
+                        .map(|token| token.amount)
+                        .sum(),
+                ),
+                None => (msg.receiver, msg.pool_token.amount),
+            };
+            let lp_token = POOL_TOKENS_LIST.load(deps.storage, &msg.pool_id)?;
+            let sub_message = send_tokens_cw20(owner, lp_token, amount)?;
 
             Ok(sub_message)
         }
-        InterchainMessageType::LeftSwap => {
+        InterchainMessageType::SingleAssetWithdraw => {
+            let msg: MsgSingleAssetWithdrawRequest = from_binary(&packet.data)?;
+            // Return the escrowed pool tokens (cw20) to the sender
+            let lp_token = POOL_TOKENS_LIST.load(deps.storage, &msg.pool_coin.denom)?;
+            let sub_message = send_tokens_cw20(msg.sender, lp_token, msg.pool_coin.amount)?;
+
+            Ok(sub_message)
+        }
+        InterchainMessageType::LeftSwap | InterchainMessageType::RightSwap => {
+            // Both swap types escrow the full input, a right swap's offer is only an estimate
             let msg: MsgSwapRequest = from_binary(&packet.data)?;
             let sub_messages = send_tokens_coin(&Addr::unchecked(msg.sender), msg.token_in)?;
 
             Ok(sub_messages)
         }
-        InterchainMessageType::RightSwap => {
-            //let state_change = packet.state_change.unwrap();
-            let state_change: StateChange = from_slice(&packet.state_change.unwrap())?;
-            let msg: MsgSwapRequest = from_binary(&packet.data)?;
-            let sub_messages = send_tokens_coin(
-                &Addr::unchecked(msg.sender),
-                state_change.out_tokens.unwrap().get(0).unwrap().clone(),
-            )?;
-            Ok(sub_messages)
+        InterchainMessageType::RampAmp | InterchainMessageType::StopRampAmp => {
+            // nothing was escrowed, the pool keeps its current curve
+            Ok(vec![])
         }
+        InterchainMessageType::SetPoolStatus => {
+            // nothing was escrowed, the pool keeps its current status
+            Ok(vec![])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::PoolCurve;
+    use crate::msg::{DepositAsset, MsgCancelPoolRequest};
+    use crate::state::Config;
+    use crate::testing::mock_pair_pool;
+    use cosmwasm_std::testing::{mock_dependencies, MockApi, MockQuerier, MockStorage};
+    use cosmwasm_std::{coin, BankMsg, CosmosMsg, IbcMsg, OwnedDeps};
+    use cw20::Cw20ExecuteMsg;
+
+    const POOL_ID: &str = "pool";
+
+    fn pool(status: crate::market::PoolStatus) -> InterchainLiquidityPool {
+        InterchainLiquidityPool {
+            status,
+            supply: coin(0, POOL_ID),
+            ..mock_pair_pool(POOL_ID, 1_000)
+        }
+    }
+
+    fn setup(status: crate::market::PoolStatus) -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+        CONFIG
+            .save(
+                deps.as_mut().storage,
+                &Config {
+                    counter: 3,
+                    token_code_id: 1,
+                    admin: "admin".to_string(),
+                    router: "".to_string(),
+                    protocol_fee_rate: 0,
+                    fee_collector: "admin".to_string(),
+                    guardian: "guardian".to_string(),
+                    paused: false,
+                    disabled_messages: vec![],
+                },
+            )
+            .unwrap();
+        POOLS
+            .save(deps.as_mut().storage, POOL_ID, &pool(status))
+            .unwrap();
+        POOL_TOKENS_LIST
+            .save(deps.as_mut().storage, POOL_ID, &"lp_token".to_string())
+            .unwrap();
+        deps
+    }
+
+    fn packet<T: Serialize>(
+        r#type: InterchainMessageType,
+        msg: &T,
+        state_change: Option<StateChange>,
+    ) -> InterchainSwapPacketData {
+        InterchainSwapPacketData {
+            r#type,
+            data: to_binary(msg).unwrap(),
+            state_change: state_change.map(|state_change| to_binary(&state_change).unwrap()),
+            memo: None,
+        }
+    }
+
+    fn ibc_packet(data: &InterchainSwapPacketData, sequence: u64) -> IbcPacket {
+        use cosmwasm_std::{IbcEndpoint, IbcTimeout, Timestamp};
+
+        let endpoint = IbcEndpoint {
+            port_id: "port".to_string(),
+            channel_id: "channel-0".to_string(),
+        };
+        let timeout = IbcTimeout::with_timestamp(Timestamp::from_seconds(0));
+        IbcPacket::new(to_binary(data).unwrap(), endpoint.clone(), endpoint, sequence, timeout)
+    }
+
+    fn state_change() -> StateChange {
+        StateChange {
+            in_tokens: None,
+            out_tokens: None,
+            pool_tokens: None,
+            pool_id: None,
+            multi_deposit_order_id: None,
+            source_chain_id: None,
+            shares: None,
+            amp_ramp: None,
+            protocol_fees: None,
+        }
+    }
+
+    fn bank_send(to: &str, amount: Coin) -> CosmosMsg {
+        CosmosMsg::Bank(BankMsg::Send {
+            to_address: to.to_string(),
+            amount: vec![amount],
+        })
+    }
+
+    fn lp_transfer(to: &str, amount: u128) -> CosmosMsg {
+        CosmosMsg::Wasm(WasmMsg::Execute {
+            contract_addr: "lp_token".to_string(),
+            msg: to_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: to.to_string(),
+                amount: Uint128::new(amount),
+            })
+            .unwrap(),
+            funds: vec![],
+        })
+    }
+
+    fn messages(sub_messages: Vec<SubMsg>) -> Vec<CosmosMsg> {
+        sub_messages.into_iter().map(|sub_msg| sub_msg.msg).collect()
+    }
+
+    fn swap(swap_type: SwapMsgType) -> MsgSwapRequest {
+        MsgSwapRequest {
+            swap_type,
+            sender: "trader".to_string(),
+            pool_id: POOL_ID.to_string(),
+            token_in: coin(100, "aside"),
+            token_out: coin(90, "bside"),
+            slippage: 0,
+            recipient: "recipient".to_string(),
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            route: None,
+            memo: None,
+        }
+    }
+
+    #[test]
+    fn test_refund_make_pool() {
+        let mut deps = setup(Initialized);
+        let msg = MsgMakePoolRequest {
+            source_port: "port".to_string(),
+            source_channel: "channel-0".to_string(),
+            source_chain_id: "a".to_string(),
+            destination_chain_id: "b".to_string(),
+            counterparty_channel: "channel-1".to_string(),
+            creator: "maker".to_string(),
+            counterparty_creator: "taker".to_string(),
+            liquidity: pool(Initialized).assets,
+            swap_fee: 30,
+            curve: PoolCurve::Weighted,
+            dynamic_fee: None,
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+        };
+        let packet = packet(
+            InterchainMessageType::MakePool,
+            &msg,
+            Some(StateChange {
+                pool_id: Some(POOL_ID.to_string()),
+                ..state_change()
+            }),
+        );
+
+        let refund = refund_packet_token(deps.as_mut(), packet.clone(), None).unwrap();
+        assert_eq!(messages(refund), vec![bank_send("maker", coin(1_000, "aside"))]);
+        assert!(!POOLS.has(deps.as_ref().storage, POOL_ID));
+        assert!(!POOL_TOKENS_LIST.has(deps.as_ref().storage, POOL_ID));
+
+        // A pool that went live is never removed by a refund
+        let mut deps = setup(Active);
+        refund_packet_token(deps.as_mut(), packet, None).unwrap();
+        assert!(POOLS.has(deps.as_ref().storage, POOL_ID));
+    }
+
+    #[test]
+    fn test_refund_take_and_cancel_pool() {
+        let mut deps = setup(Initialized);
+        let take = MsgTakePoolRequest {
+            counter_creator: "maker".to_string(),
+            creator: "someone".to_string(),
+            pool_id: POOL_ID.to_string(),
+            lp_allocation: LPAllocation::MakerChain,
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+        };
+        let refund = refund_packet_token(
+            deps.as_mut(),
+            packet(InterchainMessageType::TakePool, &take, Some(state_change())),
+            None,
+        )
+        .unwrap();
+        assert_eq!(messages(refund), vec![bank_send("taker", coin(1_000, "aside"))]);
+
+        let cancel = MsgCancelPoolRequest {
+            pool_id: POOL_ID.to_string(),
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+        };
+        let refund = refund_packet_token(
+            deps.as_mut(),
+            packet(InterchainMessageType::CancelPool, &cancel, None),
+            None,
+        )
+        .unwrap();
+        assert!(refund.is_empty());
+        assert_eq!(POOLS.load(deps.as_ref().storage, POOL_ID).unwrap(), pool(Initialized));
+    }
+
+    #[test]
+    fn test_refund_multi_asset_deposit() {
+        let mut deps = setup(Active);
+        let order = MultiAssetDepositOrder {
+            id: "order".to_string(),
+            pool_id: POOL_ID.to_string(),
+            chain_id: "a".to_string(),
+            source_maker: "maker".to_string(),
+            destination_taker: "taker".to_string(),
+            deposits: vec![coin(10, "aside"), coin(10, "bside")],
+            status: OrderStatus::Pending,
+            created_at: 0,
+            expires_at: 0,
+        };
+        let key = format!("{}-{}", POOL_ID, order.id);
+        multi_asset_deposit_orders()
+            .save(deps.as_mut().storage, key.clone(), &order)
+            .unwrap();
+        ACTIVE_ORDERS
+            .save(deps.as_mut().storage, format!("maker-{}-taker", POOL_ID), &order)
+            .unwrap();
+
+        // Taking it back leaves the pending order in place
+        let take = MsgTakeMultiAssetDepositRequest {
+            sender: "someone".to_string(),
+            pool_id: POOL_ID.to_string(),
+            order_id: order.id.clone(),
+            lp_allocation: LPAllocation::MakerChain,
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+        };
+        let refund = refund_packet_token(
+            deps.as_mut(),
+            packet(InterchainMessageType::TakeMultiDeposit, &take, Some(state_change())),
+            None,
+        )
+        .unwrap();
+        assert_eq!(messages(refund), vec![bank_send("taker", coin(10, "aside"))]);
+        assert_eq!(
+            multi_asset_deposit_orders().load(deps.as_ref().storage, key.clone()).unwrap(),
+            order
+        );
+
+        let make = MsgMakeMultiAssetDepositRequest {
+            pool_id: POOL_ID.to_string(),
+            deposits: vec![
+                DepositAsset {
+                    sender: "maker".to_string(),
+                    balance: coin(10, "aside"),
+                },
+                DepositAsset {
+                    sender: "taker".to_string(),
+                    balance: coin(10, "bside"),
+                },
+            ],
+            chain_id: "a".to_string(),
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+            expires_at: None,
+        };
+        let refund = refund_packet_token(
+            deps.as_mut(),
+            packet(
+                InterchainMessageType::MakeMultiDeposit,
+                &make,
+                Some(StateChange {
+                    multi_deposit_order_id: Some(order.id.clone()),
+                    ..state_change()
+                }),
+            ),
+            None,
+        )
+        .unwrap();
+        assert_eq!(messages(refund), vec![bank_send("maker", coin(10, "aside"))]);
+        assert!(!multi_asset_deposit_orders().has(deps.as_ref().storage, key));
+        assert!(!ACTIVE_ORDERS.has(deps.as_ref().storage, format!("maker-{}-taker", POOL_ID)));
+        assert_eq!(CONFIG.load(deps.as_ref().storage).unwrap().counter, 3);
+    }
+
+    #[test]
+    fn test_refund_deposits_and_withdrawals() {
+        let mut deps = setup(Active);
+        let deposit = MsgSingleAssetDepositRequest {
+            pool_id: POOL_ID.to_string(),
+            sender: "depositor".to_string(),
+            token: coin(25, "aside"),
+            lp_allocation: LPAllocation::MakerChain,
+            lp_taker: "".to_string(),
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+        };
+        let refund = refund_packet_token(
+            deps.as_mut(),
+            packet(InterchainMessageType::SingleAssetDeposit, &deposit, Some(state_change())),
+            None,
+        )
+        .unwrap();
+        assert_eq!(messages(refund), vec![bank_send("depositor", coin(25, "aside"))]);
+
+        // The pool tokens go back to the owner they were taken from, not the receiver
+        let withdraw = MsgMultiAssetWithdrawRequest {
+            pool_id: POOL_ID.to_string(),
+            receiver: "receiver".to_string(),
+            counterparty_receiver: "other".to_string(),
+            pool_token: coin(7, POOL_ID),
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+        };
+        let data = packet(InterchainMessageType::MultiWithdraw, &withdraw, Some(state_change()));
+        let record = InFlightPacket {
+            channel_id: "channel-0".to_string(),
+            sequence: 1,
+            packet_type: InterchainMessageType::MultiWithdraw,
+            sender: "withdrawer".to_string(),
+            pool_id: POOL_ID.to_string(),
+            escrow: vec![coin(7, POOL_ID)],
+            data: to_binary(&data).unwrap(),
+            status: crate::state::PacketStatus::Pending,
+            sent_at: 0,
+        };
+        let refund = refund_packet_token(deps.as_mut(), data.clone(), Some(&record)).unwrap();
+        assert_eq!(messages(refund), vec![lp_transfer("withdrawer", 7)]);
+        let refund = refund_packet_token(deps.as_mut(), data, None).unwrap();
+        assert_eq!(messages(refund), vec![lp_transfer("receiver", 7)]);
+
+        let withdraw = MsgSingleAssetWithdrawRequest {
+            sender: "withdrawer".to_string(),
+            denom_out: "aside".to_string(),
+            pool_coin: coin(5, POOL_ID),
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+        };
+        let refund = refund_packet_token(
+            deps.as_mut(),
+            packet(InterchainMessageType::SingleAssetWithdraw, &withdraw, Some(state_change())),
+            None,
+        )
+        .unwrap();
+        assert_eq!(messages(refund), vec![lp_transfer("withdrawer", 5)]);
+        assert_eq!(POOLS.load(deps.as_ref().storage, POOL_ID).unwrap(), pool(Active));
+    }
+
+    #[test]
+    fn test_refund_swaps_return_escrowed_input() {
+        let mut deps = setup(Active);
+        // The offer in a right swap's state change is below the escrowed input
+        let offer = StateChange {
+            out_tokens: Some(vec![coin(95, "aside")]),
+            ..state_change()
+        };
+        for (r#type, swap_type) in [
+            (InterchainMessageType::LeftSwap, SwapMsgType::LEFT),
+            (InterchainMessageType::RightSwap, SwapMsgType::RIGHT),
+        ] {
+            let refund = refund_packet_token(
+                deps.as_mut(),
+                packet(r#type, &swap(swap_type), Some(offer.clone())),
+                None,
+            )
+            .unwrap();
+            assert_eq!(messages(refund), vec![bank_send("trader", coin(100, "aside"))]);
+        }
+        assert_eq!(POOLS.load(deps.as_ref().storage, POOL_ID).unwrap(), pool(Active));
+    }
+
+    #[test]
+    fn test_pool_status_sync() {
+        use crate::market::PoolStatus::{Deprecated, Paused};
+        use cosmwasm_std::testing::mock_env;
+        use cosmwasm_std::{IbcEndpoint, IbcTimeout};
+
+        let mut deps = setup(Active);
+        let receive = |deps: DepsMut, data: InterchainSwapPacketData| {
+            let endpoint = IbcEndpoint {
+                port_id: "port".to_string(),
+                channel_id: "channel-0".to_string(),
+            };
+            let timeout = IbcTimeout::with_timestamp(mock_env().block.time);
+            let data = to_binary(&data).unwrap();
+            let packet = IbcPacket::new(data, endpoint.clone(), endpoint, 1, timeout);
+            do_ibc_packet_receive(deps, mock_env(), &packet)
+        };
+        let set_status = |status| {
+            let msg = MsgSetPoolStatusRequest {
+                pool_id: POOL_ID.to_string(),
+                status,
+                timeout_height: 0,
+                timeout_timestamp: 0,
+                memo: None,
+            };
+            packet(InterchainMessageType::SetPoolStatus, &msg, None)
+        };
+        let status = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            POOLS.load(deps.as_ref().storage, POOL_ID).unwrap().status
+        };
+
+        receive(deps.as_mut(), set_status(Paused)).unwrap();
+        assert_eq!(status(&deps), Paused);
+        let swap_packet = packet(
+            InterchainMessageType::LeftSwap,
+            &swap(SwapMsgType::LEFT),
+            Some(state_change()),
+        );
+        assert_eq!(
+            receive(deps.as_mut(), swap_packet).unwrap_err(),
+            ContractError::NotReadyForSwap
+        );
+
+        receive(deps.as_mut(), set_status(Deprecated)).unwrap();
+        assert_eq!(
+            receive(deps.as_mut(), set_status(Active)).unwrap_err(),
+            ContractError::InvalidStatus
+        );
+
+        // A pause acknowledged after the counterparty deprecated the pool is superseded
+        let endpoint = IbcEndpoint {
+            port_id: "port".to_string(),
+            channel_id: "channel-0".to_string(),
+        };
+        let ack = IbcPacket::new(
+            to_binary(&set_status(Paused)).unwrap(),
+            endpoint.clone(),
+            endpoint,
+            2,
+            IbcTimeout::with_timestamp(mock_env().block.time),
+        );
+        on_packet_success(deps.as_mut(), mock_env(), ack).unwrap();
+        assert_eq!(status(&deps), Deprecated);
+    }
+
+    #[test]
+    fn test_swap_ack_accrues_protocol_fees() {
+        use cosmwasm_std::testing::mock_env;
+
+        let mut deps = setup(Active);
+        let acknowledge = |deps: DepsMut, protocol_fee: u128, sequence| {
+            let state_change = StateChange {
+                out_tokens: Some(vec![coin(90, "bside")]),
+                protocol_fees: Some(vec![coin(protocol_fee, "aside")]),
+                ..state_change()
+            };
+            let data = packet(
+                InterchainMessageType::LeftSwap,
+                &swap(SwapMsgType::LEFT),
+                Some(state_change),
+            );
+            on_packet_success(deps, mock_env(), ibc_packet(&data, sequence)).unwrap();
+        };
+        let accrued = |deps: &OwnedDeps<MockStorage, MockApi, MockQuerier>| {
+            PROTOCOL_FEES.may_load(deps.as_ref().storage, (POOL_ID, "aside")).unwrap()
+        };
+
+        // The protocol share stays out of the pool and accrues for the collector
+        acknowledge(deps.as_mut(), 2, 1);
+        acknowledge(deps.as_mut(), 1, 2);
+        assert_eq!(accrued(&deps), Some(Uint128::new(3)));
+        let interchain_pool = POOLS.load(deps.as_ref().storage, POOL_ID).unwrap();
+        assert_eq!(interchain_pool.assets[0].balance, coin(1_197, "aside"));
+        assert_eq!(interchain_pool.assets[1].balance, coin(820, "bside"));
+
+        // With a zero protocol fee rate the whole input goes to the pool
+        acknowledge(deps.as_mut(), 0, 3);
+        assert_eq!(accrued(&deps), Some(Uint128::new(3)));
+        let interchain_pool = POOLS.load(deps.as_ref().storage, POOL_ID).unwrap();
+        assert_eq!(interchain_pool.assets[0].balance, coin(1_297, "aside"));
+    }
+
+    /// Receives the first hop of a route whose remaining hops are `requests`.
+    fn receive_routed_swap(
+        deps: DepsMut,
+        requests: Vec<crate::msg::SwapRequest>,
+    ) -> IbcReceiveResponse {
+        use cosmwasm_std::testing::mock_env;
+
+        let msg = MsgSwapRequest {
+            sender: "chaina1trader".to_string(),
+            recipient: "chainb1trader".to_string(),
+            token_in: coin(100, "bside"),
+            token_out: coin(0, "aside"),
+            route: Some(SwapRoute {
+                requests,
+                minimum_receive: Some(Uint128::one()),
+            }),
+            ..swap(SwapMsgType::LEFT)
+        };
+        let state_change = StateChange {
+            out_tokens: Some(vec![coin(90, "aside")]),
+            ..state_change()
+        };
+        let data = packet(InterchainMessageType::LeftSwap, &msg, Some(state_change.clone()));
+        on_received_swap(deps, mock_env(), &ibc_packet(&data, 1), msg, state_change).unwrap()
+    }
+
+    fn hop(pool_id: &str) -> crate::msg::SwapRequest {
+        crate::msg::SwapRequest {
+            pool_id: pool_id.to_string(),
+            asset_in: "aside".to_string(),
+            asset_out: "cside".to_string(),
+            contract_address: "".to_string(),
+            recipient: None,
+        }
+    }
+
+    #[test]
+    fn test_two_hop_route() {
+        use crate::state::OUTGOING_PACKETS;
+
+        let mut deps = setup(Active);
+        let mut next_pool = pool(Active);
+        next_pool.id = "pool2".to_string();
+        next_pool.counter_party_channel = "channel-1".to_string();
+        next_pool.assets[1].balance.denom = "cside".to_string();
+        POOLS.save(deps.as_mut().storage, "pool2", &next_pool).unwrap();
+
+        // The output of the first hop is offered to the second over the next pool's channel
+        let second_hop = crate::msg::SwapRequest {
+            recipient: Some("chainc1trader".to_string()),
+            ..hop("pool2")
+        };
+        let res = receive_routed_swap(deps.as_mut(), vec![second_hop]);
+        assert_eq!(res.messages.len(), 1);
+        let (channel_id, data) = match &res.messages[0].msg {
+            CosmosMsg::Ibc(IbcMsg::SendPacket {
+                channel_id, data, ..
+            }) => (channel_id.clone(), data.clone()),
+            msg => panic!("unexpected message {:?}", msg),
+        };
+        assert_eq!(channel_id, "channel-1");
+        let data: InterchainSwapPacketData = from_binary(&data).unwrap();
+        let next_msg: MsgSwapRequest = from_binary(&data.data).unwrap();
+        assert_eq!(next_msg.sender, "chainb1trader");
+        assert_eq!(next_msg.recipient, "chainc1trader");
+        assert_eq!(next_msg.token_in, coin(90, "aside"));
+        assert_eq!(next_msg.token_out, coin(1, "cside"));
+        let pool = POOLS.load(deps.as_ref().storage, POOL_ID).unwrap();
+        assert_eq!(pool.assets[0].balance, coin(910, "aside"));
+
+        // The escrow of the second hop belongs to an account on this chain, which can retry
+        // or refund it after a timeout
+        let record = OUTGOING_PACKETS.front(deps.as_ref().storage).unwrap().unwrap();
+        assert_eq!(record.sender, "chainb1trader");
+        assert_eq!(record.escrow, vec![coin(90, "aside")]);
+
+        // A failing second hop refunds its input on this chain, to the first hop's recipient
+        let failure = "error".to_string();
+        let refund = on_packet_failure(deps.as_mut(), ibc_packet(&data, 2), failure, None)
+            .unwrap()
+            .messages;
+        assert_eq!(messages(refund), vec![bank_send("chainb1trader", coin(90, "aside"))]);
+    }
+
+    #[test]
+    fn test_router_failure_pays_out_locally() {
+        use crate::contract::reply;
+        use crate::utils::ROUTER_REPLY_ID;
+        use cosmwasm_std::testing::mock_env;
+        use cosmwasm_std::{Reply, SubMsgResult};
+
+        let mut deps = setup(Active);
+        let res = receive_routed_swap(deps.as_mut(), vec![hop("elsewhere")]);
+        assert_eq!(res.messages.len(), 1);
+        assert_eq!(res.messages[0].id, ROUTER_REPLY_ID);
+
+        let failed = Reply {
+            id: ROUTER_REPLY_ID,
+            result: SubMsgResult::Err("no route".to_string()),
+        };
+        let res = reply(deps.as_mut(), mock_env(), failed).unwrap();
+        let payout = bank_send("chainb1trader", coin(90, "aside"));
+        assert_eq!(messages(res.messages), vec![payout]);
+        assert!(ROUTED_OUTPUTS.is_empty(deps.as_ref().storage).unwrap());
     }
 }
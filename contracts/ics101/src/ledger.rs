@@ -0,0 +1,461 @@
+use cosmwasm_std::{
+    from_binary, to_binary, Coin, Deps, DepsMut, Env, IbcMsg, IbcPacket, IbcTimeout, MessageInfo,
+    Order, Response, StdError, StdResult, Storage, SubMsg, SubMsgResponse, SubMsgResult,
+};
+use cw_storage_plus::Bound;
+
+use crate::{
+    contract::{packet_timeout, swap_packet},
+    error::ContractError,
+    interchainswap_handler::refund_packet_token,
+    msg::{MsgSwapRequest, PendingPacketListResponse},
+    state::{in_flight_packets, InFlightPacket, PacketStatus, CONFIG, OUTGOING_PACKETS, POOLS},
+    types::{InterchainMessageType, InterchainSwapPacketData},
+    utils::PACKET_SENT_REPLY_ID,
+};
+
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+
+/// Funds held by this contract for an outgoing packet, and who gets them back on a refund.
+pub struct Escrow {
+    pub sender: String,
+    pub pool_id: String,
+    pub funds: Vec<Coin>,
+}
+
+/// Message types that can be sent again after a timeout instead of being refunded.
+/// They carry no pricing that could have gone stale, except swaps, which are re-priced.
+/// Order cancels are not held, a timed out one puts its order back on the expiry queue.
+pub fn is_retryable(packet_type: &InterchainMessageType) -> bool {
+    matches!(
+        packet_type,
+        InterchainMessageType::MakePool
+            | InterchainMessageType::CancelPool
+            | InterchainMessageType::LeftSwap
+            | InterchainMessageType::RightSwap
+            | InterchainMessageType::SetPoolStatus
+    )
+}
+
+/// Sends a packet and records it in the in-flight ledger. The sequence is only known once
+/// the packet is sent, so the record waits in a queue for the reply carrying it.
+/// Fails while the contract is paused or the packet's message type is disabled.
+pub(crate) fn send_packet(
+    storage: &mut dyn Storage,
+    env: &Env,
+    channel_id: String,
+    packet: &InterchainSwapPacketData,
+    timeout: IbcTimeout,
+    escrow: Escrow,
+) -> Result<SubMsg, ContractError> {
+    let config = CONFIG.load(storage)?;
+    if config.paused {
+        return Err(ContractError::Paused);
+    }
+    if config.disabled_messages.contains(&packet.r#type) {
+        return Err(ContractError::MessageDisabled {
+            message_type: format!("{:?}", packet.r#type),
+        });
+    }
+
+    let data = to_binary(packet)?;
+    OUTGOING_PACKETS.push_back(
+        storage,
+        &InFlightPacket {
+            channel_id: channel_id.clone(),
+            sequence: 0,
+            packet_type: packet.r#type.clone(),
+            sender: escrow.sender,
+            pool_id: escrow.pool_id,
+            escrow: escrow.funds,
+            data: data.clone(),
+            status: PacketStatus::Pending,
+            sent_at: env.block.time.seconds(),
+        },
+    )?;
+
+    Ok(SubMsg::reply_on_success(
+        IbcMsg::SendPacket {
+            channel_id,
+            data,
+            timeout,
+        },
+        PACKET_SENT_REPLY_ID,
+    ))
+}
+
+/// Files the oldest queued packet under the sequence reported by its send.
+pub(crate) fn on_packet_sent(
+    storage: &mut dyn Storage,
+    result: SubMsgResult,
+) -> Result<Response, ContractError> {
+    let mut packet = OUTGOING_PACKETS
+        .pop_front(storage)?
+        .ok_or_else(|| StdError::generic_err("No packet is waiting for its sequence"))?;
+    let response = result.into_result().map_err(StdError::generic_err)?;
+    packet.sequence = sent_packet_sequence(&response)?;
+
+    in_flight_packets().save(
+        storage,
+        (packet.channel_id.clone(), packet.sequence),
+        &packet,
+    )?;
+    Ok(Response::new()
+        .add_attribute("packet_channel", packet.channel_id)
+        .add_attribute("packet_sequence", packet.sequence.to_string()))
+}
+
+/// Sequence of the packet a submessage sent, read from its `send_packet` event.
+pub(crate) fn sent_packet_sequence(response: &SubMsgResponse) -> StdResult<u64> {
+    response
+        .events
+        .iter()
+        .filter(|event| event.ty == "send_packet")
+        .flat_map(|event| event.attributes.iter())
+        .find(|attribute| attribute.key == "packet_sequence")
+        .and_then(|attribute| attribute.value.parse().ok())
+        .ok_or_else(|| StdError::generic_err("Sent packet has no sequence"))
+}
+
+/// Ledger entry of a sent packet, None for packets sent before the ledger existed.
+pub(crate) fn load_packet(
+    storage: &dyn Storage,
+    packet: &IbcPacket,
+) -> StdResult<Option<InFlightPacket>> {
+    in_flight_packets().may_load(storage, (packet.src.channel_id.clone(), packet.sequence))
+}
+
+/// Drops the ledger entry of a packet that was acknowledged or refunded.
+pub(crate) fn settle_packet(storage: &mut dyn Storage, packet: &IbcPacket) -> StdResult<()> {
+    in_flight_packets().remove(storage, (packet.src.channel_id.clone(), packet.sequence))
+}
+
+/// Keeps the escrow of a timed out packet for a retry when its type allows one.
+/// Returns false when the packet has to be refunded right away.
+pub(crate) fn hold_timed_out_packet(
+    storage: &mut dyn Storage,
+    packet: &IbcPacket,
+) -> StdResult<bool> {
+    let key = (packet.src.channel_id.clone(), packet.sequence);
+    match in_flight_packets().may_load(storage, key.clone())? {
+        Some(mut record) if is_retryable(&record.packet_type) => {
+            record.status = PacketStatus::TimedOut;
+            in_flight_packets().save(storage, key, &record)?;
+            Ok(true)
+        }
+        Some(_) => {
+            in_flight_packets().remove(storage, key)?;
+            Ok(false)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Sends a timed out packet again with a new timeout. Swaps are re-priced against the
+/// pool as it is now and must still meet their original slippage bound.
+pub fn retry_packet(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    sequence: u64,
+    timeout_height: u64,
+    timeout_timestamp: u64,
+) -> Result<Response, ContractError> {
+    let record = take_timed_out(deps.storage, &info, channel_id, sequence)?;
+    let packet: InterchainSwapPacketData = from_binary(&record.data)?;
+
+    let sub_msg = match packet.r#type {
+        InterchainMessageType::LeftSwap | InterchainMessageType::RightSwap => {
+            let mut msg: MsgSwapRequest = from_binary(&packet.data)?;
+            msg.timeout_height = timeout_height;
+            msg.timeout_timestamp = timeout_timestamp;
+            swap_packet(deps.branch(), &env, msg)?.0
+        }
+        _ => {
+            let pool = POOLS.load(deps.storage, &record.pool_id)?;
+            let timeout = packet_timeout(
+                &env,
+                pool.counterparty_chain_id(&env.block.chain_id),
+                timeout_height,
+                timeout_timestamp,
+            )?;
+            send_packet(
+                deps.storage,
+                &env,
+                record.channel_id.clone(),
+                &packet,
+                timeout,
+                Escrow {
+                    sender: record.sender,
+                    pool_id: record.pool_id.clone(),
+                    funds: record.escrow,
+                },
+            )?
+        }
+    };
+
+    Ok(Response::default()
+        .add_submessage(sub_msg)
+        .add_attribute("pool_id", record.pool_id)
+        .add_attribute("action", "retry_packet")
+        .add_attribute("sequence", sequence.to_string()))
+}
+
+/// Gives up on a timed out packet and refunds its escrow.
+pub fn refund_packet(
+    deps: DepsMut,
+    info: MessageInfo,
+    channel_id: String,
+    sequence: u64,
+) -> Result<Response, ContractError> {
+    let record = take_timed_out(deps.storage, &info, channel_id, sequence)?;
+    let packet: InterchainSwapPacketData = from_binary(&record.data)?;
+    let sub_messages = refund_packet_token(deps, packet, Some(&record))?;
+
+    Ok(Response::default()
+        .add_submessages(sub_messages)
+        .add_attribute("pool_id", record.pool_id)
+        .add_attribute("action", "refund_packet")
+        .add_attribute("sequence", sequence.to_string()))
+}
+
+/// Removes a timed out packet of the caller from the ledger.
+fn take_timed_out(
+    storage: &mut dyn Storage,
+    info: &MessageInfo,
+    channel_id: String,
+    sequence: u64,
+) -> Result<InFlightPacket, ContractError> {
+    let key = (channel_id, sequence);
+    let record = in_flight_packets().load(storage, key.clone())?;
+    if record.sender != info.sender {
+        return Err(ContractError::InvalidSender);
+    }
+    if record.status != PacketStatus::TimedOut {
+        return Err(ContractError::InvalidStatus);
+    }
+    in_flight_packets().remove(storage, key)?;
+    Ok(record)
+}
+
+pub fn query_pending_packet(
+    deps: Deps,
+    channel_id: String,
+    sequence: u64,
+) -> StdResult<InFlightPacket> {
+    in_flight_packets().load(deps.storage, (channel_id, sequence))
+}
+
+pub fn query_pending_packets_by_sender(
+    deps: Deps,
+    sender: String,
+    start_after: Option<(String, u64)>,
+    limit: Option<u32>,
+) -> StdResult<PendingPacketListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let packets = in_flight_packets()
+        .idx
+        .sender
+        .prefix(sender)
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, packet)| packet))
+        .collect::<StdResult<_>>()?;
+    Ok(PendingPacketListResponse { packets })
+}
+
+pub fn query_pending_packets_by_pool(
+    deps: Deps,
+    pool_id: String,
+    start_after: Option<(String, u64)>,
+    limit: Option<u32>,
+) -> StdResult<PendingPacketListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let packets = in_flight_packets()
+        .idx
+        .pool
+        .prefix(pool_id)
+        .range(
+            deps.storage,
+            start_after.map(Bound::exclusive),
+            None,
+            Order::Ascending,
+        )
+        .take(limit)
+        .map(|item| item.map(|(_, packet)| packet))
+        .collect::<StdResult<_>>()?;
+    Ok(PendingPacketListResponse { packets })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::Config;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{Binary, Event, IbcEndpoint, OwnedDeps, SubMsgResponse};
+
+    fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+        let config = Config {
+            counter: 0,
+            token_code_id: 1,
+            admin: "admin".to_string(),
+            router: "".to_string(),
+            protocol_fee_rate: 0,
+            fee_collector: "admin".to_string(),
+            guardian: "guardian".to_string(),
+            paused: false,
+            disabled_messages: vec![],
+        };
+        CONFIG.save(deps.as_mut().storage, &config).unwrap();
+        deps
+    }
+
+    fn packet(packet_type: InterchainMessageType) -> InterchainSwapPacketData {
+        InterchainSwapPacketData {
+            r#type: packet_type,
+            data: Binary::default(),
+            state_change: None,
+            memo: None,
+        }
+    }
+
+    fn try_send(
+        storage: &mut dyn Storage,
+        packet: &InterchainSwapPacketData,
+    ) -> Result<SubMsg, ContractError> {
+        let timeout = IbcTimeout::with_timestamp(mock_env().block.time.plus_seconds(600));
+        send_packet(
+            storage,
+            &mock_env(),
+            "channel-0".to_string(),
+            packet,
+            timeout,
+            Escrow {
+                sender: "alice".to_string(),
+                pool_id: "pool".to_string(),
+                funds: vec![],
+            },
+        )
+    }
+
+    fn send(
+        storage: &mut dyn Storage,
+        packet_type: InterchainMessageType,
+        sequence: u64,
+    ) -> IbcPacket {
+        let packet = packet(packet_type);
+        try_send(storage, &packet).unwrap();
+
+        let result = SubMsgResult::Ok(SubMsgResponse {
+            events: vec![
+                Event::new("send_packet").add_attribute("packet_sequence", sequence.to_string())
+            ],
+            data: None,
+        });
+        on_packet_sent(storage, result).unwrap();
+
+        let endpoint = IbcEndpoint {
+            port_id: "wasm.ics101".to_string(),
+            channel_id: "channel-0".to_string(),
+        };
+        IbcPacket::new(
+            to_binary(&packet).unwrap(),
+            endpoint.clone(),
+            endpoint,
+            sequence,
+            IbcTimeout::with_timestamp(mock_env().block.time.plus_seconds(600)),
+        )
+    }
+
+    #[test]
+    fn test_timed_out_packets_are_held_for_retry() {
+        let mut deps = setup();
+        let cancel = send(deps.as_mut().storage, InterchainMessageType::CancelPool, 7);
+        let deposit = send(
+            deps.as_mut().storage,
+            InterchainMessageType::SingleAssetDeposit,
+            8,
+        );
+
+        let pending =
+            query_pending_packets_by_sender(deps.as_ref(), "alice".to_string(), None, None)
+                .unwrap();
+        assert_eq!(pending.packets.len(), 2);
+        assert_eq!(pending.packets[0].sequence, 7);
+        assert_eq!(pending.packets[0].status, PacketStatus::Pending);
+
+        // Deposits are refunded right away, cancels wait for the sender
+        assert!(!hold_timed_out_packet(deps.as_mut().storage, &deposit).unwrap());
+        assert!(hold_timed_out_packet(deps.as_mut().storage, &cancel).unwrap());
+        let pending =
+            query_pending_packets_by_pool(deps.as_ref(), "pool".to_string(), None, None).unwrap();
+        assert_eq!(pending.packets.len(), 1);
+        assert_eq!(pending.packets[0].status, PacketStatus::TimedOut);
+
+        let err = refund_packet(
+            deps.as_mut(),
+            mock_info("bob", &[]),
+            "channel-0".to_string(),
+            7,
+        )
+        .unwrap_err();
+        assert_eq!(err, ContractError::InvalidSender);
+        refund_packet(
+            deps.as_mut(),
+            mock_info("alice", &[]),
+            "channel-0".to_string(),
+            7,
+        )
+        .unwrap();
+        assert!(query_pending_packet(deps.as_ref(), "channel-0".to_string(), 7).is_err());
+    }
+
+    #[test]
+    fn test_pause_blocks_outbound_packets() {
+        let mut deps = setup();
+        let cancel = send(deps.as_mut().storage, InterchainMessageType::CancelPool, 1);
+        hold_timed_out_packet(deps.as_mut().storage, &cancel).unwrap();
+
+        let mut config = CONFIG.load(deps.as_ref().storage).unwrap();
+        config.paused = true;
+        CONFIG.save(deps.as_mut().storage, &config).unwrap();
+        let swap = packet(InterchainMessageType::LeftSwap);
+        assert_eq!(
+            try_send(deps.as_mut().storage, &swap).unwrap_err(),
+            ContractError::Paused
+        );
+        // Refunds of packets already sent still complete
+        refund_packet(
+            deps.as_mut(),
+            mock_info("alice", &[]),
+            "channel-0".to_string(),
+            1,
+        )
+        .unwrap();
+
+        config.paused = false;
+        config.disabled_messages = vec![InterchainMessageType::LeftSwap];
+        CONFIG.save(deps.as_mut().storage, &config).unwrap();
+        assert_eq!(
+            try_send(deps.as_mut().storage, &swap).unwrap_err(),
+            ContractError::MessageDisabled {
+                message_type: "LeftSwap".to_string()
+            }
+        );
+        try_send(
+            deps.as_mut().storage,
+            &packet(InterchainMessageType::RightSwap),
+        )
+        .unwrap();
+    }
+}
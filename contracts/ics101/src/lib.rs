@@ -1,13 +1,21 @@
 mod approx_pow;
+pub mod channels;
 pub mod contract;
 mod error;
 pub mod ibc;
 pub mod interchainswap_handler;
+pub mod ledger;
 pub mod market;
 mod math;
+pub mod migrations;
 pub mod msg;
+pub mod oracle;
+pub mod orderbook;
 pub mod response;
+pub mod simulation;
 pub mod state;
+#[cfg(test)]
+mod testing;
 pub mod types;
 pub mod utils;
 
@@ -5,14 +5,27 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    math::{calc_minted_shares_given_single_asset_in, solve_constant_function_invariant},
+    math::{
+        calc_minted_shares_given_single_asset_in, calc_single_asset_out_given_shares_in,
+        compute_stable_d, compute_stable_spot_price, compute_stable_y,
+        solve_constant_function_invariant,
+    },
     types::WeightedAsset,
-    utils::{adjust_precision, decimal2decimal256},
+    utils::{adjust_precision, decimal2decimal256, get_pair_key},
 };
 
 pub const FEE_PRECISION: u16 = 10000;
 pub const FIXED_PRECISION: u8 = 12;
 pub const LP_TOKEN_PRECISION: u8 = 6;
+/// Bounds on the number of assets an interchain pool can hold across both chains.
+pub const MIN_POOL_ASSETS: usize = 2;
+pub const MAX_POOL_ASSETS: usize = 8;
+/// Upper bound on the amplification coefficient of a stableswap pool.
+pub const MAX_AMP: u64 = 1_000_000;
+/// Minimum duration of an amplification ramp, in seconds.
+pub const MIN_RAMP_TIME: u64 = 86400;
+/// Maximum factor by which a single ramp may raise or lower the amplification.
+pub const MAX_AMP_CHANGE: u64 = 10;
 /// Number of LP tokens to mint when liquidity is provided for the first time to the pool.
 /// This does not include the token decimals.
 // const INIT_LP_TOKENS: u128 = 100;
@@ -31,6 +44,87 @@ pub enum PoolStatus {
     Active = 1,
     #[serde(rename = "CANCELLED")]
     Cancelled = 2,
+    /// The pool's channel closed. Only local withdrawals are possible.
+    #[serde(rename = "FROZEN")]
+    Frozen = 3,
+    /// Swaps and deposits are stopped until the pool is resumed.
+    #[serde(rename = "PAUSED")]
+    Paused = 4,
+    /// Winding down for good, only withdrawals are possible.
+    #[serde(rename = "DEPRECATED")]
+    Deprecated = 5,
+}
+
+impl PoolStatus {
+    /// Returns whether a pool creator or the admin may move a pool from this status to `next`.
+    pub fn can_transition_to(&self, next: &PoolStatus) -> bool {
+        matches!(
+            (self, next),
+            (PoolStatus::Active, PoolStatus::Paused)
+                | (PoolStatus::Paused, PoolStatus::Active)
+                | (PoolStatus::Active | PoolStatus::Paused, PoolStatus::Deprecated)
+        )
+    }
+
+    /// Returns whether liquidity providers may still withdraw from a pool in this status.
+    pub fn can_withdraw(&self) -> bool {
+        matches!(self, PoolStatus::Active | PoolStatus::Paused | PoolStatus::Deprecated)
+    }
+}
+
+/// Invariant used by a pool to price swaps and deposits.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug, Default)]
+pub enum PoolCurve {
+    /// Balancer style weighted constant product curve.
+    #[default]
+    #[serde(rename = "WEIGHTED")]
+    Weighted,
+    /// Curve style stableswap invariant with amplification coefficient `amp`.
+    #[serde(rename = "STABLE")]
+    Stable { amp: u64 },
+}
+
+/// Optional fee mode where the swap fee grows with how far a trade pushes the pool
+/// away from its target weights. `sensitivity` is the fee added per unit of drift,
+/// both expressed in [`FEE_PRECISION`] units, and the result is bounded by `min_fee`
+/// and `max_fee`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct DynamicFee {
+    pub min_fee: u32,
+    pub max_fee: u32,
+    pub sensitivity: u32,
+}
+
+/// Linear change of a stable pool's amplification between two block times (in seconds).
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct AmpRamp {
+    pub initial_amp: u64,
+    pub future_amp: u64,
+    pub initial_time: u64,
+    pub future_time: u64,
+}
+
+impl AmpRamp {
+    /// Returns the amplification in effect at `now`.
+    pub fn amp_at(&self, now: u64) -> u64 {
+        if now >= self.future_time {
+            return self.future_amp;
+        }
+        if now <= self.initial_time {
+            return self.initial_amp;
+        }
+
+        let elapsed = (now - self.initial_time) as u128;
+        let duration = (self.future_time - self.initial_time) as u128;
+        let initial = self.initial_amp as u128;
+        let future = self.future_amp as u128;
+        let amp = if future > initial {
+            initial + (future - initial) * elapsed / duration
+        } else {
+            initial - (initial - future) * elapsed / duration
+        };
+        amp as u64
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -55,9 +149,39 @@ pub struct InterchainLiquidityPool {
     pub supply: Coin,
     pub swap_fee: u32,
     pub pool_price: u64,
+    #[serde(default)]
+    pub curve: PoolCurve,
+    #[serde(default)]
+    pub amp_ramp: Option<AmpRamp>,
+    #[serde(default)]
+    pub dynamic_fee: Option<DynamicFee>,
 }
 
 impl InterchainLiquidityPool {
+    /// Chain id of the other side of the pool, as seen from `chain_id`.
+    pub fn counterparty_chain_id(&self, chain_id: &str) -> &str {
+        if self.source_chain_id == chain_id {
+            &self.destination_chain_id
+        } else {
+            &self.source_chain_id
+        }
+    }
+
+    /// Sum of the asset balances in whole tokens. Assets are not priced, so this only ranks
+    /// pools of comparable assets against each other.
+    pub fn liquidity(&self) -> StdResult<Decimal256> {
+        self.assets.iter().try_fold(Decimal256::zero(), |total, asset| {
+            let balance = Decimal256::from_atomics(asset.balance.amount, asset.decimal)
+                .map_err(|err| StdError::generic_err(err.to_string()))?;
+            total.checked_add(balance).map_err(StdError::overflow)
+        })
+    }
+
+    /// Key of the pool's denoms in the pair index.
+    pub fn pair_key(&self) -> String {
+        get_pair_key(self.assets.iter().map(|asset| asset.balance.denom.clone()).collect())
+    }
+
     pub fn find_asset_by_denom(&self, denom: &str) -> StdResult<PoolAsset> {
         for asset in &self.assets {
             if asset.balance.denom == denom {
@@ -67,6 +191,41 @@ impl InterchainLiquidityPool {
         Err(StdError::generic_err("Denom not found in pool"))
     }
 
+    /// Returns the curve quoted at `now` (in seconds), following any amplification ramp.
+    pub fn curve_at(&self, now: u64) -> PoolCurve {
+        match (&self.curve, &self.amp_ramp) {
+            (PoolCurve::Stable { .. }, Some(ramp)) => PoolCurve::Stable {
+                amp: ramp.amp_at(now),
+            },
+            (curve, _) => curve.clone(),
+        }
+    }
+
+    /// Returns a copy of the pool whose curve is the one in effect at `now`.
+    pub fn at_time(&self, now: u64) -> InterchainLiquidityPool {
+        let mut pool = self.clone();
+        pool.curve = self.curve_at(now);
+        pool.amp_ramp = None;
+        pool
+    }
+
+    /// Applies an amplification ramp synced from either chain. A ramp whose end
+    /// is not after its start fixes the amplification at `initial_amp`.
+    pub fn apply_amp_ramp(&mut self, ramp: AmpRamp) -> StdResult<()> {
+        if !matches!(self.curve, PoolCurve::Stable { .. }) {
+            return Err(StdError::generic_err("Pool does not use the stable curve"));
+        }
+        self.curve = PoolCurve::Stable {
+            amp: ramp.initial_amp,
+        };
+        self.amp_ramp = if ramp.future_time > ramp.initial_time {
+            Some(ramp)
+        } else {
+            None
+        };
+        Ok(())
+    }
+
     pub fn find_asset_by_side(&self, side: PoolSide) -> StdResult<PoolAsset> {
         for asset in &self.assets {
             if asset.side == side {
@@ -76,6 +235,24 @@ impl InterchainLiquidityPool {
         Err(StdError::generic_err("Asset side not found in pool"))
     }
 
+    /// Returns every asset held on the given side of the pool.
+    pub fn find_assets_by_side(&self, side: PoolSide) -> Vec<PoolAsset> {
+        self.assets
+            .iter()
+            .filter(|asset| asset.side == side)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the combined weight of all assets on the given side of the pool.
+    pub fn side_weight(&self, side: PoolSide) -> u32 {
+        self.assets
+            .iter()
+            .filter(|asset| asset.side == side)
+            .map(|asset| asset.weight)
+            .sum()
+    }
+
     pub fn add_asset(&mut self, token: Coin) -> StdResult<Coin> {
         let mut indx = 0;
         let mut found = false;
@@ -159,6 +336,21 @@ impl InterchainMarketMaker {
 
         if self.pool.status != PoolStatus::Active {
             return Err(StdError::generic_err("Pool is not active!"));
+        } else if let PoolCurve::Stable { amp } = self.pool.curve {
+            // Shares are minted in proportion to the growth of the invariant
+            let balances = self.normalized_balances()?;
+            let index = self.asset_index(&token.denom)?;
+            let d_before = compute_stable_d(amp, &balances)?;
+
+            let mut balances_after = balances;
+            balances_after[index] = balances_after[index].checked_add(Uint256::from(
+                adjust_precision(token.amount, asset.decimal as u8, FIXED_PRECISION)?,
+            ))?;
+            let d_after = compute_stable_d(amp, &balances_after)?;
+
+            issue_amount = Uint256::from(self.pool.supply.amount)
+                .multiply_ratio(d_after - d_before, d_before)
+                .try_into()?;
         } else {
             let pool_asset_weighted = &WeightedAsset {
                 asset: asset.balance.clone(),
@@ -216,6 +408,75 @@ impl InterchainMarketMaker {
         Ok(out_tokens)
     }
 
+    /// Calculate the amount of `denom_out` released when burning `redeem` LP tokens.
+    /// Swap fees apply to the part of the withdrawal that is not proportional to the pool.
+    pub fn withdraw_single_asset(&self, redeem: Coin, denom_out: &str) -> StdResult<Coin> {
+        if !self.pool.status.can_withdraw() {
+            return Err(StdError::generic_err("Pool does not allow withdrawals!"));
+        }
+        if redeem.amount.is_zero() || redeem.amount >= self.pool.supply.amount {
+            return Err(StdError::generic_err("Invalid pool token amount"));
+        }
+        let asset = self.pool.find_asset_by_denom(denom_out)?;
+        let fee_rate = Decimal::from_ratio(self.fee_rate, FEE_PRECISION);
+
+        let amount_out = match self.pool.curve {
+            PoolCurve::Weighted => {
+                let weight = Decimal::from_ratio(asset.weight, Uint128::from(100u64));
+                let amount_before_fee = calc_single_asset_out_given_shares_in(
+                    redeem.amount,
+                    asset.decimal,
+                    &WeightedAsset {
+                        asset: asset.balance.clone(),
+                        weight,
+                    },
+                    self.pool.supply.amount,
+                )?;
+                // Only the (1 - weight) share of the output is an implicit swap
+                amount_before_fee * (Decimal::one() - (Decimal::one() - weight) * fee_rate)
+            }
+            PoolCurve::Stable { amp } => {
+                let balances = self.normalized_balances()?;
+                let index = self.asset_index(denom_out)?;
+                let d_before = compute_stable_d(amp, &balances)?;
+                let d_after = d_before.multiply_ratio(
+                    self.pool.supply.amount - redeem.amount,
+                    self.pool.supply.amount,
+                );
+
+                // Round against the withdrawer by one unit of fixed precision
+                let balance_after = compute_stable_y(amp, &balances, index, d_after)?;
+                let amount_before_fee = balances[index]
+                    .saturating_sub(balance_after)
+                    .saturating_sub(Uint256::one());
+                let amount_before_fee = adjust_precision(
+                    amount_before_fee.try_into()?,
+                    FIXED_PRECISION,
+                    asset.decimal as u8,
+                )?;
+
+                // Only the share of the output not held by this asset is an implicit swap
+                let total: Uint256 = balances.iter().fold(Uint256::zero(), |acc, b| acc + b);
+                let share = Decimal::from_ratio(
+                    Uint128::try_from(balances[index])?,
+                    Uint128::try_from(total)?,
+                );
+                amount_before_fee * (Decimal::one() - (Decimal::one() - share) * fee_rate)
+            }
+        };
+
+        if amount_out >= asset.balance.amount {
+            return Err(StdError::generic_err("Invalid asset out"));
+        }
+
+        Ok(Coin {
+            denom: denom_out.to_string(),
+            amount: amount_out,
+        })
+    }
+
+    /// Proportional withdrawal keeps the pool composition unchanged, so the same
+    /// computation holds for both the weighted and the stableswap curve.
     pub fn multi_asset_withdraw(&self, redeem: Coin) -> StdResult<Vec<Coin>> {
         let total_share = self.pool.supply.amount;
 
@@ -254,6 +515,13 @@ impl InterchainMarketMaker {
     /// * **ask_pool** is an object of type [`DecimalAsset`]. This is the asked asset.
     /// * **pools** is an array of [`DecimalAsset`] type items. These are the assets available in the pool.
     pub fn compute_swap(&self, amount_in: Coin, denom_out: &str) -> StdResult<Coin> {
+        match self.pool.curve {
+            PoolCurve::Weighted => self.compute_weighted_swap(amount_in, denom_out),
+            PoolCurve::Stable { amp } => self.compute_stable_swap(amp, amount_in, denom_out),
+        }
+    }
+
+    fn compute_weighted_swap(&self, amount_in: Coin, denom_out: &str) -> StdResult<Coin> {
         let asset_in = self.pool.clone().find_asset_by_denom(&amount_in.denom)?;
         let asset_out = self.pool.clone().find_asset_by_denom(denom_out)?;
 
@@ -311,6 +579,13 @@ impl InterchainMarketMaker {
     }
 
     pub fn compute_offer_amount(&self, amount_in: Coin, amount_out: Coin) -> StdResult<Coin> {
+        match self.pool.curve {
+            PoolCurve::Weighted => self.compute_weighted_offer_amount(amount_in, amount_out),
+            PoolCurve::Stable { amp } => self.compute_stable_offer_amount(amp, amount_in, amount_out),
+        }
+    }
+
+    fn compute_weighted_offer_amount(&self, amount_in: Coin, amount_out: Coin) -> StdResult<Coin> {
         let asset_in = self.pool.clone().find_asset_by_denom(&amount_in.denom)?;
         let asset_out = self.pool.clone().find_asset_by_denom(&amount_out.denom)?;
 
@@ -372,6 +647,255 @@ impl InterchainMarketMaker {
         })
     }
 
+    fn compute_stable_swap(&self, amp: u64, amount_in: Coin, denom_out: &str) -> StdResult<Coin> {
+        let asset_in = self.pool.find_asset_by_denom(&amount_in.denom)?;
+        let asset_out = self.pool.find_asset_by_denom(denom_out)?;
+        let index_in = self.asset_index(&amount_in.denom)?;
+        let index_out = self.asset_index(denom_out)?;
+
+        let balances = self.normalized_balances()?;
+        let d = compute_stable_d(amp, &balances)?;
+
+        let amount_in_fixed = adjust_precision(
+            self.minus_fees(amount_in.amount).to_uint_floor(),
+            asset_in.decimal as u8,
+            FIXED_PRECISION,
+        )?;
+        let mut balances_after = balances.clone();
+        balances_after[index_in] = balances_after[index_in].checked_add(amount_in_fixed.into())?;
+
+        // Round against the trader by one unit of fixed precision
+        let balance_out_after = compute_stable_y(amp, &balances_after, index_out, d)?;
+        let return_amount = balances[index_out]
+            .saturating_sub(balance_out_after)
+            .saturating_sub(Uint256::one());
+
+        let return_amount = adjust_precision(
+            return_amount.try_into()?,
+            FIXED_PRECISION,
+            asset_out.decimal as u8,
+        )?;
+
+        Ok(Coin {
+            amount: return_amount,
+            denom: denom_out.to_string(),
+        })
+    }
+
+    fn compute_stable_offer_amount(
+        &self,
+        amp: u64,
+        amount_in: Coin,
+        amount_out: Coin,
+    ) -> StdResult<Coin> {
+        let asset_in = self.pool.find_asset_by_denom(&amount_in.denom)?;
+        let asset_out = self.pool.find_asset_by_denom(&amount_out.denom)?;
+        let index_in = self.asset_index(&amount_in.denom)?;
+        let index_out = self.asset_index(&amount_out.denom)?;
+
+        let one_minus_commission = Decimal256::one()
+            - decimal2decimal256(Decimal::from_ratio(self.fee_rate, FEE_PRECISION))?;
+        let inv_one_minus_commission = Decimal256::one() / one_minus_commission;
+
+        let balances = self.normalized_balances()?;
+        let d = compute_stable_d(amp, &balances)?;
+
+        let amount_out_fixed = adjust_precision(
+            amount_out.amount,
+            asset_out.decimal as u8,
+            FIXED_PRECISION,
+        )?;
+        let mut balances_after = balances.clone();
+        balances_after[index_out] = balances_after[index_out]
+            .checked_sub(amount_out_fixed.into())
+            .map_err(|_| StdError::generic_err("Insufficient pool liquidity"))?;
+
+        // Round against the trader by one unit of fixed precision
+        let balance_in_after = compute_stable_y(amp, &balances_after, index_in, d)?;
+        let real_offer = balance_in_after
+            .saturating_sub(balances[index_in])
+            .checked_add(Uint256::one())?;
+        let real_offer =
+            adjust_precision(real_offer.try_into()?, FIXED_PRECISION, asset_in.decimal as u8)?;
+
+        let offer_amount_including_fee =
+            (Uint256::from(real_offer) * inv_one_minus_commission).try_into()?;
+
+        Ok(Coin {
+            amount: offer_amount_including_fee,
+            denom: amount_in.denom,
+        })
+    }
+
+    /// Returns the marginal price of `denom_quote` in units of `denom_base`, ignoring fees.
+    pub fn spot_price(&self, denom_base: &str, denom_quote: &str) -> StdResult<Decimal256> {
+        let index_base = self.asset_index(denom_base)?;
+        let index_quote = self.asset_index(denom_quote)?;
+        let balances = self.normalized_balances()?;
+
+        match self.pool.curve {
+            PoolCurve::Weighted => {
+                // (B_base / W_base) / (B_quote / W_quote)
+                let weight_base = self.pool.assets[index_base].weight;
+                let weight_quote = self.pool.assets[index_quote].weight;
+                Decimal256::checked_from_ratio(
+                    balances[index_base].checked_mul(weight_quote.into())?,
+                    balances[index_quote].checked_mul(weight_base.into())?,
+                )
+                .map_err(|e| StdError::generic_err(e.to_string()))
+            }
+            PoolCurve::Stable { amp } => {
+                compute_stable_spot_price(amp, &balances, index_base, index_quote)
+            }
+        }
+    }
+
+    /// Returns what `amount_in` buys at the spot price, ignoring fees and price impact.
+    pub fn spot_output(&self, amount_in: &Coin, denom_out: &str) -> StdResult<Uint128> {
+        let price = self.spot_price(denom_out, &amount_in.denom)?;
+        let asset_in = self.pool.find_asset_by_denom(&amount_in.denom)?;
+        let asset_out = self.pool.find_asset_by_denom(denom_out)?;
+
+        let normalized_in =
+            adjust_precision(amount_in.amount, asset_in.decimal as u8, FIXED_PRECISION)?;
+        let normalized_out = (Decimal256::from_ratio(normalized_in, 1u8) * price).to_uint_floor();
+        adjust_precision(
+            Uint128::try_from(normalized_out)?,
+            FIXED_PRECISION,
+            asset_out.decimal as u8,
+        )
+    }
+
+    /// Returns the pool balances scaled to [`FIXED_PRECISION`], in pool asset order.
+    fn normalized_balances(&self) -> StdResult<Vec<Uint256>> {
+        self.pool
+            .assets
+            .iter()
+            .map(|asset| {
+                adjust_precision(asset.balance.amount, asset.decimal as u8, FIXED_PRECISION)
+                    .map(Uint256::from)
+            })
+            .collect()
+    }
+
+    fn asset_index(&self, denom: &str) -> StdResult<usize> {
+        self.pool
+            .assets
+            .iter()
+            .position(|asset| asset.balance.denom == denom)
+            .ok_or_else(|| StdError::generic_err("Denom not found in pool"))
+    }
+
+    /// Returns a copy of the market maker charging `fee_rate` on swaps.
+    pub fn with_fee_rate(&self, fee_rate: u32) -> Self {
+        InterchainMarketMaker {
+            fee_rate,
+            ..self.clone()
+        }
+    }
+
+    /// Returns the fee rate charged for swapping `amount_in` into `denom_out`.
+    pub fn left_swap_fee_rate(&self, amount_in: &Coin, denom_out: &str) -> StdResult<u32> {
+        match &self.pool.dynamic_fee {
+            None => Ok(self.fee_rate),
+            Some(dynamic_fee) => {
+                let amount_out = self
+                    .with_fee_rate(dynamic_fee.min_fee)
+                    .compute_swap(amount_in.clone(), denom_out)?;
+                self.dynamic_fee_rate(dynamic_fee, amount_in, &amount_out)
+            }
+        }
+    }
+
+    /// Returns the fee rate charged for buying `amount_out` with `denom_in`.
+    pub fn right_swap_fee_rate(&self, denom_in: &str, amount_out: &Coin) -> StdResult<u32> {
+        match &self.pool.dynamic_fee {
+            None => Ok(self.fee_rate),
+            Some(dynamic_fee) => {
+                let amount_in = self.with_fee_rate(dynamic_fee.min_fee).compute_offer_amount(
+                    Coin {
+                        denom: denom_in.to_string(),
+                        amount: Uint128::zero(),
+                    },
+                    amount_out.clone(),
+                )?;
+                self.dynamic_fee_rate(dynamic_fee, &amount_in, amount_out)
+            }
+        }
+    }
+
+    fn dynamic_fee_rate(
+        &self,
+        dynamic_fee: &DynamicFee,
+        amount_in: &Coin,
+        amount_out: &Coin,
+    ) -> StdResult<u32> {
+        let drift = self.weight_drift(amount_in, amount_out)?;
+        let drift_bps = (Uint128::from(FEE_PRECISION) * drift).u128();
+        let fee = dynamic_fee.min_fee as u128
+            + drift_bps * dynamic_fee.sensitivity as u128 / FEE_PRECISION as u128;
+        Ok(fee.min(dynamic_fee.max_fee as u128) as u32)
+    }
+
+    /// Returns how far the post-trade weights drift from the target weights, as the
+    /// total variation distance between the two distributions. Post-trade weights are
+    /// valued at the pre-trade spot price so that only the trade moves them.
+    fn weight_drift(&self, amount_in: &Coin, amount_out: &Coin) -> StdResult<Decimal> {
+        let mut targets = vec![];
+        let mut values = vec![];
+        for asset in &self.pool.assets {
+            let mut balance = asset.balance.amount;
+            if asset.balance.denom == amount_in.denom {
+                balance = balance.checked_add(amount_in.amount)?;
+            }
+            if asset.balance.denom == amount_out.denom {
+                balance = balance
+                    .checked_sub(amount_out.amount)
+                    .map_err(|_| StdError::generic_err("Insufficient pool liquidity"))?;
+            }
+
+            match self.pool.curve {
+                PoolCurve::Weighted => {
+                    // Spot value of a weighted asset is proportional to weight / balance
+                    let target = Decimal::from_ratio(asset.weight, 100u64);
+                    targets.push(target);
+                    values.push(target * Decimal::from_ratio(balance, asset.balance.amount));
+                }
+                PoolCurve::Stable { .. } => {
+                    // Stable assets are valued at par
+                    targets.push(Decimal::from_ratio(1u64, self.pool.assets.len() as u64));
+                    values.push(Decimal::from_ratio(
+                        adjust_precision(balance, asset.decimal as u8, FIXED_PRECISION)?,
+                        10u128.pow(FIXED_PRECISION as u32),
+                    ));
+                }
+            }
+        }
+
+        let total = values.iter().fold(Decimal::zero(), |acc, value| acc + value);
+        if total.is_zero() {
+            return Ok(Decimal::zero());
+        }
+        let mut distance = Decimal::zero();
+        for (value, target) in values.iter().zip(targets) {
+            let share = value / total;
+            distance += if share > target {
+                share - target
+            } else {
+                target - share
+            };
+        }
+
+        Ok(distance / Decimal::from_ratio(2u64, 1u64))
+    }
+
+    /// Returns the protocol's share of the swap fee charged on `amount_in`.
+    pub fn protocol_fee(&self, amount_in: Uint128, protocol_fee_rate: u32) -> Uint128 {
+        amount_in
+            .multiply_ratio(self.fee_rate, FEE_PRECISION)
+            .multiply_ratio(protocol_fee_rate, FEE_PRECISION)
+    }
+
     pub fn minus_fees(&self, amount: Uint128) -> Decimal {
         let amount_dec = Decimal::from_ratio(amount.u128(), Uint128::one());
         let fee_rate_dec = Decimal::from_ratio(self.fee_rate, Uint128::new(10000));
@@ -392,3 +916,225 @@ pub struct MarketFeeUpdateProposal {
     #[serde(rename = "fee_rate")]
     pub fee_rate: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::coin;
+
+    use super::*;
+    use crate::msg::MsgMakePoolRequest;
+    use crate::testing;
+
+    fn mock_pool(
+        curve: PoolCurve,
+        assets: &[(PoolSide, &str, u128, u32)],
+    ) -> InterchainLiquidityPool {
+        InterchainLiquidityPool {
+            curve,
+            supply: coin(3_000_000, "pool1"),
+            ..testing::mock_pool("pool1", assets)
+        }
+    }
+
+    /// Logarithm of the weighted invariant, the product of every balance to its weight.
+    fn weighted_invariant(pool: &InterchainLiquidityPool) -> f64 {
+        pool.assets
+            .iter()
+            .map(|asset| asset.weight as f64 * (asset.balance.amount.u128() as f64).ln())
+            .sum()
+    }
+
+    #[test]
+    fn test_three_asset_pool_validation_and_invariant() {
+        let liquidity =
+            |assets: &[(PoolSide, &str, u128, u32)]| mock_pool(PoolCurve::Weighted, assets).assets;
+        let mut msg = MsgMakePoolRequest {
+            source_port: "wasm.ics101".to_string(),
+            source_channel: "channel-0".to_string(),
+            source_chain_id: "chain-a".to_string(),
+            destination_chain_id: "chain-b".to_string(),
+            counterparty_channel: "channel-1".to_string(),
+            creator: "maker".to_string(),
+            counterparty_creator: "taker".to_string(),
+            liquidity: liquidity(&[
+                (PoolSide::SOURCE, "aside", 1_000_000, 40),
+                (PoolSide::SOURCE, "cside", 1_000_000, 30),
+                (PoolSide::DESTINATION, "bside", 1_000_000, 30),
+            ]),
+            swap_fee: 30,
+            curve: PoolCurve::Weighted,
+            dynamic_fee: None,
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            memo: None,
+        };
+        assert!(msg.validate_basic().is_ok());
+
+        // Every asset on one chain
+        msg.liquidity[2].side = PoolSide::SOURCE;
+        assert!(msg.validate_basic().is_err());
+        msg.liquidity[2].side = PoolSide::DESTINATION;
+
+        // The same denom twice
+        msg.liquidity[1].balance.denom = "aside".to_string();
+        assert!(msg.validate_basic().is_err());
+        msg.liquidity[1].balance.denom = "cside".to_string();
+
+        // Weights not adding up to 100, or a zero weight
+        msg.liquidity[0].weight = 41;
+        assert!(msg.validate_basic().is_err());
+        msg.liquidity[0].weight = 70;
+        msg.liquidity[1].weight = 0;
+        assert!(msg.validate_basic().is_err());
+
+        // More assets than a pool can hold
+        let denoms = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"];
+        msg.liquidity = denoms
+            .iter()
+            .enumerate()
+            .map(|(index, denom)| PoolAsset {
+                side: if index % 2 == 0 {
+                    PoolSide::SOURCE
+                } else {
+                    PoolSide::DESTINATION
+                },
+                balance: coin(1_000_000, *denom),
+                weight: 10,
+                decimal: 6,
+            })
+            .collect();
+        assert_eq!(msg.liquidity.len(), MAX_POOL_ASSETS + 2);
+        assert!(msg.validate_basic().is_err());
+
+        // Swapping two of three assets leaves the third alone and never lowers the invariant
+        let pool = mock_pool(
+            PoolCurve::Weighted,
+            &[
+                (PoolSide::SOURCE, "aside", 1_000_000, 40),
+                (PoolSide::SOURCE, "cside", 2_000_000, 30),
+                (PoolSide::DESTINATION, "bside", 3_000_000, 30),
+            ],
+        );
+        let amm = InterchainMarketMaker::new(&pool, pool.swap_fee);
+        let amount_out = amm.compute_swap(coin(100_000, "aside"), "bside").unwrap();
+        assert!(!amount_out.amount.is_zero());
+
+        let mut after = pool.clone();
+        after.add_asset(coin(100_000, "aside")).unwrap();
+        after.subtract_asset(amount_out).unwrap();
+        assert_eq!(after.assets[1], pool.assets[1]);
+        assert!(weighted_invariant(&after) >= weighted_invariant(&pool));
+    }
+
+    #[test]
+    fn test_single_withdraw_from_winding_down_pool() {
+        let mut pool = mock_pool(
+            PoolCurve::Weighted,
+            &[
+                (PoolSide::SOURCE, "aside", 1_000_000, 50),
+                (PoolSide::DESTINATION, "bside", 1_000_000, 50),
+            ],
+        );
+        for status in [PoolStatus::Active, PoolStatus::Paused, PoolStatus::Deprecated] {
+            pool.status = status;
+            let amm = InterchainMarketMaker::new(&pool, pool.swap_fee);
+            let out = amm.withdraw_single_asset(coin(300_000, "pool1"), "aside").unwrap();
+            assert!(!out.amount.is_zero());
+        }
+        for status in [PoolStatus::Initialized, PoolStatus::Cancelled, PoolStatus::Frozen] {
+            pool.status = status;
+            let amm = InterchainMarketMaker::new(&pool, pool.swap_fee);
+            assert!(amm.withdraw_single_asset(coin(300_000, "pool1"), "aside").is_err());
+        }
+    }
+
+    #[test]
+    fn test_amp_ramp_interpolation() {
+        let up = AmpRamp {
+            initial_amp: 100,
+            future_amp: 1_000,
+            initial_time: 1_000,
+            future_time: 1_000 + MIN_RAMP_TIME,
+        };
+        assert_eq!(up.amp_at(0), 100);
+        assert_eq!(up.amp_at(1_000), 100);
+        assert_eq!(up.amp_at(1_000 + MIN_RAMP_TIME / 2), 550);
+        assert_eq!(up.amp_at(1_000 + MIN_RAMP_TIME), 1_000);
+        assert_eq!(up.amp_at(u64::MAX), 1_000);
+
+        // Ramping down rounds towards the initial amplification
+        let down = AmpRamp {
+            initial_amp: 1_000,
+            future_amp: 100,
+            ..up.clone()
+        };
+        assert_eq!(down.amp_at(1_001), 1_000);
+        assert_eq!(down.amp_at(1_000 + MIN_RAMP_TIME / 2), 550);
+        assert_eq!(down.amp_at(1_000 + MIN_RAMP_TIME), 100);
+
+        // The curve follows the ramp and a copy at a given time drops it
+        let assets = [
+            (PoolSide::SOURCE, "aside", 1_000_000, 50),
+            (PoolSide::DESTINATION, "bside", 1_000_000, 50),
+        ];
+        let mut pool = mock_pool(PoolCurve::Stable { amp: 100 }, &assets);
+        pool.apply_amp_ramp(up.clone()).unwrap();
+        assert_eq!(pool.curve_at(1_000 + MIN_RAMP_TIME / 2), PoolCurve::Stable { amp: 550 });
+        let snapshot = pool.at_time(u64::MAX);
+        assert_eq!(snapshot.curve, PoolCurve::Stable { amp: 1_000 });
+        assert_eq!(snapshot.amp_ramp, None);
+
+        // A ramp ending at its start fixes the amplification
+        let fixed = AmpRamp {
+            future_time: up.initial_time,
+            ..up.clone()
+        };
+        pool.apply_amp_ramp(fixed).unwrap();
+        assert_eq!(pool.amp_ramp, None);
+        assert_eq!(pool.curve_at(u64::MAX), PoolCurve::Stable { amp: 100 });
+
+        // Weighted pools have nothing to ramp
+        let mut weighted = mock_pool(PoolCurve::Weighted, &assets);
+        assert!(weighted.apply_amp_ramp(up).is_err());
+    }
+
+    #[test]
+    fn test_dynamic_fee_drift_and_clamping() {
+        let mut pool = mock_pool(
+            PoolCurve::Weighted,
+            &[
+                (PoolSide::SOURCE, "aside", 1_000_000, 50),
+                (PoolSide::DESTINATION, "bside", 1_000_000, 50),
+            ],
+        );
+        let amm = InterchainMarketMaker::new(&pool, pool.swap_fee);
+        let (amount_in, amount_out) = (coin(1_000_000, "aside"), coin(500_000, "bside"));
+
+        // Doubling one side and halving the other moves 30% of the value off target
+        let drift = amm.weight_drift(&amount_in, &amount_out).unwrap();
+        assert_eq!(drift, Decimal::percent(30));
+        assert_eq!(amm.weight_drift(&coin(0, "aside"), &coin(0, "bside")), Ok(Decimal::zero()));
+        assert!(amm.weight_drift(&amount_in, &coin(1_000_001, "bside")).is_err());
+
+        let dynamic_fee = |sensitivity| DynamicFee {
+            min_fee: 10,
+            max_fee: 100,
+            sensitivity,
+        };
+        let fee_rate = |sensitivity| {
+            amm.dynamic_fee_rate(&dynamic_fee(sensitivity), &amount_in, &amount_out).unwrap()
+        };
+        assert_eq!(fee_rate(0), 10);
+        assert_eq!(fee_rate(100), 40);
+        assert_eq!(fee_rate(300), 100);
+        assert_eq!(fee_rate(FEE_PRECISION.into()), 100);
+
+        // Pools without a dynamic fee charge their swap fee, others stay within bounds
+        assert_eq!(amm.left_swap_fee_rate(&amount_in, "bside"), Ok(30));
+        pool.dynamic_fee = Some(dynamic_fee(FEE_PRECISION.into()));
+        let amm = InterchainMarketMaker::new(&pool, pool.swap_fee);
+        assert_eq!(amm.left_swap_fee_rate(&coin(1, "aside"), "bside"), Ok(10));
+        assert_eq!(amm.left_swap_fee_rate(&amount_in, "bside"), Ok(100));
+        assert_eq!(amm.right_swap_fee_rate("aside", &amount_out), Ok(100));
+    }
+}
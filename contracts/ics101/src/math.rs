@@ -1,6 +1,9 @@
 use crate::utils::adjust_precision;
 use crate::{approx_pow::calculate_pow, types::WeightedAsset};
-use cosmwasm_std::{Decimal, StdError, StdResult, Uint128};
+use cosmwasm_std::{Decimal, Decimal256, StdError, StdResult, Uint128, Uint256};
+
+/// Maximum number of Newton iterations used by the stableswap solvers.
+const STABLE_MAX_ITERATIONS: u8 = 64;
 
 // Referenced from Balancer Weighted pool implementation by  Osmosis here - https://github.com/osmosis-labs/osmosis/blob/47a2366c5eeee474de9e1cb4777fab0ccfbb9592/x/gamm/pool-models/balancer/amm.go#L94
 // solveConstantFunctionInvariant solves the constant function of an AMM
@@ -79,6 +82,179 @@ pub fn calc_minted_shares_given_single_asset_in(
     Ok(pool_amount_out_adj)
 }
 
+/// ## Description - Inverse of [`calc_minted_shares_given_single_asset_in`].
+/// Calculates the amount of a single asset released when `shares_in` LP shares are burnt.
+/// Burning shares scales `k` by `(S - s) / S`, so the asset balance must scale by
+/// `((S - s) / S)^(1 / weight)` and the difference is paid out, before swap fees.
+pub fn calc_single_asset_out_given_shares_in(
+    shares_in: Uint128,
+    out_precision: u32,
+    asset_weight_and_balance: &WeightedAsset,
+    total_shares: Uint128,
+) -> StdResult<Uint128> {
+    if shares_in >= total_shares {
+        return Err(StdError::generic_err(
+            "Cannot withdraw the whole pool supply as a single asset",
+        ));
+    }
+    let total_shares_decimal = Decimal::from_atomics(total_shares, Decimal::DECIMAL_PLACES)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+    let remaining_shares_decimal =
+        Decimal::from_atomics(total_shares - shares_in, Decimal::DECIMAL_PLACES)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+    let balance_decimal =
+        Decimal::from_atomics(asset_weight_and_balance.asset.amount, out_precision)
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+
+    let token_amount_out = solve_constant_function_invariant(
+        remaining_shares_decimal,
+        total_shares_decimal,
+        Decimal::one(),
+        balance_decimal,
+        asset_weight_and_balance.weight,
+    )?;
+    let token_amount_out_adj = adjust_precision(
+        token_amount_out.atomics(),
+        token_amount_out.decimal_places() as u8,
+        out_precision as u8,
+    )?;
+
+    Ok(token_amount_out_adj)
+}
+
+// --------x--------x--------x--------x--------x--------x--------x--------x---------
+// --------x--------x StableSwap :: Curve invariant computations x--------x---------
+// --------x--------x--------x--------x--------x--------x--------x--------x---------
+
+// Referenced from the Curve StableSwap whitepaper - https://curve.fi/files/stableswap-paper.pdf
+// The invariant for n assets with balances x_i and amplification A is
+// A * n^n * sum(x_i) + D = A * D * n^n + D^(n+1) / (n^n * prod(x_i))
+// All balances are expected to be normalized to the same precision.
+
+/// ## Description - Computes the stableswap invariant `D` for the given balances.
+pub fn compute_stable_d(amp: u64, balances: &[Uint256]) -> StdResult<Uint256> {
+    let n = Uint256::from(balances.len() as u128);
+    let sum = balances
+        .iter()
+        .try_fold(Uint256::zero(), |acc, balance| acc.checked_add(*balance))?;
+    if sum.is_zero() {
+        return Ok(Uint256::zero());
+    }
+    if balances.iter().any(|balance| balance.is_zero()) {
+        return Err(StdError::generic_err("Stableswap balances must be positive"));
+    }
+
+    let ann = Uint256::from(amp).checked_mul(n.checked_pow(balances.len() as u32)?)?;
+    let mut d = sum;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        // d_p = D^(n+1) / (n^n * prod(x_i))
+        let mut d_p = d;
+        for balance in balances {
+            d_p = d_p.checked_mul(d)?.checked_div(balance.checked_mul(n)?)?;
+        }
+        let d_prev = d;
+        // D = (Ann * S + D_P * n) * D / ((Ann - 1) * D + (n + 1) * D_P)
+        let numerator = ann
+            .checked_mul(sum)?
+            .checked_add(d_p.checked_mul(n)?)?
+            .checked_mul(d)?;
+        let denominator = ann
+            .checked_sub(Uint256::one())?
+            .checked_mul(d)?
+            .checked_add(n.checked_add(Uint256::one())?.checked_mul(d_p)?)?;
+        d = numerator.checked_div(denominator)?;
+
+        if abs_diff(d, d_prev) <= Uint256::one() {
+            return Ok(d);
+        }
+    }
+
+    Err(StdError::generic_err("Stableswap invariant did not converge"))
+}
+
+/// ## Description - Computes the balance of asset `index_out` that keeps the invariant at `d`,
+/// given the balances of every other asset. The current balance at `index_out` is ignored.
+pub fn compute_stable_y(
+    amp: u64,
+    balances: &[Uint256],
+    index_out: usize,
+    d: Uint256,
+) -> StdResult<Uint256> {
+    if index_out >= balances.len() {
+        return Err(StdError::generic_err("Stableswap asset index out of range"));
+    }
+    let n = Uint256::from(balances.len() as u128);
+    let ann = Uint256::from(amp).checked_mul(n.checked_pow(balances.len() as u32)?)?;
+
+    // c = D^(n+1) / (n^n * prod(x_k) * Ann), b = sum(x_k) + D / Ann for every k != index_out
+    let mut c = d;
+    let mut sum = Uint256::zero();
+    for (index, balance) in balances.iter().enumerate() {
+        if index == index_out {
+            continue;
+        }
+        if balance.is_zero() {
+            return Err(StdError::generic_err("Stableswap balances must be positive"));
+        }
+        sum = sum.checked_add(*balance)?;
+        c = c.checked_mul(d)?.checked_div(balance.checked_mul(n)?)?;
+    }
+    c = c.checked_mul(d)?.checked_div(ann.checked_mul(n)?)?;
+    let b = sum.checked_add(d.checked_div(ann)?)?;
+
+    // y = (y^2 + c) / (2y + b - D)
+    let mut y = d;
+    for _ in 0..STABLE_MAX_ITERATIONS {
+        let y_prev = y;
+        y = y
+            .checked_mul(y)?
+            .checked_add(c)?
+            .checked_div(y.checked_add(y)?.checked_add(b)?.checked_sub(d)?)?;
+
+        if abs_diff(y, y_prev) <= Uint256::one() {
+            return Ok(y);
+        }
+    }
+
+    Err(StdError::generic_err("Stableswap balance did not converge"))
+}
+
+/// ## Description - Returns the marginal price of asset `index_quote` in units of asset
+/// `index_base`, the ratio of the invariant's partial derivatives at the given balances.
+/// With c = D^(n+1) / (n^n * prod(x_i)), dF/dx_i = Ann + c / x_i.
+pub fn compute_stable_spot_price(
+    amp: u64,
+    balances: &[Uint256],
+    index_base: usize,
+    index_quote: usize,
+) -> StdResult<Decimal256> {
+    let n = Uint256::from(balances.len() as u128);
+    let ann = Uint256::from(amp).checked_mul(n.checked_pow(balances.len() as u32)?)?;
+    let d = compute_stable_d(amp, balances)?;
+
+    let mut c = d;
+    for balance in balances {
+        c = c.checked_mul(d)?.checked_div(balance.checked_mul(n)?)?;
+    }
+
+    let x_base = balances[index_base];
+    let x_quote = balances[index_quote];
+    // (Ann + c / x_quote) / (Ann + c / x_base), multiplied through by x_base * x_quote
+    let numerator = ann.checked_mul(x_quote)?.checked_add(c)?.checked_mul(x_base)?;
+    let denominator = ann.checked_mul(x_base)?.checked_add(c)?.checked_mul(x_quote)?;
+
+    Decimal256::checked_from_ratio(numerator, denominator)
+        .map_err(|e| StdError::generic_err(e.to_string()))
+}
+
+fn abs_diff(a: Uint256, b: Uint256) -> Uint256 {
+    if a > b {
+        a - b
+    } else {
+        b - a
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -113,4 +289,49 @@ mod tests {
         let res = adjust_precision(amount_y.to_uint_floor(), 12, 6).unwrap();
         assert_eq!(res, Uint128::from(28301u128));
     }
+
+    #[test]
+    fn test_single_asset_withdraw_inverts_deposit() {
+        let mut asset = WeightedAsset {
+            asset: cosmwasm_std::Coin::new(1_000_000_000u128, "uatom"),
+            weight: Decimal::from_str("0.5").unwrap(),
+        };
+        let total_shares = Uint128::from(1_000_000_000_000u128);
+
+        let minted = calc_minted_shares_given_single_asset_in(
+            Uint128::from(10_000_000u128),
+            6,
+            &asset,
+            total_shares,
+        )
+        .unwrap();
+
+        asset.asset.amount += Uint128::from(10_000_000u128);
+        let amount_out =
+            calc_single_asset_out_given_shares_in(minted, 6, &asset, total_shares + minted)
+                .unwrap();
+
+        // Burning the minted shares returns the deposit, up to rounding
+        assert!(amount_out <= Uint128::from(10_000_000u128));
+        assert!(amount_out >= Uint128::from(9_999_000u128));
+    }
+
+    #[test]
+    fn test_stable_invariant() {
+        let balances = vec![
+            Uint256::from(1_000_000_000_000u128),
+            Uint256::from(1_000_000_000_000u128),
+        ];
+
+        // A balanced pool has D equal to the sum of its balances
+        let d = compute_stable_d(100, &balances).unwrap();
+        assert_eq!(d, Uint256::from(2_000_000_000_000u128));
+
+        // Swapping 1% of the pool in stays close to 1:1 with a high amplification
+        let balances_after = vec![Uint256::from(1_010_000_000_000u128), balances[1]];
+        let y = compute_stable_y(100, &balances_after, 1, d).unwrap();
+        let amount_out = balances[1] - y;
+        assert!(amount_out < Uint256::from(10_000_000_000u128));
+        assert!(amount_out > Uint256::from(9_990_000_000u128));
+    }
 }
@@ -0,0 +1,267 @@
+use cosmwasm_std::{Empty, Order, StdResult, Storage};
+use cw_storage_plus::Item;
+
+use crate::{
+    error::ContractError,
+    market::{InterchainLiquidityPool, PoolCurve},
+    state::{multi_asset_deposit_orders, Config, CONFIG, POOLS, POOLS_BY_CHANNEL, POOLS_BY_PAIR},
+    types::MultiAssetDepositOrder,
+};
+
+/// Layout of the state in storage. State written before it was recorded is layout 0.
+pub const STATE_VERSION: Item<u64> = Item::new("state_version");
+
+pub const CURRENT_STATE_VERSION: u64 = 4;
+
+/// Layouts stored by the first release.
+mod v0 {
+    use cosmwasm_std::Coin;
+    use cw_storage_plus::{Item, Map};
+    use serde::{Deserialize, Serialize};
+
+    use crate::market::{PoolAsset, PoolStatus};
+    use crate::types::OrderStatus;
+
+    #[derive(Serialize, Deserialize)]
+    pub struct Config {
+        pub counter: u64,
+        pub token_code_id: u64,
+        pub admin: String,
+        pub router: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct InterchainLiquidityPool {
+        pub assets: Vec<PoolAsset>,
+        pub counter_party_channel: String,
+        pub counter_party_port: String,
+        pub destination_creator: String,
+        pub destination_chain_id: String,
+        pub id: String,
+        pub source_chain_id: String,
+        pub source_creator: String,
+        pub status: PoolStatus,
+        pub supply: Coin,
+        pub swap_fee: u32,
+        pub pool_price: u64,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct MultiAssetDepositOrder {
+        pub id: String,
+        pub pool_id: String,
+        pub chain_id: String,
+        pub source_maker: String,
+        pub destination_taker: String,
+        pub deposits: Vec<Coin>,
+        pub status: OrderStatus,
+        pub created_at: u64,
+    }
+
+    pub const CONFIG: Item<Config> = Item::new("config");
+    pub const POOLS: Map<&str, InterchainLiquidityPool> = Map::new("pools");
+    pub const MULTI_ASSET_DEPOSIT_ORDERS: Map<String, MultiAssetDepositOrder> =
+        Map::new("multi_asset_deposit_orders");
+}
+
+/// Brings the stored state up to the current layout, one step at a time.
+/// Returns the layout the state was in.
+pub fn migrate_state(storage: &mut dyn Storage) -> Result<u64, ContractError> {
+    let stored = STATE_VERSION.may_load(storage)?.unwrap_or_default();
+    if stored > CURRENT_STATE_VERSION {
+        return Err(ContractError::MigrationDowngrade {
+            from: format!("state v{}", stored),
+            to: format!("state v{}", CURRENT_STATE_VERSION),
+        });
+    }
+
+    for version in stored..CURRENT_STATE_VERSION {
+        match version {
+            0 => migrate_v0(storage)?,
+            1 => migrate_v1(storage)?,
+            2 => migrate_v2(storage)?,
+            3 => migrate_v3(storage)?,
+            _ => unreachable!("no migration from state v{}", version),
+        }
+    }
+    STATE_VERSION.save(storage, &CURRENT_STATE_VERSION)?;
+    Ok(stored)
+}
+
+/// Adds protocol fees, the guardian and pause flags to the config, and curves, amplification
+/// ramps and dynamic fees to pools. Every pool of the first release is a weighted pool.
+fn migrate_v0(storage: &mut dyn Storage) -> StdResult<()> {
+    let config = v0::CONFIG.load(storage)?;
+    CONFIG.save(
+        storage,
+        &Config {
+            counter: config.counter,
+            token_code_id: config.token_code_id,
+            fee_collector: config.admin.clone(),
+            guardian: config.admin.clone(),
+            admin: config.admin,
+            router: config.router,
+            protocol_fee_rate: 0,
+            paused: false,
+            disabled_messages: vec![],
+        },
+    )?;
+
+    let pools = v0::POOLS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (pool_id, pool) in pools {
+        let pool = InterchainLiquidityPool {
+            assets: pool.assets,
+            counter_party_channel: pool.counter_party_channel,
+            counter_party_port: pool.counter_party_port,
+            destination_creator: pool.destination_creator,
+            destination_chain_id: pool.destination_chain_id,
+            id: pool.id,
+            source_chain_id: pool.source_chain_id,
+            source_creator: pool.source_creator,
+            status: pool.status,
+            supply: pool.supply,
+            swap_fee: pool.swap_fee,
+            pool_price: pool.pool_price,
+            curve: PoolCurve::Weighted,
+            amp_ramp: None,
+            dynamic_fee: None,
+        };
+        POOLS.save(storage, &pool_id, &pool)?;
+    }
+
+    let orders = v0::MULTI_ASSET_DEPOSIT_ORDERS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (key, order) in orders {
+        let order = MultiAssetDepositOrder {
+            id: order.id,
+            pool_id: order.pool_id,
+            chain_id: order.chain_id,
+            source_maker: order.source_maker,
+            destination_taker: order.destination_taker,
+            deposits: order.deposits,
+            status: order.status,
+            created_at: order.created_at,
+            expires_at: 0,
+        };
+        multi_asset_deposit_orders().save(storage, key, &order)?;
+    }
+    Ok(())
+}
+
+/// Builds the maker, taker, pool and status indexes of deposit orders.
+fn migrate_v1(storage: &mut dyn Storage) -> StdResult<()> {
+    let orders = multi_asset_deposit_orders()
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (key, order) in orders {
+        multi_asset_deposit_orders().save(storage, key, &order)?;
+    }
+    Ok(())
+}
+
+/// Adds every pool to the pair index. Pools keep the ids they were made with.
+fn migrate_v2(storage: &mut dyn Storage) -> StdResult<()> {
+    let pools = POOLS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (pool_id, pool) in pools {
+        POOLS_BY_PAIR.save(storage, (&pool.pair_key(), &pool_id), &Empty {})?;
+    }
+    Ok(())
+}
+
+/// Adds every pool to the channel index.
+fn migrate_v3(storage: &mut dyn Storage) -> StdResult<()> {
+    let pools = POOLS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (pool_id, pool) in pools {
+        POOLS_BY_CHANNEL.save(storage, (&pool.counter_party_channel, &pool_id), &Empty {})?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::market::PoolStatus;
+    use crate::types::OrderStatus;
+    use cosmwasm_std::testing::mock_dependencies;
+    use cosmwasm_std::{coin, Uint128};
+
+    #[test]
+    fn test_migrate_first_release_state() {
+        let mut deps = mock_dependencies();
+        let storage = deps.as_mut().storage;
+        let config = v0::Config {
+            counter: 3,
+            token_code_id: 7,
+            admin: "admin".to_string(),
+            router: "router".to_string(),
+        };
+        v0::CONFIG.save(storage, &config).unwrap();
+        let pool = v0::InterchainLiquidityPool {
+            assets: vec![],
+            counter_party_channel: "channel-0".to_string(),
+            counter_party_port: "wasm.ics101".to_string(),
+            destination_creator: "taker".to_string(),
+            destination_chain_id: "chain-b".to_string(),
+            id: "pool1".to_string(),
+            source_chain_id: "chain-a".to_string(),
+            source_creator: "maker".to_string(),
+            status: PoolStatus::Active,
+            supply: coin(100, "pool1"),
+            swap_fee: 300,
+            pool_price: 0,
+        };
+        v0::POOLS.save(storage, "pool1", &pool).unwrap();
+        let order = v0::MultiAssetDepositOrder {
+            id: "pool1-1".to_string(),
+            pool_id: "pool1".to_string(),
+            chain_id: "chain-a".to_string(),
+            source_maker: "maker".to_string(),
+            destination_taker: "taker".to_string(),
+            deposits: vec![coin(10, "atom"), coin(20, "osmo")],
+            status: OrderStatus::Pending,
+            created_at: 1,
+        };
+        v0::MULTI_ASSET_DEPOSIT_ORDERS
+            .save(storage, "pool1-1".to_string(), &order)
+            .unwrap();
+
+        assert_eq!(migrate_state(storage).unwrap(), 0);
+        let config = CONFIG.load(storage).unwrap();
+        assert_eq!(config.counter, 3);
+        assert_eq!(config.fee_collector, "admin");
+        assert_eq!(config.guardian, "admin");
+        let pool = POOLS.load(storage, "pool1").unwrap();
+        assert_eq!(pool.curve, PoolCurve::Weighted);
+        assert_eq!(pool.supply.amount, Uint128::new(100));
+        assert!(POOLS_BY_CHANNEL.has(storage, ("channel-0", "pool1")));
+        let order = multi_asset_deposit_orders()
+            .idx
+            .maker
+            .prefix(("maker".to_string(), OrderStatus::Pending as u8))
+            .range(storage, None, None, Order::Ascending)
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(order.0, "pool1-1");
+        assert_eq!(order.1.deposits.len(), 2);
+
+        // already current, so nothing is rewritten
+        assert_eq!(migrate_state(storage).unwrap(), CURRENT_STATE_VERSION);
+
+        STATE_VERSION
+            .save(storage, &(CURRENT_STATE_VERSION + 1))
+            .unwrap();
+        assert!(matches!(
+            migrate_state(storage),
+            Err(ContractError::MigrationDowngrade { .. })
+        ));
+    }
+}
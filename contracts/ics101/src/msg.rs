@@ -2,17 +2,27 @@ use cw20::{Cw20Coin, Logo, MinterResponse};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Binary, Coin, Response, StdError, StdResult, Uint128};
+use cosmwasm_std::{Addr, Binary, Coin, Decimal256, Response, StdError, StdResult, Uint128};
 
 use crate::error::ContractError;
-use crate::market::{InterchainLiquidityPool, InterchainMarketMaker, PoolAsset, PoolStatus};
-use crate::types::MultiAssetDepositOrder;
+use crate::market::{
+    AmpRamp, DynamicFee, InterchainLiquidityPool, InterchainMarketMaker, PoolAsset, PoolCurve,
+    PoolSide, PoolStatus, FEE_PRECISION, MAX_AMP, MAX_POOL_ASSETS, MIN_POOL_ASSETS,
+};
+use crate::state::{ChannelInfo, FrozenPool, InFlightPacket, LimitOrder};
+use crate::types::{InterchainMessageType, MultiAssetDepositOrder, OrderStatus, StateChange};
 use crate::utils::{is_valid_name, is_valid_symbol};
 
 #[derive(Serialize, Deserialize, JsonSchema)]
 pub struct InstantiateMsg {
     pub token_code_id: u64,
     pub router: String,
+    /// Share of each swap fee kept by the protocol, in `FEE_PRECISION` units. Defaults to zero.
+    pub protocol_fee_rate: Option<u32>,
+    /// Address allowed to claim protocol fees. Defaults to the admin.
+    pub fee_collector: Option<String>,
+    /// Address allowed to pause the contract in an emergency. Defaults to the admin.
+    pub guardian: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -25,10 +35,66 @@ pub enum ExecuteMsg {
     CancelMultiAssetDeposit(MsgCancelMultiAssetDepositRequest),
     TakeMultiAssetDeposit(MsgTakeMultiAssetDepositRequest),
     MultiAssetWithdraw(MsgMultiAssetWithdrawRequest),
+    SingleAssetWithdraw(MsgSingleAssetWithdrawRequest),
     Swap(MsgSwapRequest),
     RemovePool(MsgRemovePool),
     SetLogAddress { pool_id: String, address: String }, // Receive(Cw20ReceiveMsg)
     SetRouter {address: String},
+    RampAmp(MsgRampAmpRequest),
+    StopRampAmp(MsgStopRampAmpRequest),
+    /// Moves a pool between Active, Paused and Deprecated. Pool creators or the admin only.
+    SetPoolStatus(MsgSetPoolStatusRequest),
+    SetProtocolFee { protocol_fee_rate: u32, fee_collector: String },
+    ClaimProtocolFees { pool_id: String },
+    PlaceLimitOrder(MsgPlaceLimitOrderRequest),
+    CancelLimitOrder { order_id: u64 },
+    /// Fills resting orders selling `denom_in` on a pool whose price is reachable.
+    /// Anyone may call it, at most `limit` orders are considered.
+    ExecuteLimitOrders {
+        pool_id: String,
+        denom_in: String,
+        limit: Option<u32>,
+    },
+    PlaceTwapOrder(MsgPlaceTwapOrderRequest),
+    CancelTwapOrder { order_id: u64 },
+    /// Sends the next slice of a TWAP order once its interval has passed. Anyone may call it.
+    ExecuteTwapOrder { order_id: u64 },
+    /// Sends a timed out packet of the caller again. Only message types that are safe to
+    /// repeat can be retried, swaps are re-priced against the current pool.
+    RetryPacket {
+        channel_id: String,
+        sequence: u64,
+        timeout_height: u64,
+        timeout_timestamp: u64,
+    },
+    /// Refunds a timed out packet of the caller that was held for a retry.
+    RefundPacket { channel_id: String, sequence: u64 },
+    /// Proposes a new admin, who takes over once it accepts. Admin only.
+    TransferOwnership { new_admin: String },
+    AcceptOwnership {},
+    /// Gives up the admin role for good. Admin only.
+    RenounceOwnership {},
+    /// Stops all new outbound packets. Acks and timeouts keep settling, so refunds still
+    /// complete. Guardian or admin.
+    Pause {},
+    /// Admin only.
+    Unpause {},
+    SetGuardian { address: String },
+    /// Enables or disables sending one message type. The guardian may only disable.
+    SetMessageEnabled {
+        message_type: InterchainMessageType,
+        enabled: bool,
+    },
+    /// Serves pools with a counterparty chain over an open channel. Admin only.
+    RegisterChannel { chain_id: String, channel_id: String },
+    /// Pays out the local side of a frozen pool. LP holders redeem `amount` pool tokens for
+    /// their share of it, the pool tokens must be approved to this contract. If the pool
+    /// was never taken, its creator reclaims the whole deposit with a zero `amount`.
+    WithdrawFrozen { pool_id: String, amount: Uint128 },
+    /// Cancels expired multi asset deposit orders made on this chain and refunds their
+    /// makers once the counterparty confirms, or right away when the pool is frozen. Anyone
+    /// may call it, at most `limit` orders are considered.
+    SweepExpiredOrders { limit: Option<u32> },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -70,6 +136,10 @@ pub struct MsgMakePoolRequest {
     pub counterparty_creator: String,
     pub liquidity: Vec<PoolAsset>,
     pub swap_fee: u32,
+    #[serde(default)]
+    pub curve: PoolCurve,
+    #[serde(default)]
+    pub dynamic_fee: Option<DynamicFee>,
     pub timeout_height: u64,
     pub timeout_timestamp: u64,
     pub memo: Option<Binary>,
@@ -79,20 +149,63 @@ impl MsgMakePoolRequest {
     pub fn validate_basic(&self) -> Result<Response, ContractError> {
         let denom_size = self.liquidity.len();
         // // validation message
-        if denom_size != 2 {
+        if !(MIN_POOL_ASSETS..=MAX_POOL_ASSETS).contains(&denom_size) {
+            return Err(ContractError::InvalidDenomPair);
+        }
+
+        // Both chains must contribute at least one asset
+        if !self.liquidity.iter().any(|asset| asset.side == PoolSide::SOURCE)
+            || !self
+                .liquidity
+                .iter()
+                .any(|asset| asset.side == PoolSide::DESTINATION)
+        {
+            return Err(ContractError::InvalidAssetInput);
+        }
+
+        let mut denoms: Vec<&str> = self
+            .liquidity
+            .iter()
+            .map(|asset| asset.balance.denom.as_str())
+            .collect();
+        denoms.sort();
+        denoms.dedup();
+        if denoms.len() != denom_size {
             return Err(ContractError::InvalidDenomPair);
         }
 
         let mut total_weight: u32 = 0;
 
-        for i in 0..self.liquidity.len() {
-            total_weight += self.liquidity[i].weight;
+        for asset in &self.liquidity {
+            if asset.weight == 0 || asset.balance.amount.is_zero() {
+                return Err(ContractError::InvalidAssetInput);
+            }
+            total_weight += asset.weight;
         }
 
         if total_weight != 100 {
             return Err(ContractError::InvalidWeightPair);
         }
 
+        if let Some(dynamic_fee) = &self.dynamic_fee {
+            if dynamic_fee.min_fee > dynamic_fee.max_fee
+                || dynamic_fee.max_fee > FEE_PRECISION.into()
+            {
+                return Err(ContractError::Std(StdError::generic_err(
+                    "Invalid dynamic fee bounds",
+                )));
+            }
+        }
+
+        if let PoolCurve::Stable { amp } = self.curve {
+            if amp == 0 || amp > MAX_AMP {
+                return Err(ContractError::Std(StdError::generic_err(format!(
+                    "Amplification must be between 1 and {}",
+                    MAX_AMP
+                ))));
+            }
+        }
+
         Ok(Response::default())
     }
 }
@@ -104,6 +217,69 @@ pub struct MsgMakePoolResponse {
     pool_id: String,
 }
 
+/// Starts a linear ramp of a stable pool's amplification towards `future_amp`,
+/// reached at block time `future_time` (in seconds).
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MsgRampAmpRequest {
+    pub pool_id: String,
+    pub future_amp: u64,
+    pub future_time: u64,
+    pub timeout_height: u64,
+    pub timeout_timestamp: u64,
+    pub memo: Option<Binary>,
+}
+
+/// Places a resting order selling `token_in`, which must be sent along, once the pool
+/// returns at least `price` units of `denom_out` per unit offered.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MsgPlaceLimitOrderRequest {
+    pub pool_id: String,
+    pub token_in: Coin,
+    pub denom_out: String,
+    pub price: Decimal256,
+    /// Receiver of the output on the counterparty chain, the sender when unset
+    pub recipient: Option<String>,
+}
+
+/// Sells `token_in`, which must be sent along, in `slices` equal swaps at least
+/// `interval` seconds apart. Each slice may return at most `slippage` basis points
+/// less than the spot price when it is sent, swap fees included.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MsgPlaceTwapOrderRequest {
+    pub pool_id: String,
+    pub token_in: Coin,
+    pub denom_out: String,
+    pub slices: u32,
+    pub interval: u64,
+    pub slippage: u64,
+    /// Receiver of the output on the counterparty chain, the sender when unset
+    pub recipient: Option<String>,
+}
+
+/// Stops an amplification ramp, fixing the amplification at its current value.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MsgStopRampAmpRequest {
+    pub pool_id: String,
+    pub timeout_height: u64,
+    pub timeout_timestamp: u64,
+    pub memo: Option<Binary>,
+}
+
+/// Pauses, resumes or deprecates a pool on both chains.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MsgSetPoolStatusRequest {
+    pub pool_id: String,
+    pub status: PoolStatus,
+    pub timeout_height: u64,
+    pub timeout_timestamp: u64,
+    pub memo: Option<Binary>,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct MsgTakePoolRequest {
@@ -172,6 +348,10 @@ pub struct MsgMakeMultiAssetDepositRequest {
     pub timeout_height: u64,
     pub timeout_timestamp: u64,
     pub memo: Option<Binary>,
+    /// Block time in seconds from which the order can no longer be taken. Defaults to a day
+    /// after it is made.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -234,6 +414,8 @@ pub struct MsgSingleAssetWithdrawRequest {
     pub sender: String,
     pub denom_out: String,
     pub pool_coin: Coin,
+    pub timeout_height: u64,
+    pub timeout_timestamp: u64,
     pub memo: Option<Binary>,
 }
 
@@ -266,7 +448,11 @@ pub struct MsgSwapRequest {
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct SwapRoute {
+    /// Hops to run after this swap settles, in order. Hops against pools known to this
+    /// contract continue over IBC from the chain holding the previous hop's output,
+    /// other hops are handed to the router.
     pub requests: Vec<SwapRequest>,
+    /// Minimum output of the final hop.
     pub minimum_receive: Option<Uint128>,
 }
 
@@ -281,6 +467,10 @@ pub struct SwapRequest {
     pub asset_out: String,
     /// Contract address, if interchain request
     pub contract_address: String,
+    /// Receiver of this hop's output on the chain where it settles.
+    /// Defaults to the recipient of the previous hop.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -355,6 +545,29 @@ pub enum RouterExecuteMsg {
     }
 }
 
+/// Callbacks the ibc-hooks middleware makes for ICS-20 transfers this contract sent with an
+/// `ibc_callback` memo, in the middleware's snake_case encoding.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SudoMsg {
+    IbcLifecycleComplete(IbcLifecycleComplete),
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum IbcLifecycleComplete {
+    IbcAck {
+        channel: String,
+        sequence: u64,
+        ack: String,
+        success: bool,
+    },
+    IbcTimeout {
+        channel: String,
+        sequence: u64,
+    },
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub enum QueryMsg {
     /// Show all open orders. Return type is ListResponse.
@@ -366,8 +579,39 @@ pub enum QueryMsg {
         pool_id: String,
         order_id: String,
     },
+    /// Deposit orders made by `source_maker`, only those in `status` if set. Orders are
+    /// keyed by pool and order id and grouped by status. Return type is OrderListResponse.
+    OrdersByMaker {
+        source_maker: String,
+        status: Option<OrderStatus>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Deposit orders waiting on or taken by `destination_taker`. Return type is
+    /// OrderListResponse.
+    OrdersByTaker {
+        destination_taker: String,
+        status: Option<OrderStatus>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Deposit orders of a pool. Return type is OrderListResponse.
+    OrdersByPool {
+        pool_id: String,
+        status: Option<OrderStatus>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Deposit orders in `status` across all pools. Return type is OrderListResponse.
+    OrdersByStatus {
+        status: OrderStatus,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
     /// Query config
     Config {},
+    /// Current and proposed admin. Return type is OwnershipResponse.
+    Ownership {},
     /// Query all pool token list
     PoolTokenList {
         start_after: Option<String>,
@@ -383,6 +627,16 @@ pub enum QueryMsg {
         start_after: Option<String>,
         limit: Option<u32>,
     },
+    /// Pools matching every filter that is set. Return type is
+    /// QueryAllInterchainLiquidityPoolResponse.
+    InterchainPools(QueryAllInterchainLiquidityPoolRequest),
+    /// Pools holding exactly `denoms`, given in any order. Pools of a pair may differ in
+    /// weights, fee tier or curve. Return type is InterchainListResponse.
+    PoolsByPair {
+        denoms: Vec<String>,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
     LeftSwap {
         pool_id: String,
         token_in: Coin,
@@ -402,6 +656,87 @@ pub enum QueryMsg {
         amount: Uint128,
         pool_id: String,
     },
+    /// Protocol fees accrued on this chain for a pool. Return type is Vec<Coin>.
+    ProtocolFees {
+        pool_id: String,
+    },
+    /// Time weighted average prices between two block times, in seconds.
+    /// Return type is TwapResponse.
+    Twap {
+        pool_id: String,
+        start: u64,
+        end: u64,
+    },
+    LimitOrder {
+        order_id: u64,
+    },
+    /// Orders selling `denom_in` on a pool, from the lowest price up.
+    /// Return type is LimitOrderListResponse.
+    LimitOrders {
+        pool_id: String,
+        denom_in: String,
+        limit: Option<u32>,
+    },
+    /// TWAP order and its progress. Return type is TwapOrder.
+    TwapOrder {
+        order_id: u64,
+    },
+    /// Packet sent from this chain that is not settled yet. Return type is InFlightPacket.
+    PendingPacket {
+        channel_id: String,
+        sequence: u64,
+    },
+    /// Unsettled packets of a sender, ordered by channel and sequence.
+    /// Return type is PendingPacketListResponse.
+    PendingPacketsBySender {
+        sender: String,
+        start_after: Option<(String, u64)>,
+        limit: Option<u32>,
+    },
+    /// Unsettled packets of a pool. Return type is PendingPacketListResponse.
+    PendingPacketsByPool {
+        pool_id: String,
+        start_after: Option<(String, u64)>,
+        limit: Option<u32>,
+    },
+    /// Channel registered for a counterparty chain. Return type is ChainChannelResponse.
+    ChainChannel {
+        chain_id: String,
+    },
+    /// Registered channels by chain id. Return type is ChainChannelListResponse.
+    ChainChannels {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Frozen pools with the reason they were frozen. Return type is FrozenPoolListResponse.
+    FrozenPools {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    /// Prices a swap the way `Swap` would. Return type is SimulationResponse.
+    SimulateSwap {
+        pool_id: String,
+        swap_type: SwapMsgType,
+        token_in: Coin,
+        token_out: Coin,
+        slippage: u64,
+    },
+    /// Prices a single asset deposit. Return type is SimulationResponse.
+    SimulateSingleDeposit {
+        pool_id: String,
+        token: Coin,
+    },
+    /// Prices a multi asset deposit order. Return type is SimulationResponse.
+    SimulateMultiDeposit {
+        pool_id: String,
+        deposits: Vec<DepositAsset>,
+    },
+    /// Prices burning `pool_token` into `denom_out`, or into every asset when no denom is
+    /// given. Return type is SimulationResponse.
+    SimulateWithdraw {
+        pool_token: Coin,
+        denom_out: Option<String>,
+    },
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -417,6 +752,49 @@ pub struct InterchainPoolResponse {
     pub status: PoolStatus,
     pub counter_party_port: String,
     pub counter_party_channel: String,
+    pub curve: PoolCurve,
+    pub amp_ramp: Option<AmpRamp>,
+    pub dynamic_fee: Option<DynamicFee>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct AssetPrice {
+    pub denom: String,
+    pub price: Decimal256,
+}
+
+/// Average price of every pool asset over `[start, end]`, quoted in `base_denom`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct TwapResponse {
+    pub pool_id: String,
+    pub base_denom: String,
+    pub start: u64,
+    pub end: u64,
+    pub prices: Vec<AssetPrice>,
+}
+
+/// Result of the `LeftSwap` and `RightSwap` queries. `amount` is the output of a
+/// left swap or the required offer of a right swap, `fee` is charged on the offer.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct QuerySwapResponse {
+    pub amount: Coin,
+    pub fee: Coin,
+    pub fee_rate: u32,
+}
+
+/// Result of the simulation queries. Spot prices are of the asset going out in units of the
+/// asset going in, or of another pool asset when only one asset or the pool token moves. They
+/// are taken before and after the acknowledgement updates the pool. `state_change` is what
+/// the packet would carry.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct SimulationResponse {
+    pub amount_in: Vec<Coin>,
+    pub amount_out: Vec<Coin>,
+    pub fee: Vec<Coin>,
+    pub spot_price_before: Decimal256,
+    pub spot_price_after: Decimal256,
+    pub price_impact_bps: u64,
+    pub state_change: StateChange,
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
@@ -429,6 +807,32 @@ pub struct OrderListResponse {
     pub orders: Vec<MultiAssetDepositOrder>,
 }
 
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct LimitOrderListResponse {
+    pub orders: Vec<LimitOrder>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PendingPacketListResponse {
+    pub packets: Vec<InFlightPacket>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ChainChannelResponse {
+    pub chain_id: String,
+    pub channel: ChannelInfo,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ChainChannelListResponse {
+    pub channels: Vec<ChainChannelResponse>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct FrozenPoolListResponse {
+    pub pools: Vec<FrozenPool>,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct PoolListResponse {
     pub pools: Vec<String>,
@@ -473,6 +877,24 @@ pub struct QueryGetInterchainLiquidityPoolResponse {
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct QueryAllInterchainLiquidityPoolRequest {
     pub pagination: PageRequest,
+    /// Only pools holding this denom.
+    pub denom: Option<String>,
+    /// Only pools shared with this chain.
+    pub counterparty_chain_id: Option<String>,
+    pub status: Option<PoolStatus>,
+    /// Only pools created by this address, on either chain.
+    pub creator: Option<String>,
+    #[serde(default)]
+    pub order_by: PoolOrderBy,
+}
+
+/// Order of pools in discovery queries. `PageRequest::reverse` flips it.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug, Default)]
+pub enum PoolOrderBy {
+    #[default]
+    Id,
+    /// By `InterchainLiquidityPool::liquidity`, smallest first.
+    Liquidity,
 }
 
 // QueryAllInterchainLiquidityPoolResponse is the response type for the Query/AllInterchainLiquidityPool RPC method.
@@ -522,6 +944,23 @@ pub struct QueryConfigResponse {
     pub counter: u64,
     /// For Instantiating cw20 tokens
     pub token_code_id: u64,
+    /// Share of each swap fee kept by the protocol
+    pub protocol_fee_rate: u32,
+    /// Address allowed to claim protocol fees
+    pub fee_collector: String,
+    /// Address allowed to pause the contract
+    pub guardian: String,
+    /// Whether sending new packets is paused
+    pub paused: bool,
+    /// Message types that may not be sent from this chain
+    pub disabled_messages: Vec<InterchainMessageType>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct OwnershipResponse {
+    /// None once ownership was renounced
+    pub admin: Option<String>,
+    pub pending_admin: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
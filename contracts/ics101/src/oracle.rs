@@ -0,0 +1,195 @@
+use cosmwasm_std::{Decimal256, Order, StdError, StdResult, Storage};
+use cw_storage_plus::Bound;
+
+use crate::{
+    market::{InterchainLiquidityPool, InterchainMarketMaker, PoolStatus},
+    msg::{AssetPrice, TwapResponse},
+    state::{PriceObservation, PRICE_OBSERVATIONS},
+};
+
+/// Observations older than this, in seconds, are pruned as new ones are recorded.
+pub const OBSERVATION_HISTORY: u64 = 48 * 60 * 60;
+/// Maximum number of stale observations removed per recording.
+const PRUNE_LIMIT: usize = 10;
+
+/// Records the pool's prices after a balance change at block time `now` (in seconds).
+/// The prices of the previous observation are integrated over the elapsed time first,
+/// so each price is weighted by how long it was quoted.
+pub fn record_observation(
+    storage: &mut dyn Storage,
+    now: u64,
+    pool: &InterchainLiquidityPool,
+) -> StdResult<()> {
+    // Prices are undefined until the pool holds every asset
+    if pool.status != PoolStatus::Active
+        || pool.supply.amount.is_zero()
+        || pool
+            .assets
+            .iter()
+            .any(|asset| asset.balance.amount.is_zero())
+    {
+        return Ok(());
+    }
+
+    let prices = spot_prices(pool, now)?;
+    let cumulative_prices = match latest_observation(storage, &pool.id, now)? {
+        Some(last) => accumulate(&last, now)?,
+        None => vec![Decimal256::zero(); prices.len()],
+    };
+
+    PRICE_OBSERVATIONS.save(
+        storage,
+        (&pool.id, now),
+        &PriceObservation {
+            timestamp: now,
+            prices,
+            cumulative_prices,
+        },
+    )?;
+
+    prune_observations(storage, &pool.id, now)
+}
+
+/// Returns the time weighted average prices of the pool assets between `start` and `end`.
+pub fn query_twap(
+    storage: &dyn Storage,
+    pool: &InterchainLiquidityPool,
+    start: u64,
+    end: u64,
+    now: u64,
+) -> StdResult<TwapResponse> {
+    if start >= end {
+        return Err(StdError::generic_err("TWAP start must be before end"));
+    }
+    if end > now {
+        return Err(StdError::generic_err("TWAP end cannot be in the future"));
+    }
+
+    let cumulative_start = cumulative_at(storage, &pool.id, start)?;
+    let cumulative_end = cumulative_at(storage, &pool.id, end)?;
+    let duration = Decimal256::from_ratio(end - start, 1u64);
+
+    let prices = pool
+        .assets
+        .iter()
+        .zip(cumulative_start.iter().zip(cumulative_end.iter()))
+        .map(|(asset, (from, to))| {
+            Ok(AssetPrice {
+                denom: asset.balance.denom.clone(),
+                price: to
+                    .checked_sub(*from)?
+                    .checked_div(duration)
+                    .map_err(|e| StdError::generic_err(e.to_string()))?,
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(TwapResponse {
+        pool_id: pool.id.clone(),
+        base_denom: pool.assets[0].balance.denom.clone(),
+        start,
+        end,
+        prices,
+    })
+}
+
+/// Spot price of every asset quoted in the first pool asset.
+fn spot_prices(pool: &InterchainLiquidityPool, now: u64) -> StdResult<Vec<Decimal256>> {
+    let amm = InterchainMarketMaker::new(&pool.at_time(now), pool.swap_fee);
+    let base = &pool.assets[0].balance.denom;
+    pool.assets
+        .iter()
+        .map(|asset| amm.spot_price(base, &asset.balance.denom))
+        .collect()
+}
+
+fn latest_observation(
+    storage: &dyn Storage,
+    pool_id: &str,
+    at: u64,
+) -> StdResult<Option<PriceObservation>> {
+    PRICE_OBSERVATIONS
+        .prefix(pool_id)
+        .range(storage, None, Some(Bound::inclusive(at)), Order::Descending)
+        .next()
+        .transpose()
+        .map(|item| item.map(|(_, observation)| observation))
+}
+
+/// Integrates the observation's prices up to `at`.
+fn accumulate(observation: &PriceObservation, at: u64) -> StdResult<Vec<Decimal256>> {
+    let elapsed = Decimal256::from_ratio(at - observation.timestamp, 1u64);
+    observation
+        .cumulative_prices
+        .iter()
+        .zip(observation.prices.iter())
+        .map(|(cumulative, price)| Ok(cumulative.checked_add(price.checked_mul(elapsed)?)?))
+        .collect()
+}
+
+fn cumulative_at(storage: &dyn Storage, pool_id: &str, at: u64) -> StdResult<Vec<Decimal256>> {
+    let observation = latest_observation(storage, pool_id, at)?.ok_or_else(|| {
+        StdError::generic_err(format!("No price observation at or before {}", at))
+    })?;
+    accumulate(&observation, at)
+}
+
+/// Removes observations older than [`OBSERVATION_HISTORY`], keeping the latest of them
+/// so that the start of the history window can still be interpolated.
+fn prune_observations(storage: &mut dyn Storage, pool_id: &str, now: u64) -> StdResult<()> {
+    let cutoff = now.saturating_sub(OBSERVATION_HISTORY);
+    let stale: Vec<u64> = PRICE_OBSERVATIONS
+        .prefix(pool_id)
+        .keys(
+            storage,
+            None,
+            Some(Bound::exclusive(cutoff)),
+            Order::Ascending,
+        )
+        .take(PRUNE_LIMIT + 1)
+        .collect::<StdResult<_>>()?;
+
+    if let Some((_, older)) = stale.split_last() {
+        for timestamp in older {
+            PRICE_OBSERVATIONS.remove(storage, (pool_id, *timestamp));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::testing::MockStorage;
+
+    use super::*;
+    use crate::market::PoolSide;
+    use crate::testing;
+
+    fn mock_pool(balance_a: u128, balance_b: u128) -> InterchainLiquidityPool {
+        testing::mock_pool(
+            "pool1",
+            &[
+                (PoolSide::SOURCE, "aside", balance_a, 50),
+                (PoolSide::DESTINATION, "bside", balance_b, 50),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_twap_weights_prices_by_duration() {
+        let mut storage = MockStorage::new();
+
+        // bside quotes at 1 aside for 100 seconds, then at 4 aside for 300 seconds
+        record_observation(&mut storage, 1_000, &mock_pool(1_000_000, 1_000_000)).unwrap();
+        record_observation(&mut storage, 1_100, &mock_pool(2_000_000, 500_000)).unwrap();
+
+        let pool = mock_pool(2_000_000, 500_000);
+        let twap = query_twap(&storage, &pool, 1_000, 1_400, 1_400).unwrap();
+        assert_eq!(twap.base_denom, "aside");
+        assert_eq!(twap.prices[0].price, Decimal256::one());
+        assert_eq!(twap.prices[1].price, Decimal256::from_ratio(13u64, 4u64));
+
+        // Nothing was observed before the pool became active
+        assert!(query_twap(&storage, &mock_pool(1, 1), 900, 1_000, 1_400).is_err());
+    }
+}
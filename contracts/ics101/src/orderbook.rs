@@ -0,0 +1,596 @@
+use cosmwasm_std::{
+    attr, Coin, Decimal256, Deps, DepsMut, Env, MessageInfo, Order, Response, StdError, StdResult,
+    Uint128,
+};
+
+use crate::{
+    contract::{quote_swap_on, send_swap_packet, swap_packet},
+    error::ContractError,
+    market::{InterchainMarketMaker, PoolSide, PoolStatus},
+    msg::{
+        LimitOrderListResponse, MsgPlaceLimitOrderRequest, MsgPlaceTwapOrderRequest,
+        MsgSwapRequest, SwapMsgType,
+    },
+    state::{
+        limit_orders, LimitOrder, TwapOrder, LIMIT_ORDER_COUNT, POOLS, TWAP_ORDERS,
+        TWAP_ORDER_COUNT,
+    },
+    utils::{funds_match, send_tokens_coin, MAXIMUM_SLIPPAGE},
+};
+
+const MAX_LIMIT: u32 = 30;
+const DEFAULT_LIMIT: u32 = 10;
+/// Upper bound on the number of slices of a TWAP order.
+const MAX_TWAP_SLICES: u32 = 100;
+
+pub fn place_limit_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: MsgPlaceLimitOrderRequest,
+) -> Result<Response, ContractError> {
+    if !funds_match(&info.funds, std::slice::from_ref(&msg.token_in)) {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Funds mismatch: Funds mismatched to with message and sent values: Limit order"
+                .to_string(),
+        )));
+    }
+    if msg.price.is_zero() {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Limit order price must be positive".to_string(),
+        )));
+    }
+
+    validate_sell(deps.as_ref(), &msg.pool_id, &msg.token_in, &msg.denom_out)?;
+
+    let id = LIMIT_ORDER_COUNT
+        .may_load(deps.storage)?
+        .unwrap_or_default()
+        + 1;
+    LIMIT_ORDER_COUNT.save(deps.storage, &id)?;
+
+    let order = LimitOrder {
+        id,
+        owner: info.sender.to_string(),
+        pool_id: msg.pool_id,
+        token_in: msg.token_in,
+        denom_out: msg.denom_out,
+        price: msg.price,
+        recipient: msg.recipient.unwrap_or_else(|| info.sender.to_string()),
+        created_at: env.block.time.seconds(),
+    };
+    limit_orders().save(deps.storage, id, &order)?;
+
+    Ok(Response::default()
+        .add_attribute("pool_id", order.pool_id)
+        .add_attribute("order_id", id.to_string())
+        .add_attribute("action", "place_limit_order"))
+}
+
+pub fn cancel_limit_order(
+    deps: DepsMut,
+    info: MessageInfo,
+    order_id: u64,
+) -> Result<Response, ContractError> {
+    let order = limit_orders().load(deps.storage, order_id)?;
+    if order.owner != info.sender {
+        return Err(ContractError::InvalidSender);
+    }
+    limit_orders().remove(deps.storage, order_id)?;
+
+    Ok(Response::default()
+        .add_submessages(send_tokens_coin(&info.sender, order.token_in)?)
+        .add_attribute("pool_id", order.pool_id)
+        .add_attribute("order_id", order_id.to_string())
+        .add_attribute("action", "cancel_limit_order"))
+}
+
+/// Fills the resting orders selling `denom_in` on a pool whose price the pool can
+/// currently pay, cheapest first. Each fill is sent as a swap bound by the order's
+/// price and priced like any other swap, against the pool as last settled here with
+/// the earlier fills of the batch applied. Orders that cannot be sent stay on the book
+/// and are listed with their error. A fill rejected by the counterparty refunds its
+/// escrow to the order's owner.
+pub fn execute_limit_orders(
+    deps: DepsMut,
+    env: Env,
+    pool_id: String,
+    denom_in: String,
+    limit: Option<u32>,
+) -> Result<Response, ContractError> {
+    let mut interchain_pool = POOLS
+        .may_load(deps.storage, &pool_id)?
+        .ok_or_else(|| StdError::generic_err(format!("Pool doesn't exist {}", pool_id)))?;
+    if interchain_pool.status != PoolStatus::Active {
+        return Err(ContractError::NotReadyForSwap);
+    }
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let orders = book(deps.as_ref(), &pool_id, &denom_in, limit)?;
+
+    let mut sub_messages = vec![];
+    let mut filled = vec![];
+    let mut skipped = vec![];
+    for order in orders {
+        let swap_msg = MsgSwapRequest {
+            swap_type: SwapMsgType::LEFT,
+            sender: order.owner.clone(),
+            pool_id: order.pool_id.clone(),
+            token_in: order.token_in.clone(),
+            token_out: Coin {
+                denom: order.denom_out.clone(),
+                amount: limit_order_min_out(&order)?,
+            },
+            slippage: 0,
+            recipient: order.recipient.clone(),
+            timeout_height: 0,
+            timeout_timestamp: 0,
+            route: None,
+            memo: None,
+        };
+        let quote = match quote_swap_on(
+            deps.storage,
+            &env,
+            interchain_pool.clone(),
+            &swap_msg.swap_type,
+            &swap_msg.token_in,
+            &swap_msg.token_out,
+            swap_msg.slippage,
+        ) {
+            Ok(quote) => quote,
+            // The pool cannot pay the order's price yet
+            Err(ContractError::FailedOnSwapReceived { .. }) => continue,
+            Err(err) => {
+                skipped.push(attr("skipped_order", format!("{}: {}", order.id, err)));
+                continue;
+            }
+        };
+
+        // Later orders are priced as if this fill had already settled
+        let mut next_pool = interchain_pool.clone();
+        let protocol_fee: Uint128 = quote
+            .state_change
+            .protocol_fees
+            .iter()
+            .flatten()
+            .map(|fee| fee.amount)
+            .sum();
+        next_pool.add_asset(Coin {
+            denom: order.token_in.denom.clone(),
+            amount: order
+                .token_in
+                .amount
+                .checked_sub(protocol_fee)
+                .map_err(StdError::from)?,
+        })?;
+        for token_out in quote.state_change.out_tokens.iter().flatten() {
+            next_pool.subtract_asset(token_out.clone())?;
+        }
+
+        match send_swap_packet(deps.storage, &env, swap_msg, quote) {
+            Ok((ibc_msg, _)) => {
+                sub_messages.push(ibc_msg);
+                limit_orders().remove(deps.storage, order.id)?;
+                filled.push(order.id.to_string());
+                interchain_pool = next_pool;
+            }
+            Err(err) => skipped.push(attr("skipped_order", format!("{}: {}", order.id, err))),
+        }
+    }
+
+    Ok(Response::default()
+        .add_submessages(sub_messages)
+        .add_attribute("pool_id", pool_id)
+        .add_attribute("action", "execute_limit_orders")
+        .add_attribute("filled_orders", filled.join(","))
+        .add_attributes(skipped))
+}
+
+pub fn place_twap_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: MsgPlaceTwapOrderRequest,
+) -> Result<Response, ContractError> {
+    if !funds_match(&info.funds, std::slice::from_ref(&msg.token_in)) {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Funds mismatch: Funds mismatched to with message and sent values: TWAP order"
+                .to_string(),
+        )));
+    }
+    if msg.slices == 0 || msg.slices > MAX_TWAP_SLICES {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "TWAP order slices must be between 1 and {}",
+            MAX_TWAP_SLICES
+        ))));
+    }
+    if msg.token_in.amount < Uint128::from(msg.slices) {
+        return Err(ContractError::Std(StdError::generic_err(
+            "TWAP order amount is too small for its slices".to_string(),
+        )));
+    }
+    if msg.slices > 1 && msg.interval == 0 {
+        return Err(ContractError::Std(StdError::generic_err(
+            "TWAP order interval must be positive".to_string(),
+        )));
+    }
+    if msg.slippage > MAXIMUM_SLIPPAGE {
+        return Err(ContractError::InvalidSlippage {});
+    }
+    validate_sell(deps.as_ref(), &msg.pool_id, &msg.token_in, &msg.denom_out)?;
+
+    let id = TWAP_ORDER_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    TWAP_ORDER_COUNT.save(deps.storage, &id)?;
+
+    let order = TwapOrder {
+        id,
+        owner: info.sender.to_string(),
+        pool_id: msg.pool_id,
+        remaining: msg.token_in.clone(),
+        token_in: msg.token_in,
+        denom_out: msg.denom_out,
+        slices: msg.slices,
+        interval: msg.interval,
+        slippage: msg.slippage,
+        recipient: msg.recipient.unwrap_or_else(|| info.sender.to_string()),
+        slices_sent: 0,
+        next_execution: env.block.time.seconds(),
+        created_at: env.block.time.seconds(),
+    };
+    TWAP_ORDERS.save(deps.storage, id, &order)?;
+
+    Ok(Response::default()
+        .add_attribute("pool_id", order.pool_id)
+        .add_attribute("order_id", id.to_string())
+        .add_attribute("action", "place_twap_order"))
+}
+
+/// Cancels the slices of a TWAP order not sent yet and refunds their escrow.
+/// Slices already in flight settle or refund on their own.
+pub fn cancel_twap_order(
+    deps: DepsMut,
+    info: MessageInfo,
+    order_id: u64,
+) -> Result<Response, ContractError> {
+    let order = TWAP_ORDERS.load(deps.storage, order_id)?;
+    if order.owner != info.sender {
+        return Err(ContractError::InvalidSender);
+    }
+    if order.remaining.amount.is_zero() {
+        return Err(ContractError::InvalidStatus);
+    }
+    TWAP_ORDERS.remove(deps.storage, order_id);
+
+    Ok(Response::default()
+        .add_submessages(send_tokens_coin(&info.sender, order.remaining)?)
+        .add_attribute("pool_id", order.pool_id)
+        .add_attribute("order_id", order_id.to_string())
+        .add_attribute("action", "cancel_twap_order"))
+}
+
+/// Sends the next slice of a TWAP order as its own swap. The slice must return at
+/// least the spot price less the order's slippage, otherwise the crank fails and the
+/// slice can be retried later.
+pub fn execute_twap_order(
+    mut deps: DepsMut,
+    env: Env,
+    order_id: u64,
+) -> Result<Response, ContractError> {
+    let mut order = TWAP_ORDERS.load(deps.storage, order_id)?;
+    if order.remaining.amount.is_zero() {
+        return Err(ContractError::InvalidStatus);
+    }
+    let now = env.block.time.seconds();
+    if now < order.next_execution {
+        return Err(ContractError::Std(StdError::generic_err(format!(
+            "Next slice of TWAP order {} is due at {}",
+            order_id, order.next_execution
+        ))));
+    }
+
+    let interchain_pool = POOLS
+        .may_load(deps.storage, &order.pool_id)?
+        .ok_or_else(|| StdError::generic_err(format!("Pool doesn't exist {}", order.pool_id)))?;
+    let amm = InterchainMarketMaker {
+        pool_id: interchain_pool.id.clone(),
+        pool: interchain_pool.at_time(now),
+        fee_rate: interchain_pool.swap_fee,
+    };
+
+    // The last slice also takes the remainder of the division
+    let slice = Coin {
+        denom: order.token_in.denom.clone(),
+        amount: if order.slices_sent + 1 == order.slices {
+            order.remaining.amount
+        } else {
+            order.token_in.amount / Uint128::from(order.slices)
+        },
+    };
+    let swap_msg = MsgSwapRequest {
+        swap_type: SwapMsgType::LEFT,
+        sender: order.owner.clone(),
+        pool_id: order.pool_id.clone(),
+        token_in: slice.clone(),
+        token_out: Coin {
+            denom: order.denom_out.clone(),
+            amount: amm.spot_output(&slice, &order.denom_out)?,
+        },
+        slippage: order.slippage,
+        recipient: order.recipient.clone(),
+        timeout_height: 0,
+        timeout_timestamp: 0,
+        route: None,
+        memo: None,
+    };
+    let (ibc_msg, attributes) = swap_packet(deps.branch(), &env, swap_msg)?;
+
+    order.remaining.amount -= slice.amount;
+    order.slices_sent += 1;
+    order.next_execution = now + order.interval;
+    TWAP_ORDERS.save(deps.storage, order_id, &order)?;
+
+    Ok(Response::default()
+        .add_submessage(ibc_msg)
+        .add_attributes(attributes)
+        .add_attribute("order_id", order_id.to_string())
+        .add_attribute("action", "execute_twap_order")
+        .add_attribute("slices_sent", order.slices_sent.to_string()))
+}
+
+pub fn query_twap_order(deps: Deps, order_id: u64) -> StdResult<TwapOrder> {
+    TWAP_ORDERS.load(deps.storage, order_id)
+}
+
+pub fn query_limit_order(deps: Deps, order_id: u64) -> StdResult<LimitOrder> {
+    limit_orders().load(deps.storage, order_id)
+}
+
+pub fn query_limit_orders(
+    deps: Deps,
+    pool_id: String,
+    denom_in: String,
+    limit: Option<u32>,
+) -> StdResult<LimitOrderListResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    Ok(LimitOrderListResponse {
+        orders: book(deps, &pool_id, &denom_in, limit)?,
+    })
+}
+
+/// Orders selling `denom_in` on a pool, from the lowest price up.
+fn book(deps: Deps, pool_id: &str, denom_in: &str, limit: usize) -> StdResult<Vec<LimitOrder>> {
+    limit_orders()
+        .idx
+        .book
+        .sub_prefix((pool_id.to_string(), denom_in.to_string()))
+        .range(deps.storage, None, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, order)| order))
+        .collect()
+}
+
+/// Checks that an order may sell `token_in`, held on this chain, for `denom_out` on an active pool.
+fn validate_sell(
+    deps: Deps,
+    pool_id: &str,
+    token_in: &Coin,
+    denom_out: &str,
+) -> Result<(), ContractError> {
+    if token_in.amount.is_zero() {
+        return Err(ContractError::EmptyBalance {});
+    }
+    let interchain_pool = POOLS
+        .may_load(deps.storage, pool_id)?
+        .ok_or_else(|| StdError::generic_err(format!("Pool doesn't exist {}", pool_id)))?;
+    if interchain_pool.status != PoolStatus::Active {
+        return Err(ContractError::NotReadyForSwap);
+    }
+    // Orders can only sell assets held on this chain
+    if interchain_pool.find_asset_by_denom(&token_in.denom)?.side != PoolSide::SOURCE {
+        return Err(ContractError::InvalidSellToken);
+    }
+    if denom_out == token_in.denom {
+        return Err(ContractError::Std(StdError::generic_err(
+            "Order must sell for another asset".to_string(),
+        )));
+    }
+    interchain_pool.find_asset_by_denom(denom_out)?;
+    Ok(())
+}
+
+/// Smallest output satisfying the order's price, rounded up.
+fn limit_order_min_out(order: &LimitOrder) -> StdResult<Uint128> {
+    let min_out = (Decimal256::from_ratio(order.token_in.amount, 1u8) * order.price).to_uint_ceil();
+    Uint128::try_from(min_out)
+        .map_err(|_| StdError::generic_err(format!("Limit order {} output overflows", order.id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{Config, CONFIG};
+    use crate::testing::mock_pair_pool;
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+    };
+    use cosmwasm_std::{coin, BankMsg, CosmosMsg, OwnedDeps};
+
+    fn order(price: &str) -> MsgPlaceLimitOrderRequest {
+        MsgPlaceLimitOrderRequest {
+            pool_id: "pool".to_string(),
+            token_in: coin(1_000, "aside"),
+            denom_out: "bside".to_string(),
+            price: price.parse().unwrap(),
+            recipient: None,
+        }
+    }
+
+    fn setup() -> OwnedDeps<MockStorage, MockApi, MockQuerier> {
+        let mut deps = mock_dependencies();
+        CONFIG
+            .save(
+                deps.as_mut().storage,
+                &Config {
+                    counter: 0,
+                    token_code_id: 1,
+                    admin: "admin".to_string(),
+                    router: "".to_string(),
+                    protocol_fee_rate: 0,
+                    fee_collector: "admin".to_string(),
+                    guardian: "guardian".to_string(),
+                    paused: false,
+                    disabled_messages: vec![],
+                },
+            )
+            .unwrap();
+        POOLS
+            .save(
+                deps.as_mut().storage,
+                "pool",
+                &mock_pair_pool("pool", 1_000_000),
+            )
+            .unwrap();
+
+        deps
+    }
+
+    #[test]
+    fn test_limit_orders_fill_reachable_prices() {
+        let mut deps = setup();
+        let funds = [coin(1_000, "aside")];
+        for price in ["2", "0.5"] {
+            place_limit_order(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("maker", &funds),
+                order(price),
+            )
+            .unwrap();
+        }
+        // Sending less than the order offers is rejected
+        let err = place_limit_order(
+            deps.as_mut(),
+            mock_env(),
+            mock_info("maker", &[coin(999, "aside")]),
+            order("1"),
+        );
+        assert!(err.is_err());
+
+        let book = query_limit_orders(deps.as_ref(), "pool".to_string(), "aside".to_string(), None)
+            .unwrap();
+        let ids: Vec<u64> = book.orders.iter().map(|order| order.id).collect();
+        assert_eq!(ids, vec![2, 1]);
+
+        // Only the order asking half the spot price is reachable
+        let res = execute_limit_orders(
+            deps.as_mut(),
+            mock_env(),
+            "pool".to_string(),
+            "aside".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(res.messages.len(), 1);
+        assert!(query_limit_order(deps.as_ref(), 2).is_err());
+
+        assert!(cancel_limit_order(deps.as_mut(), mock_info("taker", &[]), 1).is_err());
+        let res = cancel_limit_order(deps.as_mut(), mock_info("maker", &[]), 1).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "maker".to_string(),
+                amount: funds.to_vec(),
+            })
+        );
+        assert!(query_limit_order(deps.as_ref(), 1).is_err());
+    }
+
+    #[test]
+    fn test_limit_orders_batch_applies_earlier_fills() {
+        let mut deps = setup();
+        let funds = [coin(400_000, "aside")];
+        for _ in 0..2 {
+            let msg = MsgPlaceLimitOrderRequest {
+                token_in: funds[0].clone(),
+                ..order("0.6")
+            };
+            place_limit_order(deps.as_mut(), mock_env(), mock_info("maker", &funds), msg).unwrap();
+        }
+        let execute = |deps: DepsMut| {
+            let (pool_id, denom_in) = ("pool".to_string(), "aside".to_string());
+            execute_limit_orders(deps, mock_env(), pool_id, denom_in, None).unwrap()
+        };
+
+        // A message type the guardian disabled skips every fill without failing the batch
+        let mut config = CONFIG.load(deps.as_ref().storage).unwrap();
+        config.disabled_messages = vec![crate::types::InterchainMessageType::LeftSwap];
+        CONFIG.save(deps.as_mut().storage, &config).unwrap();
+        let res = execute(deps.as_mut());
+        assert!(res.messages.is_empty());
+        let skipped = res
+            .attributes
+            .iter()
+            .filter(|attr| attr.key == "skipped_order");
+        assert_eq!(skipped.count(), 2);
+        assert!(query_limit_order(deps.as_ref(), 1).is_ok());
+        assert!(query_limit_order(deps.as_ref(), 2).is_ok());
+
+        // Each order alone is reachable, the second no longer is once the first moved the pool
+        config.disabled_messages = vec![];
+        CONFIG.save(deps.as_mut().storage, &config).unwrap();
+        let res = execute(deps.as_mut());
+        assert_eq!(res.messages.len(), 1);
+        assert!(query_limit_order(deps.as_ref(), 1).is_err());
+        assert!(query_limit_order(deps.as_ref(), 2).is_ok());
+    }
+
+    #[test]
+    fn test_twap_order_sends_slices_on_schedule() {
+        let mut deps = setup();
+        let order = MsgPlaceTwapOrderRequest {
+            pool_id: "pool".to_string(),
+            token_in: coin(1_000, "aside"),
+            denom_out: "bside".to_string(),
+            slices: 3,
+            interval: 60,
+            slippage: 100,
+            recipient: None,
+        };
+        let info = mock_info("maker", &[coin(1_000, "aside")]);
+        place_twap_order(deps.as_mut(), mock_env(), info.clone(), order.clone()).unwrap();
+
+        let mut env = mock_env();
+        execute_twap_order(deps.as_mut(), env.clone(), 1).unwrap();
+        // The next slice is not due before the interval has passed
+        assert!(execute_twap_order(deps.as_mut(), env.clone(), 1).is_err());
+        for _ in 0..2 {
+            env.block.time = env.block.time.plus_seconds(60);
+            execute_twap_order(deps.as_mut(), env.clone(), 1).unwrap();
+        }
+        let progress = query_twap_order(deps.as_ref(), 1).unwrap();
+        assert_eq!(progress.slices_sent, 3);
+        assert!(progress.remaining.amount.is_zero());
+        env.block.time = env.block.time.plus_seconds(60);
+        assert!(execute_twap_order(deps.as_mut(), env, 1).is_err());
+
+        // Slices paying less than spot less the swap fee are not sent
+        let tight = MsgPlaceTwapOrderRequest {
+            slippage: 0,
+            ..order.clone()
+        };
+        place_twap_order(deps.as_mut(), mock_env(), info.clone(), tight).unwrap();
+        assert!(execute_twap_order(deps.as_mut(), mock_env(), 2).is_err());
+
+        // Cancelling refunds the slices not sent yet
+        place_twap_order(deps.as_mut(), mock_env(), info, order).unwrap();
+        execute_twap_order(deps.as_mut(), mock_env(), 3).unwrap();
+        let res = cancel_twap_order(deps.as_mut(), mock_info("maker", &[]), 3).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send {
+                to_address: "maker".to_string(),
+                amount: vec![coin(667, "aside")],
+            })
+        );
+    }
+}
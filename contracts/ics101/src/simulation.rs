@@ -0,0 +1,345 @@
+use cosmwasm_std::{Coin, Decimal256, Deps, Env, StdError, StdResult, Uint128};
+
+use crate::contract::{
+    quote_multi_deposit, quote_multi_withdraw, quote_single_deposit, quote_single_withdraw,
+    quote_swap,
+};
+use crate::error::ContractError;
+use crate::market::{InterchainLiquidityPool, InterchainMarketMaker};
+use crate::msg::{DepositAsset, SimulationResponse, SwapMsgType};
+
+/// Price impact is reported in basis points of the spot price before the operation.
+const BPS: u64 = 10_000;
+
+fn query_err(err: ContractError) -> StdError {
+    match err {
+        ContractError::Std(err) => err,
+        err => StdError::generic_err(err.to_string()),
+    }
+}
+
+/// Spot price of `quote` in units of `base` before and after the operation, and the move
+/// between the two in basis points.
+fn price_impact(
+    now: u64,
+    before: &InterchainLiquidityPool,
+    after: &InterchainLiquidityPool,
+    base: &str,
+    quote: &str,
+) -> StdResult<(Decimal256, Decimal256, u64)> {
+    let spot_price = |pool: &InterchainLiquidityPool| {
+        InterchainMarketMaker::new(&pool.at_time(now), pool.swap_fee).spot_price(base, quote)
+    };
+    let price_before = spot_price(before)?;
+    let price_after = spot_price(after)?;
+    if price_before.is_zero() {
+        return Ok((price_before, price_after, 0));
+    }
+
+    let change = if price_after > price_before {
+        price_after - price_before
+    } else {
+        price_before - price_after
+    };
+    let impact = (change / price_before * Decimal256::from_ratio(BPS, 1u64)).to_uint_floor();
+    let impact = Uint128::try_from(impact)
+        .ok()
+        .and_then(|impact| u64::try_from(impact.u128()).ok())
+        .unwrap_or(u64::MAX);
+    Ok((price_before, price_after, impact))
+}
+
+/// The asset quoted against `denom` when only one asset of the pool moves.
+fn other_denom(pool: &InterchainLiquidityPool, denom: &str) -> StdResult<String> {
+    pool.assets
+        .iter()
+        .find(|asset| asset.balance.denom != denom)
+        .map(|asset| asset.balance.denom.clone())
+        .ok_or_else(|| StdError::generic_err(format!("No asset to quote {} in", denom)))
+}
+
+fn total_pool_tokens(pool: &InterchainLiquidityPool, pool_tokens: &[Coin]) -> Coin {
+    Coin {
+        denom: pool.supply.denom.clone(),
+        amount: pool_tokens.iter().map(|token| token.amount).sum(),
+    }
+}
+
+pub fn simulate_swap(
+    deps: Deps,
+    env: Env,
+    pool_id: String,
+    swap_type: SwapMsgType,
+    token_in: Coin,
+    token_out: Coin,
+    slippage: u64,
+) -> StdResult<SimulationResponse> {
+    let quote = quote_swap(
+        deps.storage,
+        &env,
+        &pool_id,
+        &swap_type,
+        &token_in,
+        &token_out,
+        slippage,
+    )
+    .map_err(query_err)?;
+    let computed = quote
+        .state_change
+        .out_tokens
+        .clone()
+        .unwrap_or_default()
+        .remove(0);
+    let protocol_fee: Uint128 = quote
+        .state_change
+        .protocol_fees
+        .iter()
+        .flatten()
+        .map(|fee| fee.amount)
+        .sum();
+
+    // Applied the way the acknowledgement updates the pool
+    let (amount_in, amount_out) = match swap_type {
+        SwapMsgType::LEFT => (token_in, computed),
+        SwapMsgType::RIGHT => (computed, token_out),
+    };
+    let mut after = quote.pool.clone();
+    after.add_asset(Coin {
+        denom: amount_in.denom.clone(),
+        amount: amount_in.amount.checked_sub(protocol_fee)?,
+    })?;
+    after.subtract_asset(amount_out.clone())?;
+
+    let (spot_price_before, spot_price_after, price_impact_bps) = price_impact(
+        env.block.time.seconds(),
+        &quote.pool,
+        &after,
+        &amount_in.denom,
+        &amount_out.denom,
+    )?;
+    Ok(SimulationResponse {
+        amount_in: vec![amount_in],
+        amount_out: vec![amount_out],
+        fee: vec![quote.swap_fee],
+        spot_price_before,
+        spot_price_after,
+        price_impact_bps,
+        state_change: quote.state_change,
+    })
+}
+
+pub fn simulate_single_deposit(
+    deps: Deps,
+    env: Env,
+    pool_id: String,
+    token: Coin,
+) -> StdResult<SimulationResponse> {
+    let (pool, state_change) =
+        quote_single_deposit(deps.storage, &env, &pool_id, &token).map_err(query_err)?;
+    let pool_tokens = state_change.pool_tokens.clone().unwrap_or_default();
+
+    let mut after = pool.clone();
+    after.add_asset(token.clone())?;
+    after.add_supply(total_pool_tokens(&pool, &pool_tokens))?;
+
+    let quote_denom = other_denom(&pool, &token.denom)?;
+    let (spot_price_before, spot_price_after, price_impact_bps) = price_impact(
+        env.block.time.seconds(),
+        &pool,
+        &after,
+        &token.denom,
+        &quote_denom,
+    )?;
+    Ok(SimulationResponse {
+        amount_in: vec![token],
+        amount_out: pool_tokens,
+        fee: vec![],
+        spot_price_before,
+        spot_price_after,
+        price_impact_bps,
+        state_change,
+    })
+}
+
+pub fn simulate_multi_deposit(
+    deps: Deps,
+    env: Env,
+    pool_id: String,
+    deposits: Vec<DepositAsset>,
+) -> StdResult<SimulationResponse> {
+    let quote = quote_multi_deposit(deps.storage, &env, &pool_id, &deposits).map_err(query_err)?;
+    let pool_tokens = quote.state_change.pool_tokens.clone().unwrap_or_default();
+    let shares = total_pool_tokens(&quote.pool, &pool_tokens);
+
+    // The maker's chain applies the order once it is taken
+    let mut after = quote.pool.clone();
+    for token in &quote.tokens {
+        after.add_asset(token.clone())?;
+    }
+    after.add_supply(shares.clone())?;
+
+    let base = quote.pool.assets[0].balance.denom.clone();
+    let quote_denom = other_denom(&quote.pool, &base)?;
+    let (spot_price_before, spot_price_after, price_impact_bps) = price_impact(
+        env.block.time.seconds(),
+        &quote.pool,
+        &after,
+        &base,
+        &quote_denom,
+    )?;
+    Ok(SimulationResponse {
+        amount_in: quote.tokens,
+        amount_out: vec![shares],
+        fee: vec![],
+        spot_price_before,
+        spot_price_after,
+        price_impact_bps,
+        state_change: quote.state_change,
+    })
+}
+
+pub fn simulate_withdraw(
+    deps: Deps,
+    env: Env,
+    pool_token: Coin,
+    denom_out: Option<String>,
+) -> StdResult<SimulationResponse> {
+    let now = env.block.time.seconds();
+    let (pool, state_change, fee) = match &denom_out {
+        Some(denom_out) => {
+            let (pool, state_change) =
+                quote_single_withdraw(deps.storage, &env, &pool_token, denom_out)
+                    .map_err(query_err)?;
+            // The fee is what the same withdraw would have paid out without it
+            let token_out = state_change
+                .out_tokens
+                .clone()
+                .unwrap_or_default()
+                .remove(0);
+            let fee_free = InterchainMarketMaker::new(&pool.at_time(now), 0)
+                .withdraw_single_asset(pool_token.clone(), denom_out)?;
+            let fee = Coin {
+                denom: token_out.denom,
+                amount: fee_free.amount.saturating_sub(token_out.amount),
+            };
+            (pool, state_change, vec![fee])
+        }
+        None => {
+            let (pool, state_change) =
+                quote_multi_withdraw(deps.storage, &env, &pool_token.denom, &pool_token)
+                    .map_err(query_err)?;
+            (pool, state_change, vec![])
+        }
+    };
+    let amount_out = state_change.out_tokens.clone().unwrap_or_default();
+
+    let mut after = pool.clone();
+    for token in &amount_out {
+        after.subtract_asset(token.clone())?;
+    }
+    after.subtract_supply(pool_token.clone())?;
+
+    let base = match denom_out {
+        Some(denom_out) => denom_out,
+        None => pool.assets[0].balance.denom.clone(),
+    };
+    let quote_denom = other_denom(&pool, &base)?;
+    let (spot_price_before, spot_price_after, price_impact_bps) =
+        price_impact(now, &pool, &after, &base, &quote_denom)?;
+    Ok(SimulationResponse {
+        amount_in: vec![pool_token],
+        amount_out,
+        fee,
+        spot_price_before,
+        spot_price_after,
+        price_impact_bps,
+        state_change,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::coin;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
+
+    use super::*;
+    use crate::contract::instantiate;
+    use crate::msg::InstantiateMsg;
+    use crate::state::POOLS;
+    use crate::testing::mock_pair_pool;
+
+    fn mock_pool() -> InterchainLiquidityPool {
+        InterchainLiquidityPool {
+            source_chain_id: mock_env().block.chain_id,
+            ..mock_pair_pool("pool1", 1_000_000)
+        }
+    }
+
+    #[test]
+    fn test_simulations_match_execute_quotes() {
+        let mut deps = mock_dependencies();
+        let msg = InstantiateMsg {
+            token_code_id: 1,
+            router: "".to_string(),
+            protocol_fee_rate: None,
+            fee_collector: None,
+            guardian: None,
+        };
+        instantiate(deps.as_mut(), mock_env(), mock_info("admin", &[]), msg).unwrap();
+        POOLS
+            .save(deps.as_mut().storage, "pool1", &mock_pool())
+            .unwrap();
+
+        // The swap reports the state change the packet would carry
+        let (token_in, token_out) = (coin(100_000, "aside"), coin(0, "bside"));
+        let swap_type = SwapMsgType::LEFT;
+        let quote = quote_swap(
+            &deps.storage,
+            &mock_env(),
+            "pool1",
+            &swap_type,
+            &token_in,
+            &token_out,
+            0,
+        )
+        .unwrap();
+        let res = simulate_swap(
+            deps.as_ref(),
+            mock_env(),
+            "pool1".to_string(),
+            swap_type,
+            token_in.clone(),
+            token_out,
+            0,
+        )
+        .unwrap();
+        assert_eq!(res.state_change, quote.state_change);
+        assert_eq!(res.amount_in, vec![token_in]);
+        assert_eq!(res.amount_out, quote.state_change.out_tokens.unwrap());
+        assert_eq!(res.fee, vec![coin(300, "aside")]);
+        assert_eq!(res.spot_price_before, Decimal256::one());
+        assert!(res.spot_price_after > res.spot_price_before);
+        assert!(res.price_impact_bps > 1_000);
+
+        // Burning into one asset pays the fee on the part that is not proportional
+        let res = simulate_withdraw(
+            deps.as_ref(),
+            mock_env(),
+            coin(200_000, "pool1"),
+            Some("aside".into()),
+        )
+        .unwrap();
+        assert_eq!(res.amount_out[0].denom, "aside");
+        assert!(!res.fee[0].amount.is_zero());
+        assert!(res.price_impact_bps > 0);
+
+        // Proportional withdraws leave the price where it is
+        let res =
+            simulate_withdraw(deps.as_ref(), mock_env(), coin(200_000, "pool1"), None).unwrap();
+        assert_eq!(
+            res.amount_out,
+            vec![coin(100_000, "aside"), coin(100_000, "bside")]
+        );
+        assert_eq!(res.price_impact_bps, 0);
+    }
+}
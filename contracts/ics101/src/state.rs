@@ -1,13 +1,19 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::IbcEndpoint;
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Binary, Coin, Decimal256, Empty, IbcEndpoint, Uint128};
+use cw_storage_plus::{Deque, Index, IndexList, IndexedMap, Item, Map, MultiIndex};
 
-use crate::{market::InterchainLiquidityPool, types::MultiAssetDepositOrder};
+use crate::{
+    market::InterchainLiquidityPool,
+    types::{Forward, InterchainMessageType, MultiAssetDepositOrder},
+};
 
 pub const CHANNEL_INFO: Map<&str, ChannelInfo> = Map::new("channel_info");
 
+// Map from counterparty chain id to the channel its pools are served over
+pub const CHAIN_CHANNELS: Map<&str, ChannelInfo> = Map::new("chain_channels");
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct ChannelInfo {
     /// id of this channel
@@ -18,6 +24,15 @@ pub struct ChannelInfo {
     pub connection_id: String,
 }
 
+/// Spot prices of every pool asset, quoted in the pool's first asset, and the
+/// time integrals of those prices since the first observation.
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PriceObservation {
+    pub timestamp: u64,
+    pub prices: Vec<Decimal256>,
+    pub cumulative_prices: Vec<Decimal256>,
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct Config {
     // Counter to keep track of multiassetdeposit orders
@@ -28,6 +43,21 @@ pub struct Config {
     pub admin: String,
     // Router address
     pub router: String,
+    // Share of each swap fee kept by the protocol, in FEE_PRECISION units
+    #[serde(default)]
+    pub protocol_fee_rate: u32,
+    // Address allowed to claim accrued protocol fees
+    #[serde(default)]
+    pub fee_collector: String,
+    // Address allowed to pause the contract and disable message types in an emergency
+    #[serde(default)]
+    pub guardian: String,
+    // While set, no new packets are sent. Acks and timeouts are still processed
+    #[serde(default)]
+    pub paused: bool,
+    // Message types that may not be sent from this chain
+    #[serde(default)]
+    pub disabled_messages: Vec<InterchainMessageType>,
 }
 
 // Each pool has it's pool token (cw20)
@@ -36,17 +66,245 @@ pub const POOL_TOKENS_LIST: Map<&str, String> = Map::new("pool_tokens_list");
 
 pub const CONFIG: Item<Config> = Item::new("config");
 
+// Admin proposed by the current admin, in charge once it accepts
+pub const PENDING_ADMIN: Item<String> = Item::new("pending_admin");
+
 pub const TEMP: Item<String> = Item::new("temp");
 
+/// Swap output being forwarded over ICS-20, paid out locally once the retries are spent.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PendingForward {
+    pub recipient: String,
+    pub token: Coin,
+    pub forward: Forward,
+    pub attempts_left: u8,
+}
+
+/// Forwards waiting for the reply that reports their packet sequence, in dispatch order.
+pub const FORWARDS_DISPATCHED: Deque<PendingForward> = Deque::new("forwards_dispatched");
+
+/// Forwards in flight over ICS-20, keyed by (channel id, packet sequence) until the transfer
+/// is acknowledged or times out.
+pub const PENDING_FORWARDS: Map<(&str, u64), PendingForward> = Map::new("pending_forwards");
+
+/// Swap output handed to the router, paid out to `recipient` here if the router fails.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RoutedOutput {
+    pub recipient: String,
+    pub token: Coin,
+}
+
+/// Router calls waiting for their reply, in the order they were dispatched.
+pub const ROUTED_OUTPUTS: Deque<RoutedOutput> = Deque::new("routed_outputs");
+
 pub const POOLS: Map<&str, InterchainLiquidityPool> = Map::new("pools");
 
-// Map from key (pool_id + "-" + order_id) to value multi asset orders
-pub const MULTI_ASSET_DEPOSIT_ORDERS: Map<String, MultiAssetDepositOrder> =
-    Map::new("multi_asset_deposit_orders");
+/// Why and when a pool was frozen.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct FrozenPool {
+    pub pool_id: String,
+    pub channel_id: String,
+    pub reason: String,
+    pub frozen_at: u64,
+}
+
+pub const FROZEN_POOLS: Map<&str, FrozenPool> = Map::new("frozen_pools");
+
+/// Resting order selling `token_in` on a pool once it returns at least `price`
+/// units of `denom_out` per unit offered. The offer is escrowed by this contract.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LimitOrder {
+    pub id: u64,
+    pub owner: String,
+    pub pool_id: String,
+    pub token_in: Coin,
+    pub denom_out: String,
+    pub price: Decimal256,
+    pub recipient: String,
+    pub created_at: u64,
+}
+
+pub struct LimitOrderIndexes<'a> {
+    /// Orders by (pool id, offer denom, price), iterated from the lowest price up
+    pub book: MultiIndex<'a, (String, String, Vec<u8>), LimitOrder, u64>,
+}
+
+impl<'a> IndexList<LimitOrder> for LimitOrderIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<LimitOrder>> + '_> {
+        let v: Vec<&dyn Index<LimitOrder>> = vec![&self.book];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn limit_orders<'a>() -> IndexedMap<'a, u64, LimitOrder, LimitOrderIndexes<'a>> {
+    let indexes = LimitOrderIndexes {
+        book: MultiIndex::new(
+            |_pk, order| {
+                (
+                    order.pool_id.clone(),
+                    order.token_in.denom.clone(),
+                    order.price.atomics().to_be_bytes().to_vec(),
+                )
+            },
+            "limit_orders",
+            "limit_orders__book",
+        ),
+    };
+    IndexedMap::new("limit_orders", indexes)
+}
+
+pub const LIMIT_ORDER_COUNT: Item<u64> = Item::new("limit_order_count");
+
+/// Large swap of `token_in` split into `slices` swaps sent at least `interval` seconds
+/// apart. `remaining` is the escrowed part of the offer not sent yet.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TwapOrder {
+    pub id: u64,
+    pub owner: String,
+    pub pool_id: String,
+    pub token_in: Coin,
+    pub denom_out: String,
+    pub slices: u32,
+    pub interval: u64,
+    pub slippage: u64,
+    pub recipient: String,
+    pub slices_sent: u32,
+    pub remaining: Coin,
+    pub next_execution: u64,
+    pub created_at: u64,
+}
+
+pub const TWAP_ORDERS: Map<u64, TwapOrder> = Map::new("twap_orders");
+
+pub const TWAP_ORDER_COUNT: Item<u64> = Item::new("twap_order_count");
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PacketStatus {
+    Pending,  // sent, waiting for an acknowledgement
+    TimedOut, // timed out, escrow held until it is retried or refunded
+}
+
+/// Outgoing packet that has not been settled yet, with the funds it holds in escrow.
+/// `data` is the whole packet as it was sent.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct InFlightPacket {
+    pub channel_id: String,
+    pub sequence: u64,
+    pub packet_type: InterchainMessageType,
+    pub sender: String,
+    pub pool_id: String,
+    pub escrow: Vec<Coin>,
+    pub data: Binary,
+    pub status: PacketStatus,
+    pub sent_at: u64,
+}
+
+pub struct InFlightPacketIndexes<'a> {
+    pub sender: MultiIndex<'a, String, InFlightPacket, (String, u64)>,
+    pub pool: MultiIndex<'a, String, InFlightPacket, (String, u64)>,
+}
+
+impl<'a> IndexList<InFlightPacket> for InFlightPacketIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<InFlightPacket>> + '_> {
+        let v: Vec<&dyn Index<InFlightPacket>> = vec![&self.sender, &self.pool];
+        Box::new(v.into_iter())
+    }
+}
+
+// Map from (channel_id, sequence) to the packet sent with that sequence
+pub fn in_flight_packets<'a>(
+) -> IndexedMap<'a, (String, u64), InFlightPacket, InFlightPacketIndexes<'a>> {
+    let indexes = InFlightPacketIndexes {
+        sender: MultiIndex::new(
+            |_pk, packet| packet.sender.clone(),
+            "in_flight_packets",
+            "in_flight_packets__sender",
+        ),
+        pool: MultiIndex::new(
+            |_pk, packet| packet.pool_id.clone(),
+            "in_flight_packets",
+            "in_flight_packets__pool",
+        ),
+    };
+    IndexedMap::new("in_flight_packets", indexes)
+}
+
+// Packets sent in this transaction, waiting for the reply carrying their sequence
+pub const OUTGOING_PACKETS: Deque<InFlightPacket> = Deque::new("outgoing_packets");
+
+pub struct DepositOrderIndexes<'a> {
+    pub maker: MultiIndex<'a, (String, u8), MultiAssetDepositOrder, String>,
+    pub taker: MultiIndex<'a, (String, u8), MultiAssetDepositOrder, String>,
+    pub pool: MultiIndex<'a, (String, u8), MultiAssetDepositOrder, String>,
+    pub status: MultiIndex<'a, u8, MultiAssetDepositOrder, String>,
+}
+
+impl<'a> IndexList<MultiAssetDepositOrder> for DepositOrderIndexes<'a> {
+    fn get_indexes(
+        &'_ self,
+    ) -> Box<dyn Iterator<Item = &'_ dyn Index<MultiAssetDepositOrder>> + '_> {
+        let v: Vec<&dyn Index<MultiAssetDepositOrder>> =
+            vec![&self.maker, &self.taker, &self.pool, &self.status];
+        Box::new(v.into_iter())
+    }
+}
+
+// Map from (pair key of a pool's denoms, pool_id) to nothing, to find the pools of a pair
+pub const POOLS_BY_PAIR: Map<(&str, &str), Empty> = Map::new("pools_by_pair");
+
+// Map from (channel id, pool_id) to nothing, to find the pools bound to a channel
+pub const POOLS_BY_CHANNEL: Map<(&str, &str), Empty> = Map::new("pools_by_channel");
+
+// Map from key (pool_id + "-" + order_id) to value multi asset orders. The maker, taker and
+// pool indexes are keyed together with the order status
+pub fn multi_asset_deposit_orders<'a>(
+) -> IndexedMap<'a, String, MultiAssetDepositOrder, DepositOrderIndexes<'a>> {
+    let indexes = DepositOrderIndexes {
+        maker: MultiIndex::new(
+            |_pk, order| (order.source_maker.clone(), order.status.clone() as u8),
+            "multi_asset_deposit_orders",
+            "multi_asset_deposit_orders__maker",
+        ),
+        taker: MultiIndex::new(
+            |_pk, order| (order.destination_taker.clone(), order.status.clone() as u8),
+            "multi_asset_deposit_orders",
+            "multi_asset_deposit_orders__taker",
+        ),
+        pool: MultiIndex::new(
+            |_pk, order| (order.pool_id.clone(), order.status.clone() as u8),
+            "multi_asset_deposit_orders",
+            "multi_asset_deposit_orders__pool",
+        ),
+        status: MultiIndex::new(
+            |_pk, order| order.status.clone() as u8,
+            "multi_asset_deposit_orders",
+            "multi_asset_deposit_orders__status",
+        ),
+    };
+    IndexedMap::new("multi_asset_deposit_orders", indexes)
+}
+
+// Map from (expires_at, pool_id + "-" + order_id) to multi asset orders made on this chain
+pub const ORDER_EXPIRIES: Map<(u64, String), Empty> = Map::new("order_expiries");
+
+// Map from (pool_id, pool_id + "-" + order_id) to the expiry of orders a sweep set aside
+// while their pool was neither active nor frozen
+pub const DEFERRED_EXPIRIES: Map<(&str, &str), u64> = Map::new("deferred_expiries");
+
+// Pools whose deferred orders go back on the expiry queue at the next sweeps
+pub const RESUMED_POOLS: Deque<String> = Deque::new("resumed_pools");
 
 // Map from key (source_makers + "-" + pool_id)
 pub const ACTIVE_ORDERS: Map<String, MultiAssetDepositOrder> = Map::new("active_order");
 
+// Map from (pool_id, denom) to protocol fees accrued on this chain
+pub const PROTOCOL_FEES: Map<(&str, &str), Uint128> = Map::new("protocol_fees");
+
+// Map from (pool_id, block time in seconds) to the price observation recorded then
+pub const PRICE_OBSERVATIONS: Map<(&str, u64), PriceObservation> =
+    Map::new("price_observations");
+
 // Map from pool_id to contract address
 pub const LOG_VOLUME: Map<String, String> = Map::new("log_volume");
 
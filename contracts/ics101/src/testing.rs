@@ -0,0 +1,48 @@
+use cosmwasm_std::coin;
+
+use crate::market::{InterchainLiquidityPool, PoolAsset, PoolCurve, PoolSide, PoolStatus};
+
+/// Active weighted pool `id` made by "maker" on chain "a" and taken by "taker" on chain "b"
+/// over "channel-0", with 2_000_000 pool tokens issued. Assets are given as
+/// (side, denom, amount, weight) with 6 decimals. Tests override the fields they depend on.
+pub(crate) fn mock_pool(
+    id: &str,
+    assets: &[(PoolSide, &str, u128, u32)],
+) -> InterchainLiquidityPool {
+    InterchainLiquidityPool {
+        assets: assets
+            .iter()
+            .map(|(side, denom, amount, weight)| PoolAsset {
+                side: side.clone(),
+                balance: coin(*amount, *denom),
+                weight: *weight,
+                decimal: 6,
+            })
+            .collect(),
+        counter_party_channel: "channel-0".to_string(),
+        counter_party_port: "port".to_string(),
+        destination_creator: "taker".to_string(),
+        destination_chain_id: "b".to_string(),
+        id: id.to_string(),
+        source_chain_id: "a".to_string(),
+        source_creator: "maker".to_string(),
+        status: PoolStatus::Active,
+        supply: coin(2_000_000, id),
+        swap_fee: 30,
+        pool_price: 0,
+        curve: PoolCurve::Weighted,
+        amp_ramp: None,
+        dynamic_fee: None,
+    }
+}
+
+/// Pool `id` holding `amount` of "aside" here and of "bside" on the counterparty chain.
+pub(crate) fn mock_pair_pool(id: &str, amount: u128) -> InterchainLiquidityPool {
+    mock_pool(
+        id,
+        &[
+            (PoolSide::SOURCE, "aside", amount, 50),
+            (PoolSide::DESTINATION, "bside", amount, 50),
+        ],
+    )
+}
@@ -1,7 +1,12 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Binary, Coin, Decimal, Uint128};
+use cosmwasm_std::{from_binary, Binary, Coin, Decimal, StdError, StdResult, Uint128};
+
+use crate::market::AmpRamp;
+
+/// The only port outgoing forwards can use, the one the ICS-20 transfer module is bound to.
+pub const TRANSFER_PORT: &str = "transfer";
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct StateChange {
@@ -19,23 +24,97 @@ pub struct StateChange {
     pub source_chain_id: Option<String>,
     #[serde(rename = "Shares")]
     pub shares: Option<Uint128>,
+    #[serde(rename = "AmpRamp", default)]
+    pub amp_ramp: Option<AmpRamp>,
+    #[serde(rename = "ProtocolFees", default)]
+    pub protocol_fees: Option<Vec<Coin>>,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Forward instruction in the shape of a packet-forward-middleware memo. Swap outputs
+/// carrying one are sent on over ICS-20 instead of being paid out locally.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Forward {
+    pub receiver: String,
     pub port: String,
     pub channel: String,
+    /// Duration such as "10m" or "1h30m", the default timeout offset applies when empty
+    #[serde(default)]
     pub timeout: String,
-    pub retries: i32,
+    #[serde(default)]
+    pub retries: u8,
     #[serde(skip_serializing_if = "Option::is_none")] // This line is to skip serialization if next is None
     pub next: Option<String>, // Optional because it seems to be a comment in your example
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Memo {
     pub forward: Forward,
 }
 
+impl Memo {
+    /// Parses and checks a packet memo, rejecting forwards this contract cannot honor.
+    /// Memos that are not forward instructions are left to other middleware and yield None.
+    pub fn from_memo(memo: &Binary) -> StdResult<Option<Memo>> {
+        let memo: Memo = match from_binary(memo) {
+            Ok(memo) => memo,
+            Err(_) => return Ok(None),
+        };
+        memo.forward.validate()?;
+        Ok(Some(memo))
+    }
+}
+
+impl Forward {
+    pub fn validate(&self) -> StdResult<()> {
+        if self.receiver.is_empty() {
+            return Err(StdError::generic_err("Forward receiver is empty"));
+        }
+        if self.port != TRANSFER_PORT {
+            return Err(StdError::generic_err(format!(
+                "Forward port must be {}",
+                TRANSFER_PORT
+            )));
+        }
+        if self.channel.is_empty() {
+            return Err(StdError::generic_err("Forward channel is empty"));
+        }
+        if self.next.is_some() {
+            return Err(StdError::generic_err("Nested forwards are not supported"));
+        }
+        self.timeout_seconds()?;
+        Ok(())
+    }
+
+    /// Timeout of the forwarded transfer in seconds, zero when unset.
+    pub fn timeout_seconds(&self) -> StdResult<u64> {
+        let invalid = || StdError::generic_err(format!("Invalid forward timeout: {}", self.timeout));
+        let mut seconds: u64 = 0;
+        let mut value = String::new();
+        for c in self.timeout.chars() {
+            if c.is_ascii_digit() {
+                value.push(c);
+                continue;
+            }
+            let unit = match c {
+                'h' => 3600,
+                'm' => 60,
+                's' => 1,
+                _ => return Err(invalid()),
+            };
+            let amount: u64 = value.parse().map_err(|_| invalid())?;
+            seconds = amount
+                .checked_mul(unit)
+                .and_then(|amount| seconds.checked_add(amount))
+                .ok_or_else(invalid)?;
+            value.clear();
+        }
+        if !value.is_empty() {
+            return Err(invalid());
+        }
+        Ok(seconds)
+    }
+}
+
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct InterchainSwapPacketData {
@@ -73,9 +152,21 @@ pub enum InterchainMessageType {
     LeftSwap = 9,
     #[serde(rename = "RIGHT_SWAP")]
     RightSwap = 10,
+    #[serde(rename = "RAMP_AMP")]
+    RampAmp = 11,
+    #[serde(rename = "STOP_RAMP_AMP")]
+    StopRampAmp = 12,
+    #[serde(rename = "SINGLE_ASSET_WITHDRAW")]
+    SingleAssetWithdraw = 13,
+    #[serde(rename = "SET_POOL_STATUS")]
+    SetPoolStatus = 14,
 }
 
 pub const MULTI_DEPOSIT_PENDING_LIMIT: u64 = 10;
+/// Seconds a multi asset deposit order stays open when the maker sets no expiry.
+pub const MULTI_DEPOSIT_DEFAULT_EXPIRY: u64 = 24 * 60 * 60;
+/// Longest a multi asset deposit order may stay open, in seconds.
+pub const MULTI_DEPOSIT_MAX_EXPIRY: u64 = 30 * 24 * 60 * 60;
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
@@ -96,6 +187,15 @@ pub struct MultiAssetDepositOrder {
     //pub pool_tokens: Vec<Coin>,
     pub status: OrderStatus,
     pub created_at: u64,
+    // Block time in seconds from which the order can no longer be taken, 0 for never
+    #[serde(default)]
+    pub expires_at: u64,
+}
+
+impl MultiAssetDepositOrder {
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at != 0 && now >= self.expires_at
+    }
 }
 
 /// ## Description - This struct describes a asset (native or CW20) and its normalized weight
@@ -107,3 +207,40 @@ pub struct WeightedAsset {
     /// The weight of the asset
     pub weight: Decimal,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::to_binary;
+
+    fn forward(timeout: &str) -> Forward {
+        Forward {
+            receiver: "osmo1receiver".to_string(),
+            port: TRANSFER_PORT.to_string(),
+            channel: "channel-7".to_string(),
+            timeout: timeout.to_string(),
+            retries: 2,
+            next: None,
+        }
+    }
+
+    #[test]
+    fn test_forward_memo_validation() {
+        assert_eq!(forward("").timeout_seconds().unwrap(), 0);
+        assert_eq!(forward("10m").timeout_seconds().unwrap(), 600);
+        assert_eq!(forward("1h30m15s").timeout_seconds().unwrap(), 5415);
+        assert!(forward("10").timeout_seconds().is_err());
+        assert!(forward("5d").timeout_seconds().is_err());
+
+        let memo = to_binary(&Memo { forward: forward("10m") }).unwrap();
+        assert_eq!(Memo::from_memo(&memo).unwrap().unwrap().forward, forward("10m"));
+
+        let mut nested = forward("10m");
+        nested.next = Some("{}".to_string());
+        let memo = to_binary(&Memo { forward: nested }).unwrap();
+        assert!(Memo::from_memo(&memo).is_err());
+        assert_eq!(Memo::from_memo(&Binary::from(b"not json".to_vec())).unwrap(), None);
+        let other = Binary::from(br#"{"wasm":{"contract":"osmo1contract"}}"#.to_vec());
+        assert_eq!(Memo::from_memo(&other).unwrap(), None);
+    }
+}
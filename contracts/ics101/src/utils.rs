@@ -8,30 +8,50 @@ use cw20::Cw20ExecuteMsg;
 use sha2::{Digest, Sha256};
 
 use crate::{
-    interchainswap_handler::InterchainSwapPacketAcknowledgement, market::PoolAsset,
-    msg::DepositAsset, ContractError,
+    interchainswap_handler::InterchainSwapPacketAcknowledgement,
+    market::{InterchainLiquidityPool, PoolAsset, PoolCurve, PoolSide},
+    msg::DepositAsset,
+    ContractError,
 };
 use hex;
 
 pub const MULTIPLIER: u128 = 1e18 as u128;
 pub const MAXIMUM_SLIPPAGE: u64 = 10000;
 pub const INSTANTIATE_TOKEN_REPLY_ID: u64 = 2000;
+pub const FORWARD_REPLY_ID: u64 = 2001;
+pub const PACKET_SENT_REPLY_ID: u64 = 2002;
+pub const ROUTER_REPLY_ID: u64 = 2003;
 
-pub fn get_pool_id_with_tokens(tokens: &[Coin], source: String, destination: String) -> String {
-    let mut denoms: Vec<String> = tokens.iter().map(|token| token.denom.clone()).collect();
-    denoms.sort();
-    let mut chan = vec![];
-    chan.push(source);
-    chan.push(destination);
-    let connection = get_connection_id(chan);
+/// Pool ids commit to the assets and their weights, the fee tier, the kind of curve and both
+/// chains, so a pair can have several pools that differ in any of them. The separators never
+/// occur in denoms, which keeps the hashed strings of different pools apart.
+pub fn get_pool_id(
+    assets: &[PoolAsset],
+    swap_fee: u32,
+    curve: &PoolCurve,
+    source: String,
+    destination: String,
+) -> String {
+    let mut weights: Vec<String> = assets
+        .iter()
+        .map(|asset| format!("{}={}", asset.balance.denom, asset.weight))
+        .collect();
+    weights.sort();
+    let curve = match curve {
+        PoolCurve::Weighted => "weighted",
+        PoolCurve::Stable { .. } => "stable",
+    };
+    let connection = get_connection_id(vec![source, destination]);
 
-    let mut res = denoms.join("");
-    res = res + &connection;
-    let res_bytes = res.as_bytes();
-    let hash = Sha256::digest(res_bytes);
+    let res = format!("{}|{}|{}|{}", weights.join(","), swap_fee, curve, connection);
+    let hash = Sha256::digest(res.as_bytes());
+    format!("pool{}", hex::encode(hash))
+}
 
-    let pool_id = format!("pool{}", hex::encode(hash));
-    pool_id
+/// Key of a set of denoms in the pair index, whatever order they are given in.
+pub fn get_pair_key(mut denoms: Vec<String>) -> String {
+    denoms.sort();
+    denoms.join(",")
 }
 
 pub fn get_connection_id(mut chain_ids: Vec<String>) -> String {
@@ -167,10 +187,76 @@ pub(crate) fn enforce_order_and_version(
 }
 
 pub fn get_coins_from_deposits(deposits: Vec<DepositAsset>) -> Vec<Coin> {
-    let mut tokens = vec![];
-    tokens.push(deposits[0].balance.clone());
-    tokens.push(deposits[1].balance.clone());
-    tokens
+    deposits.into_iter().map(|deposit| deposit.balance).collect()
+}
+
+/// Returns the maker and taker addresses of a multi asset deposit.
+/// Every pool asset has to be deposited exactly once, and all deposits on one side of the
+/// pool must come from the same sender. `maker_side` is the side of the maker's assets
+/// from the point of view of the local pool.
+pub fn get_depositors(
+    pool: &InterchainLiquidityPool,
+    deposits: &[DepositAsset],
+    maker_side: PoolSide,
+) -> Result<(String, String), ContractError> {
+    if deposits.len() != pool.assets.len() {
+        return Err(ContractError::InvalidAssetInput);
+    }
+
+    let mut maker: Option<String> = None;
+    let mut taker: Option<String> = None;
+    for asset in &pool.assets {
+        let mut matching = deposits
+            .iter()
+            .filter(|deposit| deposit.balance.denom == asset.balance.denom);
+        let deposit = matching.next().ok_or(ContractError::InvalidAssetInput)?;
+        if matching.next().is_some() || deposit.balance.amount.is_zero() {
+            return Err(ContractError::InvalidAssetInput);
+        }
+
+        let depositor = if asset.side == maker_side {
+            &mut maker
+        } else {
+            &mut taker
+        };
+        match depositor {
+            Some(sender) if *sender != deposit.sender => {
+                return Err(ContractError::InvalidSender);
+            }
+            _ => *depositor = Some(deposit.sender.clone()),
+        }
+    }
+
+    match (maker, taker) {
+        (Some(maker), Some(taker)) => Ok((maker, taker)),
+        _ => Err(ContractError::InvalidAssetInput),
+    }
+}
+
+/// Returns the tokens of `deposits` that belong to the given side of the pool.
+pub fn get_coins_by_side(
+    pool: &InterchainLiquidityPool,
+    deposits: &[Coin],
+    side: PoolSide,
+) -> Vec<Coin> {
+    deposits
+        .iter()
+        .filter(|token| {
+            pool.find_asset_by_denom(&token.denom)
+                .map(|asset| asset.side == side)
+                .unwrap_or(false)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Checks that the funds sent with the message are exactly the expected tokens, with no
+/// denom missing, no amount off and nothing extra that would be stuck in the contract.
+pub fn funds_match(funds: &[Coin], expected: &[Coin]) -> bool {
+    !expected.is_empty()
+        && funds.len() == expected.len()
+        && expected.iter().all(|token| funds.contains(token))
+        && funds.iter().all(|fund| expected.contains(fund))
 }
 
 pub(crate) fn send_tokens_coin(to: &Addr, amount: Coin) -> StdResult<Vec<SubMsg>> {
@@ -246,3 +332,25 @@ pub fn is_valid_symbol(symbol: &str, max_length: Option<usize>) -> bool {
     }
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use cosmwasm_std::coin;
+
+    use super::*;
+
+    #[test]
+    fn test_funds_match_exactly() {
+        let expected = vec![coin(100, "aside"), coin(200, "cside")];
+        let funds = [coin(200, "cside"), coin(100, "aside")];
+        assert!(funds_match(&funds, &expected));
+
+        // Missing, short or extra funds are all rejected
+        assert!(!funds_match(&[coin(100, "aside")], &expected));
+        let short = [coin(100, "aside"), coin(199, "cside")];
+        assert!(!funds_match(&short, &expected));
+        let extra = [coin(100, "aside"), coin(200, "cside"), coin(1, "bside")];
+        assert!(!funds_match(&extra, &expected));
+        assert!(!funds_match(&[], &[]));
+    }
+}